@@ -1,7 +1,9 @@
 use std::ffi::{c_void, CStr};
 use std::mem::size_of;
 
-use gl::types::{GLfloat, GLsizei, GLsizeiptr, GLuint};
+use gl::types::{GLfloat, GLint, GLsizei, GLsizeiptr, GLuint};
+
+use chip8::Damage;
 
 use crate::gl_scene::shaders::Shader;
 
@@ -20,8 +22,7 @@ mod shaders {
         if status == (i32::from(gl::FALSE)) {
             let mut len = 0;
             gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len(len as usize);
+            let mut buf = vec![0u8; len as usize];
             gl::GetShaderInfoLog(
                 shader,
                 len,
@@ -44,15 +45,14 @@ mod shaders {
         if status == (i32::from(gl::FALSE)) {
             let mut len = 0;
             gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-            let mut buf = Vec::with_capacity(len as usize);
-            buf.set_len(len as usize);
+            let mut buf = vec![0u8; len as usize];
             gl::GetProgramInfoLog(
                 program,
                 len,
                 std::ptr::null_mut(),
                 buf.as_mut_ptr() as *mut GLchar,
             );
-            panic!(String::from_utf8(buf));
+            panic!("{}", String::from_utf8(buf).unwrap());
         }
         program
     }
@@ -135,6 +135,8 @@ pub struct Scene {
     vbo: GLuint,
     ebo: GLuint,
     texture: GLuint,
+    /// Size of the texture, (0, 0) until the first upload
+    texture_dims: (u32, u32),
 }
 
 impl Scene {
@@ -195,26 +197,49 @@ impl Scene {
             vbo,
             ebo,
             texture,
+            texture_dims: (0, 0),
         }
     }
 
-    pub fn render(&self, framebuffer: &[u32], width: u32, height: u32) {
+    /// Draw the screen. Only the `damage` region of `framebuffer`, changed
+    /// since the last call, is uploaded to the texture, unless the
+    /// resolution changed.
+    pub fn render(&mut self, framebuffer: &[u32], width: u32, height: u32, damage: Option<Damage>) {
         assert_eq!(framebuffer.len(), (width * height) as usize);
         // Update texture data
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGB as i32,
-                width as GLsizei,
-                height as GLsizei,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                framebuffer.as_ptr() as *const c_void,
-            );
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+            if self.texture_dims != (width, height) {
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGB as i32,
+                    width as GLsizei,
+                    height as GLsizei,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    framebuffer.as_ptr() as *const c_void,
+                );
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                self.texture_dims = (width, height);
+            } else if let Some(d) = damage {
+                let start = (d.y * width + d.x) as usize;
+                gl::PixelStorei(gl::UNPACK_ROW_LENGTH, width as GLint);
+                gl::TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    d.x as GLint,
+                    d.y as GLint,
+                    d.width as GLsizei,
+                    d.height as GLsizei,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    framebuffer[start..].as_ptr() as *const c_void,
+                );
+                gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
             gl::ActiveTexture(gl::TEXTURE0);
             self.shader.use_program();
             let var_id = CStr::from_bytes_with_nul(b"screen\0").unwrap();
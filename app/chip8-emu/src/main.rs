@@ -1,16 +1,20 @@
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, Read, Write};
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use sdl2::audio::{AudioCallback, AudioSpecDesired, AudioSpec};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use structopt::StructOpt;
 
-use chip8::{Chip8Emulator, Insn};
+use chip8::{Chip8Emulator, Insn, Platform, Quirks, StopReason, TimingModel, CHIP8_PERIPH_HZ, STATE_SIZE};
+use chip8::movie::{Movie, MoviePlayer, MovieRecorder};
+use chip8::rewind::RewindConfig;
+use chip8::trace::{TraceFormat, TraceHook, TraceWriter};
+use chip8::disasm::{parse_symbols, Disassembly};
+use chip8::export::ImageFormat;
 use gl_scene::Scene;
 use std::sync::{Arc, RwLock};
 
@@ -20,53 +24,187 @@ impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        let enabled = *self.enabled.read().unwrap();
+        let state = *self.state.read().unwrap();
         for x in out.iter_mut() {
-            if !enabled {
+            if !state.enabled {
                 *x = 0.0;
                 continue;
             }
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
+            let high = match state.pattern {
+                // Generate a square wave
+                None => self.phase <= 0.5,
+                // XO-CHIP: loop over the 128 bits of the pattern
+                Some((pattern, _)) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    (pattern[bit / 8] >> (7 - bit % 8)) & 1 != 0
+                }
+            };
+            *x = if high { self.volume } else { -self.volume };
+            let phase_inc = match state.pattern {
+                None => 440.0 / self.freq,
+                Some((_, rate)) => rate / 128.0 / self.freq,
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            self.phase = (self.phase + phase_inc) % 1.0;
         }
     }
 }
 
+#[derive(Debug, Default, Copy, Clone)]
+struct BuzzerState {
+    enabled: bool,
+    /// XO-CHIP audio pattern and its playback rate (bits per second)
+    pattern: Option<([u8; 16], f32)>,
+}
 
 struct SquareWave {
-    enabled: Arc<RwLock<bool>>,
-    phase_inc: f32,
+    state: Arc<RwLock<BuzzerState>>,
+    freq: f32,
     phase: f32,
     volume: f32,
 }
 
 impl SquareWave {
-    pub fn new(spec: &AudioSpec, enabled: Arc<RwLock<bool>>) -> Self {
+    pub fn new(spec: &AudioSpec, state: Arc<RwLock<BuzzerState>>) -> Self {
         SquareWave {
-            enabled,
-            phase_inc: 440.0 / spec.freq as f32,
+            state,
+            freq: spec.freq as f32,
             phase: 0.0,
             volume: 0.25,
         }
     }
 }
 
+const DEFAULT_CPU_HZ: u32 = 500;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "chip8emu", about = "Rust-powered Chip 8 Emulator (SDL2-OpenGL GUI)")]
 pub struct CliOpts {
-    #[structopt(short = "h", long = "cpuhz", default_value = "500")]
-    emu_hz: u32,
+    /// CPU frequency (default: recommended value for known ROMs, or 500)
+    #[structopt(short = "h", long = "cpuhz")]
+    emu_hz: Option<u32>,
+
+    /// Instruction set to emulate (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform")]
+    platform: Option<Platform>,
+
+    /// Override the platform quirks (default, vip, chip48, schip, xochip)
+    #[structopt(short = "q", long = "quirks")]
+    quirks: Option<Quirks>,
+
+    /// Instruction timing: flat (`cpuhz` instructions per second) or vip
+    /// (cycle costs and display wait of the COSMAC VIP interpreter)
+    #[structopt(short = "t", long = "timing", default_value = "flat")]
+    timing: TimingModel,
+
+    /// Symbol map naming addresses in the disassembly (`0xADDR name` lines,
+    /// as written by chip8-octo)
+    #[structopt(short = "s", long = "symbols")]
+    symbols: Option<String>,
+
+    /// Record the keypad input into a movie file, written on exit
+    #[structopt(short = "r", long = "record", conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Replay a movie file recorded with --record, the platform, quirks, CPU
+    /// frequency and timing are taken from the movie
+    #[structopt(long = "replay")]
+    replay: Option<String>,
+
+    /// Break when the CPU reaches the given address (hexadecimal), may be
+    /// repeated
+    #[structopt(short = "b", long = "break", parse(try_from_str = parse_addr))]
+    breakpoints: Vec<u16>,
+
+    /// Log the executed instructions to a file
+    #[structopt(long = "trace")]
+    trace: Option<String>,
+
+    /// Format of the trace (text, compat, binary)
+    #[structopt(long = "trace-format", default_value = "text")]
+    trace_format: TraceFormat,
 
     #[structopt(name = "FILE.ch8")]
     rom_path: String,
 }
 
-fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error>> {
+fn parse_addr(s: &str) -> Result<u16, std::num::ParseIntError> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16)
+}
+
+fn add_breakpoints(emu: &mut Chip8Emulator, breakpoints: &[u16]) {
+    for addr in breakpoints.iter() {
+        if !emu.debugger_mut().add_breakpoint(*addr) {
+            eprintln!("warning: too many breakpoints, {:04X} ignored", addr);
+        }
+    }
+}
+
+/// Show where and why the debugger stopped
+fn print_stop(emu: &Chip8Emulator, reason: StopReason) {
+    let pc = emu.cpu().pc() as usize;
+    let mem = &emu.peripherals().memory;
+    let insn = mem.get(pc..pc + 2).and_then(|w| Insn::decode(u16::from_be_bytes([w[0], w[1]])));
+    match insn {
+        Some(insn) => println!("break: {:?}, next: {:04X}  {}", reason, pc, insn),
+        None => println!("break: {:?}, next: {:04X}", reason, pc),
+    }
+}
+
+/// Write the emulator state to a file
+fn quicksave(emu: &Chip8Emulator, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = vec![0u8; STATE_SIZE];
+    let n = emu.save_state(&mut state)?;
+    std::fs::write(path, &state[..n])?;
+    Ok(())
+}
+
+/// Restore the emulator state from a file
+fn quickload(emu: &mut Chip8Emulator, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let state = std::fs::read(path)?;
+    emu.load_state(&state)?;
+    Ok(())
+}
+
+/// Save the screen as a PNG next to the ROM, returns the path
+fn screenshot(emu: &Chip8Emulator, rom_path: &str, scale: u32) -> Result<String, Box<dyn std::error::Error>> {
+    let format = ImageFormat::Png;
+    let path = (1..)
+        .map(|n| format!("{}.{}.{}", rom_path, n, format.extension()))
+        .find(|path| !std::path::Path::new(path).exists())
+        .unwrap();
+    let mut out = BufWriter::new(File::create(&path)?);
+    emu.peripherals().screen.write_image(&mut out, format, scale)?;
+    out.flush()?;
+    Ok(path)
+}
+
+/// Duration of a 60Hz frame
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / CHIP8_PERIPH_HZ as u64);
+
+/// Frames emulated at most per iteration of the main loop, the emulation
+/// slows down instead of catching up after a stall
+const MAX_FRAMES_PER_LOOP: u32 = 4;
+
+/// Source of the keypad input
+enum Input {
+    Keyboard,
+    /// Keyboard, recorded into a movie
+    Record(MovieRecorder),
+    Replay(MoviePlayer),
+}
+
+/// Run the emulator, F5 and F9 save and restore its state from `ROM.state`,
+/// holding Backspace plays the game backwards, F12 saves a screenshot. The
+/// emulation pauses when the debugger stops, F6 pauses or resumes it, F7, F8
+/// and F11 step into, over and out. Executed instructions are reported to
+/// `trace`. Returns the recorded movie, if any.
+fn run_emulator(
+    emu: &mut Chip8Emulator,
+    rom_path: &str,
+    mut input: Input,
+    mut trace: Option<&mut dyn TraceHook>,
+) -> Result<Option<Movie>, Box<dyn std::error::Error>> {
+    let state_path = &format!("{}.state", rom_path);
     let keymap: HashMap<Keycode, u8> = [
         (Keycode::Kp1, 1),
         (Keycode::Kp2, 2),
@@ -88,8 +226,6 @@ fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error
     const WIN_W: u32 = 512;
     const WIN_H: u32 = 256;
 
-    let (emu_w, emu_h) = emu.peripherals().screen.dims();
-
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
@@ -106,7 +242,7 @@ fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error
     //        .unwrap();
     gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const _);
 
-    let buzzer_active = Arc::new(RwLock::new(false));
+    let buzzer_state = Arc::new(RwLock::new(BuzzerState::default()));
     let audio_subsystem = sdl_context.audio().unwrap();
     let desired_spec = AudioSpecDesired {
         freq: Some(44100),
@@ -114,14 +250,20 @@ fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error
         samples: None,       // default sample size
     };
     let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-        SquareWave::new(&spec, buzzer_active.clone())
+        SquareWave::new(&spec, buzzer_state.clone())
     }).unwrap();
     device.resume();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let scene = Scene::new();
+    let mut scene = Scene::new();
 
     let mut timer = SystemTime::now();
+    // Time not emulated yet, less than a frame once caught up
+    let mut lag = Duration::ZERO;
+    let mut sound_active = false;
+    let mut rewinding = false;
+    let mut movie_ended = false;
+    let mut paused = false;
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -130,15 +272,74 @@ fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error
                     if k == Keycode::Escape {
                         break 'running;
                     }
+                    if k == Keycode::F5 {
+                        match quicksave(emu, state_path) {
+                            Ok(()) => println!("info: state saved to {}", state_path),
+                            Err(e) => eprintln!("error: cannot save state: {}", e),
+                        }
+                        continue;
+                    }
+                    if k == Keycode::F12 {
+                        // Same size as the window
+                        let scale = WIN_W / emu.peripherals().screen.width();
+                        match screenshot(emu, rom_path, scale) {
+                            Ok(path) => println!("info: screenshot saved to {}", path),
+                            Err(e) => eprintln!("error: cannot save the screenshot: {}", e),
+                        }
+                        continue;
+                    }
+                    if (k == Keycode::Backspace || k == Keycode::F9) && !matches!(input, Input::Keyboard) {
+                        eprintln!("note: loading states and rewinding are disabled with movies");
+                        continue;
+                    }
+                    if k == Keycode::Backspace {
+                        rewinding = true;
+                        continue;
+                    }
+                    if k == Keycode::F6 {
+                        paused = !paused;
+                        if paused {
+                            print_stop(emu, StopReason::Completed);
+                        }
+                        continue;
+                    }
+                    if k == Keycode::F7 || k == Keycode::F8 || k == Keycode::F11 {
+                        match k {
+                            Keycode::F7 => emu.step_into(),
+                            Keycode::F8 => emu.step_over(),
+                            _ => emu.step_out(),
+                        }
+                        paused = false;
+                        continue;
+                    }
+                    if k == Keycode::F9 {
+                        match quickload(emu, state_path) {
+                            Ok(()) => println!("info: state loaded from {}", state_path),
+                            Err(e) => eprintln!("error: cannot load state: {}", e),
+                        }
+                        continue;
+                    }
                     if let Some(k_num) = keymap.get(&k).cloned() {
-                        emu.peripherals_mut().keypad.key_pressed(k_num);
+                        match &mut input {
+                            Input::Keyboard => emu.peripherals_mut().keypad.key_pressed(k_num),
+                            Input::Record(recorder) => recorder.key_pressed(emu, k_num),
+                            Input::Replay(_) => {}
+                        }
                     } else {
                         eprintln!("note: unmapped keycode (down): {:?}", k);
                     }
                 }
                 Event::KeyUp { keycode: Some(k), .. } => {
+                    if k == Keycode::Backspace {
+                        rewinding = false;
+                        continue;
+                    }
                     if let Some(k_num) = keymap.get(&k).cloned() {
-                        emu.peripherals_mut().keypad.key_released(k_num);
+                        match &mut input {
+                            Input::Keyboard => emu.peripherals_mut().keypad.key_released(k_num),
+                            Input::Record(recorder) => recorder.key_released(emu, k_num),
+                            Input::Replay(_) => {}
+                        }
                     } else {
                         eprintln!("note: unmapped keycode (up): {:?}", k);
                     }
@@ -147,28 +348,105 @@ fn run_emulator(emu: &mut Chip8Emulator) -> Result<(), Box<dyn std::error::Error
             }
         }
 
-        let ms = timer.elapsed().unwrap().as_millis();
+        let elapsed = timer.elapsed().unwrap();
         timer = SystemTime::now();
-        match emu.advance_ms(ms as u32) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("error: CPU crashed during emulation: {:?}", e);
+        if paused {
+            // Keep the display, nothing to emulate
+            lag = Duration::ZERO;
+            sound_active = false;
+        } else if rewinding {
+            let frames = (elapsed.as_millis() as u32 * CHIP8_PERIPH_HZ / 1000).max(1);
+            emu.rewind(frames);
+            sound_active = false;
+        } else {
+            lag += elapsed;
+            let mut frames = 0;
+            while lag >= FRAME_TIME && !paused {
+                lag -= FRAME_TIME;
+                frames += 1;
+                if frames > MAX_FRAMES_PER_LOOP {
+                    lag = Duration::ZERO;
+                    break;
+                }
+                let r = match &mut input {
+                    Input::Replay(player) => {
+                        if !movie_ended && player.finished(emu) {
+                            println!("info: end of the movie");
+                            movie_ended = true;
+                        }
+                        match trace {
+                            Some(ref mut hook) => player.run_frame_traced(emu, &mut **hook),
+                            None => player.run_frame(emu),
+                        }
+                    }
+                    _ => match trace {
+                        Some(ref mut hook) => emu.run_frame_traced(&mut **hook),
+                        None => emu.run_frame(),
+                    },
+                };
+                match r {
+                    Ok(frame) => {
+                        sound_active = frame.sound_active;
+                        if frame.stop != StopReason::Completed {
+                            paused = true;
+                            print_stop(emu, frame.stop);
+                        }
+                    }
+                    Err(e) => {
+                        // Running again would only fail the same way
+                        eprintln!("error: CPU crashed: {}, emulation paused", e);
+                        paused = true;
+                    }
+                }
             }
         }
         // Note: the buzzer is updated at 60Hz at best...
         {
-            let mut buzzer_ptr = buzzer_active.write().unwrap();
-            *buzzer_ptr = emu.peripherals().sound_timer > 0;
+            let mut buzzer_ptr = buzzer_state.write().unwrap();
+            let periph = emu.peripherals();
+            buzzer_ptr.enabled = sound_active;
+            buzzer_ptr.pattern = if emu.platform() == Platform::XoChip {
+                let rate = 4000.0 * 2f32.powf((periph.pitch as f32 - 64.0) / 48.0);
+                Some((periph.audio_pattern, rate))
+            } else {
+                None
+            };
         }
 
+        // Note: SUPER-CHIP programs may switch resolution at any time
+        let (emu_w, emu_h) = emu.peripherals().screen.dims();
+        let damage = emu.peripherals_mut().screen.take_damage();
         unsafe {
             gl::ClearColor(0., 0., 0., 1.);
             gl::Clear(gl::COLOR_BUFFER_BIT);
-            scene.render(emu.framebuffer(), emu_w, emu_h);
+            scene.render(emu.framebuffer(), emu_w, emu_h, damage);
             gl::BindVertexArray(0);
         }
         window.gl_swap_window();
     }
+    match input {
+        Input::Record(recorder) => Ok(Some(recorder.finish(emu))),
+        _ => Ok(None),
+    }
+}
+
+type TraceFile = TraceWriter<BufWriter<File>>;
+
+/// Create the trace file requested on the command line, if any
+fn open_trace(opts: &CliOpts) -> std::io::Result<Option<TraceFile>> {
+    match &opts.trace {
+        Some(path) => Ok(Some(TraceWriter::new(BufWriter::new(File::create(path)?), opts.trace_format))),
+        None => Ok(None),
+    }
+}
+
+/// Flush the trace file, reporting the first write error
+fn close_trace(opts: &CliOpts, trace: Option<TraceFile>) -> Result<(), String> {
+    if let Some(trace) = trace {
+        let path = opts.trace.as_ref().unwrap();
+        trace.finish().map_err(|e| format!("{}: {}", path, e))?;
+        println!("info: trace written to {}", path);
+    }
     Ok(())
 }
 
@@ -182,21 +460,67 @@ pub fn run_app(opts: &CliOpts) -> Result<i32, Box<dyn std::error::Error>> {
     let mut buffer = Vec::new();
     // read the whole file
     f.read_to_end(&mut buffer)?;
-    println!("{}", buffer.len());
-    for (i, bs) in buffer.chunks_exact(2).enumerate() {
-        let insn = u16::from_be_bytes(bs.try_into().unwrap());
-        print!("{:4x}: {:04x} ", 2 * i, insn);
-        if let Some(w) = Insn::decode(insn) {
-            println!("{:?}", w);
-        } else {
-            println!("<INVALID>")
+    if let Some(path) = &opts.replay {
+        let movie = Movie::from_bytes(&std::fs::read(path)?)?;
+        let mut emulator = movie.make_emulator(&buffer)?;
+        add_breakpoints(&mut emulator, &opts.breakpoints);
+        println!("info: replaying {} ({} key events)", path, movie.events.len());
+        let mut trace = open_trace(opts)?;
+        let hook = trace.as_mut().map(|t| t as &mut dyn TraceHook);
+        run_emulator(&mut emulator, &opts.rom_path, Input::Replay(MoviePlayer::new(movie)), hook)?;
+        close_trace(opts, trace)?;
+        return Ok(0);
+    }
+    let mut emulator = Chip8Emulator::new(DEFAULT_CPU_HZ);
+    // Command line options take precedence over the ROM database
+    if let Some(info) = emulator.load_rom_auto(&buffer) {
+        println!("info: found '{}' in the ROM database ({:?}, {}Hz)", info.title, info.platform, info.cpu_hz);
+        for hint in info.keys {
+            println!("info: key {:X}: {}", hint.key, hint.action);
         }
     }
-    let mut emulator = Chip8Emulator::new(opts.emu_hz);
-    emulator.load_rom(&buffer);
+    if let Some(platform) = opts.platform {
+        emulator.set_platform(platform);
+    }
+    if let Some(quirks) = opts.quirks {
+        emulator.set_quirks(quirks);
+    }
+    if let Some(hz) = opts.emu_hz {
+        emulator.set_cpu_hz(hz);
+    }
+    emulator.set_timing(opts.timing);
+    let symbol_map = match &opts.symbols {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => String::new(),
+    };
+    let symbols = parse_symbols(&symbol_map)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|line| format!("{}:{}: invalid symbol", opts.symbols.as_ref().unwrap(), line))?;
+    let mut listing = String::new();
+    let mut disasm = Disassembly::analyze(&buffer, 0x200, emulator.platform());
+    disasm.set_symbols(&symbols);
+    disasm.write_listing(&mut listing)?;
+    println!("{}", listing);
+    add_breakpoints(&mut emulator, &opts.breakpoints);
     // TODO: read from command line
-    emulator.set_cpu_rng_seed(0x1234_56789);
-    run_emulator(&mut emulator)?;
+    let seed = 0x1234_56789;
+    let input = match &opts.record {
+        Some(_) => Input::Record(MovieRecorder::new(&mut emulator, &buffer, seed)),
+        None => {
+            emulator.set_cpu_rng_seed(seed);
+            emulator.enable_rewind(RewindConfig::default());
+            Input::Keyboard
+        }
+    };
+    let mut trace = open_trace(opts)?;
+    let hook = trace.as_mut().map(|t| t as &mut dyn TraceHook);
+    let movie = run_emulator(&mut emulator, &opts.rom_path, input, hook)?;
+    close_trace(opts, trace)?;
+    if let Some(movie) = movie {
+        let path = opts.record.as_ref().unwrap();
+        std::fs::write(path, movie.to_bytes())?;
+        println!("info: movie written to {} ({} key events)", path, movie.events.len());
+    }
     Ok(0)
 }
 
@@ -0,0 +1,501 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use structopt::StructOpt;
+
+use chip8::cpu::CpuStatus;
+use chip8::export::ImageFormat;
+use chip8::trace::{TraceFormat, TraceHook, TraceWriter};
+use chip8::{Chip8Emulator, Platform, Quirks, CHIP8_PERIPH_HZ};
+
+const DEFAULT_CPU_HZ: u32 = 500;
+
+/// The run ended after the requested cycles or frames, or the ROM exited
+const EXIT_OK: i32 = 0;
+/// Invalid arguments or input files, I/O errors
+const EXIT_ERROR: i32 = 1;
+/// The CPU failed (invalid instruction, stack overflow...)
+const EXIT_CPU_ERROR: i32 = 2;
+/// The wall-clock timeout expired, or the ROM did not exit in time with
+/// `--until-exit`
+const EXIT_TIMEOUT: i32 = 3;
+
+/// Cycles executed between two checks of the timeout
+const CHUNK_CYCLES: u64 = 10_000;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "chip8headless",
+    about = "Run a Chip 8 ROM without display, for scripts and automated tests",
+    after_help = "Exit codes: 0 on a clean finish, 1 on errors in the arguments or files, 2 if \
+                  the CPU failed, 3 on a timeout.\n\nThe key script has one event per line: the \
+                  cycle, the key (hexadecimal) and `down` or `up`, e.g. `1200 5 down`. Comments \
+                  start with #."
+)]
+pub struct CliOpts {
+    /// CPU frequency (default: recommended value for known ROMs, or 500)
+    #[structopt(short = "h", long = "cpuhz")]
+    emu_hz: Option<u32>,
+
+    /// Instruction set to emulate (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform")]
+    platform: Option<Platform>,
+
+    /// Override the platform quirks (default, vip, chip48, schip, xochip)
+    #[structopt(short = "q", long = "quirks")]
+    quirks: Option<Quirks>,
+
+    /// Seed of the random number generator
+    #[structopt(long = "seed", default_value = "4886718345")]
+    seed: u64,
+
+    /// Number of CPU cycles to run
+    #[structopt(short = "n", long = "cycles", required_unless = "frames", conflicts_with = "frames")]
+    cycles: Option<u64>,
+
+    /// Number of frames (1/60 s) to run
+    #[structopt(short = "f", long = "frames")]
+    frames: Option<u64>,
+
+    /// Run until the ROM exits (00FD), the number of cycles or frames being
+    /// a timeout
+    #[structopt(long = "until-exit")]
+    until_exit: bool,
+
+    /// Give up after this many seconds of wall-clock time
+    #[structopt(long = "timeout")]
+    timeout: Option<u64>,
+
+    /// Script of key events
+    #[structopt(short = "k", long = "keys")]
+    keys: Option<String>,
+
+    /// Write the final screen as text (`-` for stdout)
+    #[structopt(long = "screen")]
+    screen: Option<String>,
+
+    /// Write the final memory, raw (`-` for stdout)
+    #[structopt(long = "memory")]
+    memory: Option<String>,
+
+    /// Write the final CPU registers and timers as text (`-` for stdout)
+    #[structopt(long = "registers")]
+    registers: Option<String>,
+
+    /// Take a screenshot when reaching the given frame, may be repeated
+    #[structopt(long = "screenshot-at")]
+    screenshot_at: Vec<u64>,
+
+    /// Format of the screenshots (pbm, ppm, png, text)
+    #[structopt(long = "screenshot-format", default_value = "png")]
+    screenshot_format: ImageFormat,
+
+    /// Scale factor of the screenshots
+    #[structopt(long = "screenshot-scale", default_value = "4")]
+    screenshot_scale: u32,
+
+    /// Screenshots are written to PREFIX-FRAME.EXT
+    #[structopt(long = "screenshot-prefix", default_value = "screenshot")]
+    screenshot_prefix: String,
+
+    /// Log the executed instructions to a file
+    #[structopt(long = "trace")]
+    trace: Option<String>,
+
+    /// Format of the trace (text, compat, binary)
+    #[structopt(long = "trace-format", default_value = "text")]
+    trace_format: TraceFormat,
+
+    #[structopt(name = "FILE.ch8")]
+    rom_path: String,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct KeyEvent {
+    cycle: u64,
+    key: u8,
+    pressed: bool,
+}
+
+/// Parse a key script, sorted by cycle. Errors give the line number.
+fn parse_keys(text: &str) -> Result<Vec<KeyEvent>, usize> {
+    let mut events = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let event = match fields[..] {
+            [cycle, key, action] => {
+                let cycle = cycle.parse().ok();
+                let key = u8::from_str_radix(key, 16).ok().filter(|k| *k < 16);
+                let pressed = match action {
+                    "down" => Some(true),
+                    "up" => Some(false),
+                    _ => None,
+                };
+                match (cycle, key, pressed) {
+                    (Some(cycle), Some(key), Some(pressed)) => Some(KeyEvent { cycle, key, pressed }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        events.push(event.ok_or(n + 1)?);
+    }
+    events.sort_by_key(|e| e.cycle);
+    Ok(events)
+}
+
+/// Why the run ended
+enum Finish {
+    Done,
+    Exited,
+    CpuError(chip8::cpu::CpuError),
+    Timeout,
+    /// A screenshot could not be written
+    Error(io::Error),
+}
+
+/// Inputs and outputs of a run, at given cycles
+struct Schedule<'a> {
+    keys: &'a [KeyEvent],
+    /// Cycles at which `screenshot` is invoked, sorted
+    screenshots: &'a [u64],
+    screenshot: &'a mut dyn FnMut(&Chip8Emulator) -> io::Result<()>,
+}
+
+fn run(
+    emu: &mut Chip8Emulator,
+    cycles: u64,
+    schedule: Schedule,
+    timeout: Option<Duration>,
+    until_exit: bool,
+    mut trace: Option<&mut dyn TraceHook>,
+) -> Finish {
+    let start = Instant::now();
+    let end = emu.cycles() + cycles;
+    let keys = schedule.keys;
+    let mut next_key = 0;
+    let mut next_screenshot = 0;
+    loop {
+        while schedule.screenshots.get(next_screenshot).is_some_and(|c| *c <= emu.cycles()) {
+            if let Err(e) = (schedule.screenshot)(emu) {
+                return Finish::Error(e);
+            }
+            next_screenshot += 1;
+        }
+        while let Some(ev) = keys.get(next_key).filter(|ev| ev.cycle <= emu.cycles()) {
+            let keypad = &mut emu.peripherals_mut().keypad;
+            if ev.pressed {
+                keypad.key_pressed(ev.key);
+            } else {
+                keypad.key_released(ev.key);
+            }
+            next_key += 1;
+        }
+        if *emu.cpu().status() == CpuStatus::Halted {
+            return Finish::Exited;
+        }
+        if emu.cycles() >= end {
+            return if until_exit { Finish::Timeout } else { Finish::Done };
+        }
+        if timeout.is_some_and(|t| start.elapsed() >= t) {
+            return Finish::Timeout;
+        }
+        let mut n = (end - emu.cycles()).min(CHUNK_CYCLES);
+        if let Some(ev) = keys.get(next_key) {
+            n = n.min(ev.cycle - emu.cycles());
+        }
+        if let Some(cycle) = schedule.screenshots.get(next_screenshot) {
+            n = n.min(cycle - emu.cycles());
+        }
+        let r = match trace {
+            Some(ref mut hook) => emu.tick_traced(n as usize, &mut **hook),
+            None => emu.tick(n as usize),
+        };
+        if let Err(e) = r {
+            return Finish::CpuError(e);
+        }
+    }
+}
+
+fn screen_text(emu: &Chip8Emulator) -> String {
+    // Characters of the plane combinations (none, 1, 2, both)
+    const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+    let screen = &emu.peripherals().screen;
+    let mut out = String::new();
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            out.push(PIXELS[screen.pixel(x, y) as usize & 3]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn registers_text(emu: &Chip8Emulator) -> String {
+    let s = emu.cpu().snapshot();
+    let periph = emu.peripherals();
+    let mut out = String::new();
+    out += &format!("status: {:?}\n", s.status);
+    out += &format!("cycles: {}\n", s.cycles);
+    out += &format!("pc: {:04X}\n", s.pc);
+    out += &format!("i: {:04X}\n", s.i);
+    out += &format!("v:{}\n", s.v.iter().map(|v| format!(" {:02X}", v)).collect::<String>());
+    out += &format!("sp: {}\n", s.sp);
+    let stack = &s.stack[..s.sp as usize];
+    out += &format!("stack:{}\n", stack.iter().map(|a| format!(" {:04X}", a)).collect::<String>());
+    out += &format!("dt: {}\n", periph.delay_timer);
+    out += &format!("st: {}\n", periph.sound_timer);
+    out
+}
+
+/// Write to a file, or to stdout for `-`
+fn write_output(path: &str, data: &[u8]) -> io::Result<()> {
+    if path == "-" {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(data)?;
+        out.flush()
+    } else {
+        std::fs::write(path, data)
+    }
+}
+
+fn run_app(opts: &CliOpts) -> Result<i32, Box<dyn std::error::Error>> {
+    let rom = std::fs::read(&opts.rom_path).map_err(|e| format!("{}: {}", opts.rom_path, e))?;
+    let keys = match &opts.keys {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            parse_keys(&text).map_err(|line| format!("{}:{}: invalid key event", path, line))?
+        }
+        None => Vec::new(),
+    };
+    let mut emu = Chip8Emulator::new(DEFAULT_CPU_HZ);
+    // Command line options take precedence over the ROM database
+    emu.load_rom_auto(&rom);
+    if let Some(platform) = opts.platform {
+        emu.set_platform(platform);
+    }
+    if let Some(quirks) = opts.quirks {
+        emu.set_quirks(quirks);
+    }
+    if let Some(hz) = opts.emu_hz {
+        emu.set_cpu_hz(hz);
+    }
+    emu.set_cpu_rng_seed(opts.seed);
+    let cpu_hz = emu.cpu_hz() as u64;
+    let frame_cycles = |frame: u64| frame * cpu_hz / CHIP8_PERIPH_HZ as u64;
+    let cycles = match (opts.cycles, opts.frames) {
+        (Some(cycles), _) => cycles,
+        (None, Some(frames)) => frame_cycles(frames),
+        (None, None) => unreachable!(),
+    };
+    let mut frames = opts.screenshot_at.clone();
+    frames.sort_unstable();
+    frames.dedup();
+    let screenshots: Vec<u64> = frames.iter().map(|f| frame_cycles(*f)).collect();
+    let mut taken = 0;
+    let mut screenshot = |emu: &Chip8Emulator| -> io::Result<()> {
+        let format = opts.screenshot_format;
+        let path = format!("{}-{:06}.{}", opts.screenshot_prefix, frames[taken], format.extension());
+        let write = || -> io::Result<()> {
+            let mut out = BufWriter::new(File::create(&path)?);
+            emu.peripherals().screen.write_image(&mut out, format, opts.screenshot_scale)?;
+            out.flush()
+        };
+        write().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        eprintln!("info: screenshot written to {}", path);
+        taken += 1;
+        Ok(())
+    };
+    let schedule = Schedule {
+        keys: &keys,
+        screenshots: &screenshots,
+        screenshot: &mut screenshot,
+    };
+
+    let mut trace = match &opts.trace {
+        Some(path) => {
+            let f = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            Some(TraceWriter::new(BufWriter::new(f), opts.trace_format))
+        }
+        None => None,
+    };
+    let hook = trace.as_mut().map(|t| t as &mut dyn TraceHook);
+    let timeout = opts.timeout.map(Duration::from_secs);
+    let finish = run(&mut emu, cycles, schedule, timeout, opts.until_exit, hook);
+    if let Some(trace) = trace {
+        let path = opts.trace.as_ref().unwrap();
+        trace.finish().map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    // The final state is written in any case, to investigate failures
+    if let Some(path) = &opts.screen {
+        write_output(path, screen_text(&emu).as_bytes())?;
+    }
+    if let Some(path) = &opts.memory {
+        write_output(path, &emu.peripherals().memory)?;
+    }
+    if let Some(path) = &opts.registers {
+        write_output(path, registers_text(&emu).as_bytes())?;
+    }
+    let code = match finish {
+        Finish::Done => {
+            eprintln!("info: finished after {} cycles", emu.cycles());
+            EXIT_OK
+        }
+        Finish::Exited => {
+            eprintln!("info: the ROM exited after {} cycles", emu.cycles());
+            EXIT_OK
+        }
+        Finish::CpuError(e) => {
+            eprintln!("error: {}", e);
+            EXIT_CPU_ERROR
+        }
+        Finish::Timeout => {
+            eprintln!("error: timeout after {} cycles", emu.cycles());
+            EXIT_TIMEOUT
+        }
+        Finish::Error(e) => return Err(e.into()),
+    };
+    for frame in &frames[taken..] {
+        eprintln!("warning: no screenshot at frame {}, the run ended before", frame);
+    }
+    Ok(code)
+}
+
+fn main() {
+    let opts = CliOpts::from_args();
+    match run_app(&opts) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator(platform: Platform, rom: &[u8]) -> Chip8Emulator {
+        let mut emu = Chip8Emulator::new(DEFAULT_CPU_HZ);
+        emu.set_platform(platform);
+        emu.load_rom(rom);
+        emu
+    }
+
+    /// Run without screenshots nor trace
+    fn run_keys(emu: &mut Chip8Emulator, cycles: u64, keys: &[KeyEvent], until_exit: bool) -> Finish {
+        let schedule = Schedule {
+            keys,
+            screenshots: &[],
+            screenshot: &mut |_| Ok(()),
+        };
+        run(emu, cycles, schedule, None, until_exit, None)
+    }
+
+    /// JP 0x200
+    const LOOP: &[u8] = &[0x12, 0x00];
+    /// LD V0, 5; EXIT
+    const EXIT: &[u8] = &[0x60, 0x05, 0x00, 0xFD];
+
+    #[test]
+    fn key_script() {
+        let keys = parse_keys("# start\n\n300 a down # fire\n  100 5 down\n120   5 up\n").unwrap();
+        let keys: Vec<(u64, u8, bool)> = keys.iter().map(|e| (e.cycle, e.key, e.pressed)).collect();
+        assert_eq!(keys, [(100, 5, true), (120, 5, false), (300, 10, true)]);
+        assert!(parse_keys("").unwrap().is_empty());
+
+        for (text, line) in [
+            ("1 2 down\n10 g down", 2),
+            ("10 10 down", 1),
+            ("-1 5 down", 1),
+            ("10 5 pressed", 1),
+            ("\n# comment\n10 5", 3),
+            ("10 5 down up", 1),
+        ] {
+            assert_eq!(parse_keys(text).map(|_| ()), Err(line), "{}", text);
+        }
+    }
+
+    #[test]
+    fn finish_done() {
+        let mut emu = emulator(Platform::Chip8, LOOP);
+        assert!(matches!(run_keys(&mut emu, 25_000, &[], false), Finish::Done));
+        assert_eq!(emu.cycles(), 25_000);
+    }
+
+    #[test]
+    fn finish_exited() {
+        let mut emu = emulator(Platform::SuperChip, EXIT);
+        assert!(matches!(run_keys(&mut emu, 1000, &[], true), Finish::Exited));
+        assert_eq!(emu.cpu().gpr(0), Some(5));
+        // Also without waiting for it
+        let mut emu = emulator(Platform::SuperChip, EXIT);
+        assert!(matches!(run_keys(&mut emu, 1000, &[], false), Finish::Exited));
+    }
+
+    #[test]
+    fn finish_cpu_error() {
+        let mut emu = emulator(Platform::Chip8, &[0x60, 0x01, 0xFF, 0xFF]);
+        match run_keys(&mut emu, 1000, &[], false) {
+            Finish::CpuError(e) => assert_eq!(e.pc, 0x202),
+            _ => panic!("no CPU error"),
+        }
+    }
+
+    #[test]
+    fn finish_timeout() {
+        // The ROM does not exit in the given cycles
+        let mut emu = emulator(Platform::SuperChip, LOOP);
+        assert!(matches!(run_keys(&mut emu, 1000, &[], true), Finish::Timeout));
+        assert_eq!(emu.cycles(), 1000);
+
+        // Wall-clock
+        let mut emu = emulator(Platform::Chip8, LOOP);
+        let schedule = Schedule {
+            keys: &[],
+            screenshots: &[],
+            screenshot: &mut |_| Ok(()),
+        };
+        let finish = run(&mut emu, u64::MAX / 2, schedule, Some(Duration::from_millis(20)), false, None);
+        assert!(matches!(finish, Finish::Timeout));
+        assert!(emu.cycles() > 0);
+    }
+
+    #[test]
+    fn schedule() {
+        // LD V0, K; EXIT
+        let mut emu = emulator(Platform::SuperChip, &[0xF0, 0x0A, 0x00, 0xFD]);
+        let keys = parse_keys("150 7 up\n100 7 down").unwrap();
+        let mut taken = Vec::new();
+        let schedule = Schedule {
+            keys: &keys,
+            screenshots: &[0, 50, 50],
+            screenshot: &mut |emu| {
+                taken.push(emu.cycles());
+                Ok(())
+            },
+        };
+        assert!(matches!(run(&mut emu, 1000, schedule, None, true, None), Finish::Exited));
+        // The key is seen at cycle 100, exactly
+        assert_eq!((emu.cycles(), emu.cpu().gpr(0)), (102, Some(7)));
+        assert_eq!(taken, [0, 50, 50]);
+
+        // A failed screenshot stops the run
+        let mut emu = emulator(Platform::Chip8, LOOP);
+        let schedule = Schedule {
+            keys: &[],
+            screenshots: &[10],
+            screenshot: &mut |_| Err(io::Error::other("disk full")),
+        };
+        assert!(matches!(run(&mut emu, 1000, schedule, None, false, None), Finish::Error(_)));
+        assert_eq!(emu.cycles(), 10);
+    }
+}
@@ -1,19 +1,42 @@
 #![no_std]
 // #![feature(maybe_uninit_ref)]
 
+use core::fmt::{self, Write};
 use core::mem::MaybeUninit;
 use core::panic::PanicInfo;
 
-use chip8::{CHIP8_FB_W, CHIP8_FB_H, Chip8Emulator, Chip8Fb, CHIP8_PERIPH_HZ};
+use chip8::{SCHIP_FB_W, SCHIP_FB_H, Chip8Emulator, Chip8Fb, CHIP8_PERIPH_HZ, MEMORY_SIZE, Platform, STATE_SIZE, StopReason};
+use chip8::cpu::CpuError;
 
 static mut EMU_CPU_HZ: u32 = 600;
+static mut EMU_PLATFORM: Platform = Platform::Chip8;
 
 // NOTE: the lower part of the memory is supposed to be reserved to the emulator
-const CHIP8_MEM_SIZE: usize = 4096 - 0x200;
+const CHIP8_MEM_SIZE: usize = MEMORY_SIZE - 0x200;
 
-static mut FRAMEBUFFER: Chip8Fb = [0; CHIP8_FB_W * CHIP8_FB_H];
+static mut FRAMEBUFFER: Chip8Fb = [0; SCHIP_FB_W * SCHIP_FB_H];
 static mut MEMORY_BUFF: [u8; CHIP8_MEM_SIZE] = [0u8; CHIP8_MEM_SIZE];
+static mut STATE_BUFF: [u8; STATE_SIZE] = [0u8; STATE_SIZE];
 static mut EMULATOR: MaybeUninit::<Chip8Emulator> = MaybeUninit::uninit();
+static mut STOP_REASON: StopReason = StopReason::Completed;
+
+const ERROR_SIZE: usize = 256;
+static mut ERROR_BUFF: [u8; ERROR_SIZE] = [0u8; ERROR_SIZE];
+static mut ERROR_LEN: usize = 0;
+
+/// Writes the error message to `ERROR_BUFF`, truncated
+struct ErrorWriter;
+
+impl Write for ErrorWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe {
+            let n = s.len().min(ERROR_SIZE - ERROR_LEN);
+            ERROR_BUFF[ERROR_LEN..ERROR_LEN + n].copy_from_slice(&s.as_bytes()[..n]);
+            ERROR_LEN += n;
+        }
+        Ok(())
+    }
+}
 
 #[no_mangle]
 pub unsafe extern fn chip8_init() {
@@ -24,23 +47,127 @@ pub unsafe extern fn chip8_init() {
 pub unsafe extern fn chip8_reset() {
     let emu = &mut *EMULATOR.as_mut_ptr();
     *emu = Chip8Emulator::new(EMU_CPU_HZ);
+    emu.set_platform(EMU_PLATFORM);
     emu.peripherals_mut().screen.set_inverted_y(false);
     emu.load_rom(&MEMORY_BUFF);
 }
 
+/// Copy the part of the screen changed since the last call to `FRAMEBUFFER`
+unsafe fn update_framebuffer(emu: &mut Chip8Emulator) {
+    let damage = match emu.peripherals_mut().screen.take_damage() {
+        Some(damage) => damage,
+        None => return,
+    };
+    let width = emu.peripherals().screen.width() as usize;
+    let fb = emu.framebuffer();
+    for y in damage.rows() {
+        let row = y as usize * width;
+        let columns = row + damage.x as usize..row + (damage.x + damage.width) as usize;
+        for (dst, src) in FRAMEBUFFER[columns.clone()].iter_mut().zip(fb[columns].iter()) {
+            *dst = *src | 0xFF_00_00_00;
+        }
+    }
+}
+
+unsafe fn set_error(e: CpuError) {
+    ERROR_LEN = 0;
+    let _ = write!(ErrorWriter, "{}", e);
+}
+
 #[no_mangle]
 pub unsafe extern fn chip8_advance_ms(ms: u32) -> bool {
     let emu = &mut *EMULATOR.as_mut_ptr();
-    FRAMEBUFFER.copy_from_slice(emu.framebuffer());
-    for x in FRAMEBUFFER.iter_mut() {
-        *x |= 0xFF_00_00_00;
-    }
+    update_framebuffer(emu);
     match emu.advance_ms(ms) {
-        Ok(()) => true,
-        Err(_) => false
+        Ok(reason) => {
+            STOP_REASON = reason;
+            true
+        }
+        Err(e) => {
+            set_error(e);
+            false
+        }
+    }
+}
+
+/// Run one 60Hz frame and update the framebuffer. Returns -1 if the CPU
+/// failed, otherwise the flags: 1 = the screen changed, 2 = the sound timer
+/// is active, 4 = the CPU waits for a key.
+#[no_mangle]
+pub unsafe extern fn chip8_run_frame() -> i32 {
+    let emu = &mut *EMULATOR.as_mut_ptr();
+    match emu.run_frame() {
+        Ok(frame) => {
+            STOP_REASON = frame.stop;
+            update_framebuffer(emu);
+            frame.screen_changed as i32 | (frame.sound_active as i32) << 1 | (frame.waiting_for_key as i32) << 2
+        }
+        Err(e) => {
+            set_error(e);
+            -1
+        }
     }
 }
 
+/// Message of the CPU error which made the last `chip8_advance_ms` or
+/// `chip8_run_frame` fail, `chip8_error_len` bytes of ASCII
+#[no_mangle]
+pub unsafe extern fn chip8_error() -> &'static [u8; ERROR_SIZE] {
+    &ERROR_BUFF
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_error_len() -> u32 {
+    ERROR_LEN as u32
+}
+
+/// Why the last `chip8_advance_ms` or `chip8_run_frame` returned early: 0 = it did not, 1 =
+/// breakpoint, 2 = watchpoint, 3 = register condition, 4 = instruction
+/// class, 5 = end of a step.
+#[no_mangle]
+pub unsafe extern fn chip8_stop_reason() -> u32 {
+    match STOP_REASON {
+        StopReason::Completed => 0,
+        StopReason::Breakpoint(_) => 1,
+        StopReason::Watchpoint { .. } => 2,
+        StopReason::Register { .. } => 3,
+        StopReason::InsnClass(_) => 4,
+        StopReason::Step => 5,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_pc() -> u32 {
+    let emu = &*EMULATOR.as_ptr();
+    emu.cpu().pc() as u32
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_add_breakpoint(addr: u32) -> i32 {
+    let emu = &mut *EMULATOR.as_mut_ptr();
+    if emu.debugger_mut().add_breakpoint(addr as u16) { 0 } else { -1 }
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_remove_breakpoint(addr: u32) -> i32 {
+    let emu = &mut *EMULATOR.as_mut_ptr();
+    if emu.debugger_mut().remove_breakpoint(addr as u16) { 0 } else { -1 }
+}
+
+/// Stop after the next instruction: 0 = step into, 1 = step over, 2 = step
+/// out.
+#[no_mangle]
+pub unsafe extern fn chip8_step(kind: u32) -> i32 {
+    let emu = &mut *EMULATOR.as_mut_ptr();
+    match kind {
+        0 => emu.step_into(),
+        1 => emu.step_over(),
+        2 => emu.step_out(),
+        _ => return -1,
+    }
+    0
+}
+
 #[no_mangle]
 pub unsafe extern fn chip8_key_down(k: u32) {
     let emu = &mut *EMULATOR.as_mut_ptr();
@@ -54,19 +181,21 @@ pub unsafe extern fn chip8_key_up(k: u32) {
 }
 
 #[no_mangle]
-pub unsafe extern fn chip8_fb() -> &'static [u32; CHIP8_FB_W * CHIP8_FB_H] {
+pub unsafe extern fn chip8_fb() -> &'static Chip8Fb {
     &FRAMEBUFFER
 }
 
 
 #[no_mangle]
 pub unsafe extern fn chip8_fb_width() -> u32 {
-    CHIP8_FB_W as u32
+    let emu = &*EMULATOR.as_ptr();
+    emu.peripherals().screen.width()
 }
 
 #[no_mangle]
 pub unsafe extern fn chip8_fb_height() -> u32 {
-    CHIP8_FB_H as u32
+    let emu = &*EMULATOR.as_ptr();
+    emu.peripherals().screen.height()
 }
 
 #[no_mangle]
@@ -74,6 +203,11 @@ pub unsafe extern fn chip8_memory() -> &'static [u8; CHIP8_MEM_SIZE] {
     &MEMORY_BUFF
 }
 
+#[no_mangle]
+pub unsafe extern fn chip8_memory_size() -> u32 {
+    CHIP8_MEM_SIZE as u32
+}
+
 #[no_mangle]
 pub unsafe extern fn chip8_set_cpu_hz(hz: u32) -> i32 {
     let emu = &mut *EMULATOR.as_mut_ptr();
@@ -85,6 +219,49 @@ pub unsafe extern fn chip8_set_cpu_hz(hz: u32) -> i32 {
     0
 }
 
+/// Select the emulated platform: 0 = CHIP-8, 1 = SUPER-CHIP, 2 = XO-CHIP.
+/// Takes effect on the next reset.
+#[no_mangle]
+pub unsafe extern fn chip8_set_platform(platform: u32) -> i32 {
+    EMU_PLATFORM = match platform {
+        0 => Platform::Chip8,
+        1 => Platform::SuperChip,
+        2 => Platform::XoChip,
+        _ => return -1,
+    };
+    0
+}
+
+/// Buffer holding the save state, written by `chip8_save_state` and read by
+/// `chip8_load_state`
+#[no_mangle]
+pub unsafe extern fn chip8_state() -> &'static [u8; STATE_SIZE] {
+    &STATE_BUFF
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_state_size() -> u32 {
+    STATE_SIZE as u32
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_save_state() -> i32 {
+    let emu = &*EMULATOR.as_ptr();
+    match emu.save_state(&mut STATE_BUFF) {
+        Ok(_) => 0,
+        Err(_) => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern fn chip8_load_state() -> i32 {
+    let emu = &mut *EMULATOR.as_mut_ptr();
+    match emu.load_state(&STATE_BUFF) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
 #[panic_handler]
 fn handle_panic(_: &PanicInfo) -> ! {
     loop {}
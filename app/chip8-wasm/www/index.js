@@ -37,20 +37,18 @@ async function init() {
     }
     const {instance} = await WebAssembly.instantiateStreaming(fetch("./chip8_wasm.wasm"));
     const exports = instance.exports;
+    // Debugging from the console, e.g. chip8.chip8_add_breakpoint(0x200)
+    window.chip8 = exports;
 
     exports.chip8_init();
 
-    const canvas_w = exports.chip8_fb_width();
-    const canvas_h = exports.chip8_fb_height();
     const canvas = document.getElementById("demo-canvas");
-    canvas.width = canvas_w;
-    canvas.height = canvas_h;
 
     // obtain the various memory sections
     const chip8Memory = new Uint8Array(
         exports.memory.buffer,
         exports.chip8_memory(),
-        4096
+        exports.chip8_memory_size()
     );
 
     const loadRom = rom =>
@@ -59,26 +57,59 @@ async function init() {
             .then(buffer => {
                 // write the ROM to memory
                 const rom = new DataView(buffer, 0, buffer.byteLength);
+                chip8Memory.fill(0);
                 for (var k = 0; k < rom.byteLength; k++) {
                     chip8Memory[k] = rom.getUint8(k);
                 }
                 exports.chip8_reset();
             });
 
+    // The framebuffer dimensions change when SUPER-CHIP programs switch
+    // between low and high resolution modes.
     const framebuffer = exports.chip8_fb();
-    const image = new ImageData(
-        new Uint8ClampedArray(
-            exports.memory.buffer,
-            framebuffer,
-            4 * canvas_w * canvas_h,
-        ),
-        canvas_w,
-    );
+    let image;
+    const updateImage = () => {
+        const canvas_w = exports.chip8_fb_width();
+        const canvas_h = exports.chip8_fb_height();
+        if (image !== undefined && image.width === canvas_w && image.height === canvas_h)
+            return;
+        canvas.width = canvas_w;
+        canvas.height = canvas_h;
+        image = new ImageData(
+            new Uint8ClampedArray(
+                exports.memory.buffer,
+                framebuffer,
+                4 * canvas_w * canvas_h,
+            ),
+            canvas_w,
+        );
+    };
 
+    // Quicksave (F5) and quickload (F9), the state is kept in the WASM
+    // module memory.
+    let hasState = false;
+    // Debugger: the emulation pauses on breakpoints, F6 pauses or resumes
+    // it, F7, F8 and F11 step into, over and out.
+    let paused = false;
+    const steps = {"F7": 0, "F8": 1, "F11": 2};
     document.addEventListener("keydown", event => {
         const key = event.key;
         console.log(`Key down: ${key}`);
-        if (key in keymap) {
+        if (key === "F6") {
+            event.preventDefault();
+            paused = !paused;
+        } else if (key in steps) {
+            event.preventDefault();
+            exports.chip8_step(steps[key]);
+            paused = false;
+        } else if (key === "F5") {
+            event.preventDefault();
+            hasState = exports.chip8_save_state() === 0;
+        } else if (key === "F9") {
+            event.preventDefault();
+            if (hasState && exports.chip8_load_state() !== 0)
+                console.log('invalid save state');
+        } else if (key in keymap) {
             exports.chip8_key_down(keymap[key]);
         }
     });
@@ -103,15 +134,42 @@ async function init() {
     document.getElementById("chip8-cpuhz").addEventListener("change", e => {
         exports.chip8_set_cpu_hz(parseInt(e.target.value));
     });
+    document.getElementById("chip8-platform").addEventListener("change", e => {
+        exports.chip8_set_platform(parseInt(e.target.value));
+        exports.chip8_reset();
+    });
 
     const ctx = canvas.getContext("2d");
+    // The emulation runs by 60Hz frames, the time not emulated yet being
+    // carried over. After a stall, it slows down instead of catching up.
+    const frameMs = 1000 / 60;
+    const maxFramesPerRender = 4;
+    let lag = 0;
     let start;
     const render = (timestamp) => {
         if (start === undefined)
             start = timestamp;
         const elapsed = timestamp - start;
         start = timestamp;
-        exports.chip8_advance_ms(elapsed);
+        lag = paused ? 0 : lag + elapsed;
+        for (let frames = 0; lag >= frameMs && !paused; frames++) {
+            if (frames === maxFramesPerRender) {
+                lag = 0;
+                break;
+            }
+            lag -= frameMs;
+            if (exports.chip8_run_frame() < 0) {
+                const msg = new Uint8Array(exports.memory.buffer, exports.chip8_error(), exports.chip8_error_len());
+                console.error(`CPU error: ${new TextDecoder().decode(msg)}`);
+                paused = true;
+            }
+            const reason = exports.chip8_stop_reason();
+            if (!paused && reason !== 0) {
+                console.log(`debugger stop (reason ${reason}) at ${exports.chip8_pc().toString(16)}`);
+                paused = true;
+            }
+        }
+        updateImage();
         ctx.putImageData(image, 0, 0);
         requestAnimationFrame(render);
     };
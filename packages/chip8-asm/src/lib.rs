@@ -0,0 +1,481 @@
+//
+// CHIP-8 assembler
+//
+// The syntax follows the mnemonics printed by `Insn`'s `Display`
+// implementation (case-insensitive), with a few directives:
+//
+//     SPEED   equ 2               ; constant
+//     start:  LD V0, SPEED        ; label
+//             LD I, ship
+//             DRW V0, V1, 3
+//             JP start
+//     ship:   sprite "..##....", ".####...", "##..##.."
+//     text:   db "HI", 0, 0x10
+//     table:  dw start, ship + 1
+//             include "lib.asm"   ; relative to the current file
+//             org 0x300           ; move the output address
+//
+// Assembly runs in two passes: the first one assigns an address to every
+// label, the second one evaluates expressions and emits the bytes. Errors
+// are collected and reported together, with their file and line.
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chip8::{Insn, Platform, MEMORY_SIZE};
+
+mod parser;
+
+pub use parser::{parse_line, Expr, Operand, ParsedLine, Stmt, Term};
+
+/// Default load address of CHIP-8 programs
+pub const DEFAULT_ORIGIN: u16 = 0x200;
+
+/// Maximum nesting of `include` directives
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Maximum nesting of constant definitions
+const MAX_EQU_DEPTH: usize = 32;
+
+/// Error attached to a source line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: error: {}", self.file, self.line, self.message)
+    }
+}
+
+/// Assembled program
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// Bytes to load at the origin
+    pub rom: Vec<u8>,
+    /// Address of the labels
+    pub labels: BTreeMap<String, u16>,
+}
+
+struct SourceLine {
+    file: Rc<str>,
+    line: usize,
+    parsed: ParsedLine,
+}
+
+enum Symbol {
+    Label(u16),
+    Const(Expr),
+}
+
+pub struct Assembler {
+    platform: Platform,
+    origin: u16,
+    lines: Vec<SourceLine>,
+    symbols: HashMap<String, Symbol>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Assembler {
+    /// Create an assembler accepting the instructions of `platform`
+    pub fn new(platform: Platform) -> Self {
+        Assembler {
+            platform,
+            origin: DEFAULT_ORIGIN,
+            lines: Vec::new(),
+            symbols: HashMap::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Change the address of the first byte of the program
+    pub fn set_origin(&mut self, origin: u16) {
+        self.origin = origin;
+    }
+
+    /// Assemble a source file
+    pub fn assemble_file<P: AsRef<Path>>(mut self, path: P) -> Result<Program, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(src) => self.load(path, &src, 0),
+            Err(e) => self.error_at(&path.display().to_string(), 0, format!("cannot read file: {}", e)),
+        }
+        self.assemble()
+    }
+
+    /// Assemble source code, `name` is used in diagnostics and included
+    /// files are searched relative to it.
+    pub fn assemble_str(mut self, name: &str, src: &str) -> Result<Program, Vec<Diagnostic>> {
+        self.load(Path::new(name), src, 0);
+        self.assemble()
+    }
+
+    fn error_at(&mut self, file: &str, line: usize, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: file.to_string(),
+            line,
+            message,
+        });
+    }
+
+    fn error(&mut self, k: usize, message: String) {
+        let (file, line) = (self.lines[k].file.clone(), self.lines[k].line);
+        self.error_at(&file, line, message);
+    }
+
+    /// Parse a file, and recursively its includes
+    fn load(&mut self, path: &Path, src: &str, depth: usize) {
+        let file: Rc<str> = Rc::from(path.display().to_string());
+        for (k, text) in src.lines().enumerate() {
+            let line = k + 1;
+            let parsed = match parse_line(text) {
+                Ok(parsed) => parsed,
+                Err(msg) => {
+                    self.error_at(&file, line, msg);
+                    continue;
+                }
+            };
+            if let Some(Stmt::Include(name)) = &parsed.stmt {
+                let inc_path: PathBuf = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+                if parsed.label.is_some() {
+                    self.error_at(&file, line, "label not allowed on include".to_string());
+                } else if depth >= MAX_INCLUDE_DEPTH {
+                    self.error_at(&file, line, format!("includes nested too deeply ({})", name));
+                } else {
+                    match fs::read_to_string(&inc_path) {
+                        Ok(inc) => self.load(&inc_path, &inc, depth + 1),
+                        Err(e) => self.error_at(&file, line, format!("cannot include '{}': {}", name, e)),
+                    }
+                }
+                continue;
+            }
+            self.lines.push(SourceLine {
+                file: file.clone(),
+                line,
+                parsed,
+            });
+        }
+    }
+
+    fn assemble(mut self) -> Result<Program, Vec<Diagnostic>> {
+        let addrs = self.assign_addresses();
+        let mut image = vec![0u8; MEMORY_SIZE];
+        let mut end = self.origin as usize;
+        for (k, addr) in addrs.into_iter().enumerate() {
+            let stmt = match &self.lines[k].parsed.stmt {
+                Some(stmt) => stmt.clone(),
+                None => continue,
+            };
+            let bytes = match self.emit(&stmt) {
+                Ok(bytes) => bytes,
+                Err(msg) => {
+                    self.error(k, msg);
+                    continue;
+                }
+            };
+            let addr = addr as usize;
+            if addr < self.origin as usize && !bytes.is_empty() {
+                self.error(k, format!("address 0x{:03X} is below the origin", addr));
+            } else if addr + bytes.len() > MEMORY_SIZE {
+                self.error(k, "program does not fit in memory".to_string());
+            } else {
+                image[addr..addr + bytes.len()].copy_from_slice(&bytes);
+                end = end.max(addr + bytes.len());
+            }
+        }
+        if !self.diagnostics.is_empty() {
+            return Err(self.diagnostics);
+        }
+        let labels = self
+            .symbols
+            .iter()
+            .filter_map(|(name, s)| match s {
+                Symbol::Label(a) => Some((name.clone(), *a)),
+                Symbol::Const(_) => None,
+            })
+            .collect();
+        Ok(Program {
+            rom: image[self.origin as usize..end].to_vec(),
+            labels,
+        })
+    }
+
+    /// First pass: define the symbols, returns the address of each line
+    fn assign_addresses(&mut self) -> Vec<u32> {
+        let mut addrs = Vec::with_capacity(self.lines.len());
+        let mut addr = self.origin as u32;
+        for k in 0..self.lines.len() {
+            // Overflows are reported when emitting the bytes
+            addrs.push(addr);
+            if let Some(name) = self.lines[k].parsed.label.clone() {
+                self.define(k, name, Symbol::Label(addr as u16));
+            }
+            match self.lines[k].parsed.stmt.clone() {
+                Some(Stmt::Equ(name, e)) => self.define(k, name, Symbol::Const(e)),
+                Some(Stmt::Org(e)) => match self.eval_range(&e, 0, MEMORY_SIZE as i64 - 1) {
+                    Ok(a) => {
+                        addr = a as u32;
+                        // Labels on the directive refer to the new address
+                        if let Some(name) = &self.lines[k].parsed.label {
+                            self.symbols.insert(name.clone(), Symbol::Label(a as u16));
+                        }
+                    }
+                    Err(msg) => self.error(k, msg),
+                },
+                Some(stmt) => addr += self.stmt_size(&stmt) as u32,
+                None => {}
+            }
+        }
+        addrs
+    }
+
+    fn define(&mut self, k: usize, name: String, sym: Symbol) {
+        let defined = match self.symbols.entry(name.clone()) {
+            Entry::Occupied(_) => true,
+            Entry::Vacant(e) => {
+                e.insert(sym);
+                false
+            }
+        };
+        if defined {
+            self.error(k, format!("symbol '{}' is already defined", name));
+        }
+    }
+
+    /// Number of bytes emitted by a statement, computed without evaluating
+    /// expressions.
+    fn stmt_size(&self, stmt: &Stmt) -> usize {
+        match stmt {
+            Stmt::Insn { operands, .. } => match operands.as_slice() {
+                [_, Operand::Long(_)] => 4,
+                _ => 2,
+            },
+            Stmt::Db(items) => items
+                .iter()
+                .map(|i| match i {
+                    Operand::Str(s) => s.len(),
+                    _ => 1,
+                })
+                .sum(),
+            Stmt::Dw(items) => 2 * items.len(),
+            Stmt::Sprite(rows) => rows.iter().map(|r| r.len().div_ceil(8)).sum(),
+            Stmt::Equ(_, _) | Stmt::Org(_) | Stmt::Include(_) => 0,
+        }
+    }
+
+    fn eval(&self, e: &Expr, depth: usize) -> Result<i64, String> {
+        if depth > MAX_EQU_DEPTH {
+            return Err(format!("recursive definition in '{}'", e));
+        }
+        let mut acc: i64 = 0;
+        for (neg, term) in &e.0 {
+            let v = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(name) => match self.symbols.get(name) {
+                    Some(Symbol::Label(a)) => *a as i64,
+                    Some(Symbol::Const(e)) => self.eval(e, depth + 1)?,
+                    None => return Err(format!("undefined symbol '{}'", name)),
+                },
+            };
+            acc = if *neg { acc.wrapping_sub(v) } else { acc.wrapping_add(v) };
+        }
+        Ok(acc)
+    }
+
+    fn eval_range(&self, e: &Expr, min: i64, max: i64) -> Result<i64, String> {
+        let v = self.eval(e, 0)?;
+        if v < min || v > max {
+            return Err(format!("value {} of '{}' out of range [{}, {}]", v, e, min, max));
+        }
+        Ok(v)
+    }
+
+    fn emit(&self, stmt: &Stmt) -> Result<Vec<u8>, String> {
+        match stmt {
+            Stmt::Insn { mnemonic, operands } => {
+                let insn = self.build_insn(mnemonic, operands)?;
+                if insn.platform() > self.platform {
+                    return Err(format!("'{}' requires the {:?} platform", insn, insn.platform()));
+                }
+                let mut bytes = vec![0; insn.size() as usize];
+                insn.write_bytes(&mut bytes);
+                Ok(bytes)
+            }
+            Stmt::Db(items) => {
+                let mut bytes = Vec::new();
+                for item in items {
+                    match item {
+                        Operand::Str(s) if s.is_ascii() => bytes.extend_from_slice(s.as_bytes()),
+                        Operand::Str(s) => return Err(format!("non-ASCII string \"{}\"", s)),
+                        Operand::Expr(e) => bytes.push(self.eval_range(e, -0x80, 0xFF)? as u8),
+                        _ => return Err("db expects numbers or strings".to_string()),
+                    }
+                }
+                Ok(bytes)
+            }
+            Stmt::Dw(items) => {
+                let mut bytes = Vec::new();
+                for e in items {
+                    let w = self.eval_range(e, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&w.to_be_bytes());
+                }
+                Ok(bytes)
+            }
+            Stmt::Sprite(rows) => sprite_bytes(rows),
+            Stmt::Equ(_, _) | Stmt::Org(_) | Stmt::Include(_) => Ok(Vec::new()),
+        }
+    }
+
+    fn reg(&self, op: &Operand) -> Result<u8, String> {
+        match op {
+            Operand::Reg(r) => Ok(*r),
+            _ => Err(format!("expected a register, found {}", describe(op))),
+        }
+    }
+
+    fn value(&self, op: &Operand, min: i64, max: i64) -> Result<i64, String> {
+        match op {
+            Operand::Expr(e) => self.eval_range(e, min, max),
+            _ => Err(format!("expected a value, found {}", describe(op))),
+        }
+    }
+
+    fn byte(&self, op: &Operand) -> Result<u8, String> {
+        self.value(op, -0x80, 0xFF).map(|v| v as u8)
+    }
+
+    fn nibble(&self, op: &Operand) -> Result<u8, String> {
+        self.value(op, 0, 0xF).map(|v| v as u8)
+    }
+
+    fn addr(&self, op: &Operand) -> Result<u16, String> {
+        self.value(op, 0, 0xFFF).map(|v| v as u16)
+    }
+
+    fn build_insn(&self, mnemonic: &str, ops: &[Operand]) -> Result<Insn, String> {
+        use Operand::{Keyword as K, Reg as R};
+
+        let kw = |op: &Operand, k: &str| matches!(op, K(s) if s == k);
+        let insn = match (mnemonic, ops) {
+            ("CLS", []) => Insn::Cls,
+            ("RET", []) => Insn::Ret,
+            ("SCR", []) => Insn::ScrollRight,
+            ("SCL", []) => Insn::ScrollLeft,
+            ("EXIT", []) => Insn::Exit,
+            ("LOW", []) => Insn::LoRes,
+            ("HIGH", []) => Insn::HiRes,
+            ("AUDIO", []) => Insn::LoadAudio,
+            ("SCD", [n]) => Insn::ScrollDown(self.nibble(n)?),
+            ("SCU", [n]) => Insn::ScrollUp(self.nibble(n)?),
+            // The mask is truncated to the existing planes by the CPU
+            ("PLANE", [n]) => Insn::SelectPlanes(self.nibble(n)?),
+            ("JP", [R(0), a]) => Insn::JumpV0(self.addr(a)?),
+            ("JP", [a]) => Insn::Jump(self.addr(a)?),
+            ("CALL", [a]) => Insn::Call(self.addr(a)?),
+            ("SE", [R(x), R(y)]) => Insn::SkipEq(*x, *y),
+            ("SE", [R(x), b]) => Insn::SkipEqI(*x, self.byte(b)?),
+            ("SNE", [R(x), R(y)]) => Insn::SkipNeq(*x, *y),
+            ("SNE", [R(x), b]) => Insn::SkipNeqI(*x, self.byte(b)?),
+            ("LD", [R(x), R(y)]) => Insn::Move(*x, *y),
+            ("LD", [R(x), t]) if kw(t, "DT") => Insn::LoadTimer(*x),
+            ("LD", [R(x), t]) if kw(t, "K") => Insn::WaitForKey(*x),
+            ("LD", [R(x), t]) if kw(t, "[I]") => Insn::LoadRegs(*x),
+            ("LD", [R(x), t]) if kw(t, "R") => Insn::LoadFlags(*x),
+            ("LD", [R(x), b]) => Insn::LoadI(*x, self.byte(b)?),
+            ("LD", [i, Operand::Long(e)]) if kw(i, "I") => Insn::LoadLongA(self.eval_range(e, 0, 0xFFFF)? as u16),
+            ("LD", [i, a]) if kw(i, "I") => Insn::LoadA(self.addr(a)?),
+            ("LD", [t, x]) if kw(t, "DT") => Insn::SetDelayTimer(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "ST") => Insn::SetSoundTimer(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "F") => Insn::SpriteLoc(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "HF") => Insn::BigSpriteLoc(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "B") => Insn::StoreBCD(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "[I]") => Insn::StoreRegs(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "R") => Insn::StoreFlags(self.reg(x)?),
+            ("LD", [t, x]) if kw(t, "PITCH") => Insn::SetPitch(self.reg(x)?),
+            ("ADD", [R(x), R(y)]) => Insn::Add(*x, *y),
+            ("ADD", [R(x), b]) => Insn::AddI(*x, self.byte(b)?),
+            ("ADD", [i, x]) if kw(i, "I") => Insn::AddA(self.reg(x)?),
+            ("OR", [x, y]) => Insn::Or(self.reg(x)?, self.reg(y)?),
+            ("AND", [x, y]) => Insn::And(self.reg(x)?, self.reg(y)?),
+            ("XOR", [x, y]) => Insn::Xor(self.reg(x)?, self.reg(y)?),
+            ("SUB", [x, y]) => Insn::Sub(self.reg(x)?, self.reg(y)?),
+            ("SUBN", [x, y]) => Insn::SubN(self.reg(x)?, self.reg(y)?),
+            ("SHR", [x]) => Insn::Shr(self.reg(x)?, self.reg(x)?),
+            ("SHR", [x, y]) => Insn::Shr(self.reg(x)?, self.reg(y)?),
+            ("SHL", [x]) => Insn::Shl(self.reg(x)?, self.reg(x)?),
+            ("SHL", [x, y]) => Insn::Shl(self.reg(x)?, self.reg(y)?),
+            ("RND", [x, b]) => Insn::RndAnd(self.reg(x)?, self.byte(b)?),
+            ("DRW", [x, y, n]) => Insn::DrawSprite(self.reg(x)?, self.reg(y)?, self.nibble(n)?),
+            ("SKP", [x]) => Insn::SkipKeyPressed(self.reg(x)?),
+            ("SKNP", [x]) => Insn::SkipKeyNPressed(self.reg(x)?),
+            ("SAVE", [x, y]) => Insn::SaveRange(self.reg(x)?, self.reg(y)?),
+            ("LOAD", [x, y]) => Insn::LoadRange(self.reg(x)?, self.reg(y)?),
+            (
+                "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" | "SCD" | "SCU" | "PLANE" | "JP"
+                | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL"
+                | "RND" | "DRW" | "SKP" | "SKNP" | "SAVE" | "LOAD",
+                _,
+            ) => return Err(format!("invalid operands for {}", mnemonic)),
+            _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+        };
+        // The CPU must read back the same instruction
+        if decode_bytes(&insn).as_ref() != Some(&insn) {
+            return Err(format!("'{}' has no valid encoding", insn));
+        }
+        Ok(insn)
+    }
+}
+
+fn describe(op: &Operand) -> String {
+    match op {
+        Operand::Reg(r) => format!("register V{:X}", r),
+        Operand::Keyword(k) => format!("'{}'", k),
+        Operand::Long(e) => format!("'LONG {}'", e),
+        Operand::Expr(e) => format!("'{}'", e),
+        Operand::Str(s) => format!("string \"{}\"", s),
+    }
+}
+
+/// Decode the encoding of an instruction, as the CPU would
+fn decode_bytes(insn: &Insn) -> Option<Insn> {
+    let mut bytes = [0u8; 4];
+    insn.write_bytes(&mut bytes);
+    let w0 = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let w1 = u16::from_be_bytes([bytes[2], bytes[3]]);
+    Insn::decode_long(w0, w1)
+}
+
+/// Convert sprite rows (`#` for set pixels, `.` for cleared ones) to bytes
+fn sprite_bytes(rows: &[String]) -> Result<Vec<u8>, String> {
+    let width = rows.first().map(|r| r.len()).unwrap_or(0);
+    if width != 8 && width != 16 {
+        return Err(format!("sprite rows must be 8 or 16 pixels wide, found {}", width));
+    }
+    let mut bytes = Vec::new();
+    for row in rows {
+        if row.len() != width {
+            return Err(format!("sprite row \"{}\" is not {} pixels wide", row, width));
+        }
+        let mut bits: u16 = 0;
+        for c in row.chars() {
+            bits = (bits << 1)
+                | match c {
+                    '#' | 'X' | 'x' | '1' => 1,
+                    '.' | ' ' | '0' => 0,
+                    _ => return Err(format!("invalid sprite pixel '{}'", c)),
+                };
+        }
+        if width == 16 {
+            bytes.extend_from_slice(&bits.to_be_bytes());
+        } else {
+            bytes.push(bits as u8);
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip8::Platform;
+use chip8_asm::Assembler;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chip8asm", about = "Chip 8 assembler")]
+pub struct CliOpts {
+    /// Instruction set accepted (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform", default_value = "chip8")]
+    platform: Platform,
+
+    /// Output ROM (default: input file with the .ch8 extension)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    #[structopt(name = "FILE.asm", parse(from_os_str))]
+    input: PathBuf,
+}
+
+fn main() {
+    let opts = CliOpts::from_args();
+    let program = match Assembler::new(opts.platform).assemble_file(&opts.input) {
+        Ok(program) => program,
+        Err(diagnostics) => {
+            for d in diagnostics.iter() {
+                eprintln!("{}", d);
+            }
+            eprintln!("{} error(s)", diagnostics.len());
+            std::process::exit(1);
+        }
+    };
+    let output = opts.output.clone().unwrap_or_else(|| opts.input.with_extension("ch8"));
+    if let Err(e) = fs::write(&output, &program.rom) {
+        eprintln!("error: cannot write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+    println!("{}: {} bytes", output.display(), program.rom.len());
+}
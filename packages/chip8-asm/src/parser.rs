@@ -0,0 +1,262 @@
+//
+// Line-oriented parser of the assembly syntax
+//
+// Each line holds an optional label, followed by an optional statement:
+//
+//     loop:   LD V0, K        ; comment
+//     SPEED   equ 3
+//             db 0x01, 2, "text"
+//
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+/// Sum of signed terms, e.g. `table + 2 - OFFSET`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Expr(pub Vec<(bool, Term)>);
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, (neg, t)) in self.0.iter().enumerate() {
+            if *neg {
+                write!(f, "-")?;
+            } else if k > 0 {
+                write!(f, "+")?;
+            }
+            match t {
+                Term::Number(n) => write!(f, "{}", n)?,
+                Term::Symbol(s) => write!(f, "{}", s)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Operand {
+    /// General purpose register (V0-VF)
+    Reg(u8),
+    /// Special operand (I, [I], DT, ST, K, F, HF, B, R, PITCH), uppercase
+    Keyword(String),
+    /// `LONG expr`, the 16-bit operand of the XO-CHIP long load
+    Long(Expr),
+    Expr(Expr),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Stmt {
+    Insn { mnemonic: String, operands: Vec<Operand> },
+    /// Bytes, given as expressions or strings
+    Db(Vec<Operand>),
+    /// Big-endian 16-bit words
+    Dw(Vec<Expr>),
+    /// Sprite rows drawn with `#` (on) and `.` (off), 8 or 16 pixels wide
+    Sprite(Vec<String>),
+    Equ(String, Expr),
+    Org(Expr),
+    Include(String),
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParsedLine {
+    pub label: Option<String>,
+    pub stmt: Option<Stmt>,
+}
+
+const KEYWORDS: [&str; 10] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R", "PITCH"];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if is_ident_start(c) => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.'),
+        _ => false,
+    }
+}
+
+/// Remove the comment at the end of a line, ignoring `;` in strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    for (k, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            ';' if !in_str => return &line[..k],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Split operands on commas, ignoring commas in strings
+fn split_operands(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_str = false;
+    let mut start = 0;
+    for (k, c) in s.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            ',' if !in_str => {
+                parts.push(s[start..k].trim());
+                start = k + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+pub fn parse_number(s: &str) -> Result<i64, String> {
+    let lower = s.to_ascii_lowercase();
+    let r = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        lower.parse::<i64>()
+    };
+    r.map_err(|_| format!("invalid number '{}'", s))
+}
+
+pub fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut terms = Vec::new();
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err("missing expression".to_string());
+    }
+    let mut neg = false;
+    let mut expect_term = true;
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            rest = rest.trim_start();
+            continue;
+        }
+        if !expect_term {
+            match c {
+                '+' => neg = false,
+                '-' => neg = true,
+                _ => return Err(format!("expected '+' or '-' in expression '{}'", s)),
+            }
+            rest = &rest[1..];
+            expect_term = true;
+            continue;
+        }
+        if c == '-' && terms.is_empty() && !neg {
+            neg = true;
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        if word.is_empty() {
+            return Err(format!("unexpected character '{}' in expression '{}'", c, s));
+        }
+        let term = if c.is_ascii_digit() {
+            Term::Number(parse_number(word)?)
+        } else if is_ident(word) {
+            Term::Symbol(word.to_string())
+        } else {
+            return Err(format!("invalid term '{}'", word));
+        };
+        terms.push((neg, term));
+        neg = false;
+        expect_term = false;
+        rest = &rest[end..];
+    }
+    if expect_term {
+        return Err(format!("incomplete expression '{}'", s));
+    }
+    Ok(Expr(terms))
+}
+
+fn parse_string(s: &str) -> Result<String, String> {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        Ok(s[1..s.len() - 1].to_string())
+    } else {
+        Err(format!("invalid string {}", s))
+    }
+}
+
+pub fn parse_operand(s: &str) -> Result<Operand, String> {
+    if s.is_empty() {
+        return Err("missing operand".to_string());
+    }
+    if s.starts_with('"') {
+        return parse_string(s).map(Operand::Str);
+    }
+    let upper = s.to_ascii_uppercase();
+    if upper.len() == 2 && upper.starts_with('V') {
+        if let Ok(r) = u8::from_str_radix(&upper[1..], 16) {
+            return Ok(Operand::Reg(r));
+        }
+    }
+    if KEYWORDS.contains(&upper.as_str()) {
+        return Ok(Operand::Keyword(upper));
+    }
+    if upper.starts_with("LONG ") {
+        return parse_expr(&s[5..]).map(Operand::Long);
+    }
+    parse_expr(s).map(Operand::Expr)
+}
+
+/// Split the first whitespace separated word
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(k) => (&s[..k], s[k..].trim()),
+        None => (s, ""),
+    }
+}
+
+pub fn parse_line(line: &str) -> Result<ParsedLine, String> {
+    let mut rest = strip_comment(line).trim();
+    let mut parsed = ParsedLine::default();
+
+    let (first, after) = split_word(rest);
+    if let Some(name) = first.strip_suffix(':') {
+        if !is_ident(name) {
+            return Err(format!("invalid label name '{}'", name));
+        }
+        parsed.label = Some(name.to_string());
+        rest = after;
+    }
+    if rest.is_empty() {
+        return Ok(parsed);
+    }
+
+    let (word, args) = split_word(rest);
+    let (second, value) = split_word(args);
+    if second.eq_ignore_ascii_case("equ") {
+        if !is_ident(word) {
+            return Err(format!("invalid constant name '{}'", word));
+        }
+        parsed.stmt = Some(Stmt::Equ(word.to_string(), parse_expr(value)?));
+        return Ok(parsed);
+    }
+
+    let operands = if args.is_empty() { Vec::new() } else { split_operands(args) };
+    let stmt = match word.to_ascii_lowercase().as_str() {
+        "db" => Stmt::Db(operands.iter().map(|s| parse_operand(s)).collect::<Result<_, _>>()?),
+        "dw" => Stmt::Dw(operands.iter().map(|s| parse_expr(s)).collect::<Result<_, _>>()?),
+        "sprite" => Stmt::Sprite(operands.iter().map(|s| parse_string(s)).collect::<Result<_, _>>()?),
+        "org" => Stmt::Org(parse_expr(args)?),
+        "include" => Stmt::Include(parse_string(args)?),
+        _ => Stmt::Insn {
+            mnemonic: word.to_ascii_uppercase(),
+            operands: operands.iter().map(|s| parse_operand(s)).collect::<Result<_, _>>()?,
+        },
+    };
+    parsed.stmt = Some(stmt);
+    Ok(parsed)
+}
@@ -0,0 +1,214 @@
+//
+// Assembler: instruction syntax, directives and diagnostics
+use std::collections::HashSet;
+use std::fs;
+use std::mem::discriminant;
+use std::path::PathBuf;
+
+use chip8::{Insn, Platform};
+use chip8_asm::{Assembler, Diagnostic, Program};
+
+fn assemble(platform: Platform, src: &str) -> Result<Program, Vec<Diagnostic>> {
+    Assembler::new(platform).assemble_str("test.asm", src)
+}
+
+fn rom(src: &str) -> Vec<u8> {
+    assemble(Platform::XoChip, src).unwrap().rom
+}
+
+/// Diagnostics formatted as `file:line: error: message`
+fn errors(platform: Platform, src: &str) -> Vec<String> {
+    assemble(platform, src).unwrap_err().iter().map(Diagnostic::to_string).collect()
+}
+
+/// Empty directory for the files of a test
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-asm-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn mnemonics_round_trip() {
+    // The listing syntax of every instruction assembles back to its opcode
+    let mut variants = HashSet::new();
+    for word in 0..=0xFFFFu16 {
+        let insn = match Insn::decode(word) {
+            Some(insn) => insn,
+            None => continue,
+        };
+        let text = insn.to_string();
+        assert_eq!(rom(&text), word.to_be_bytes(), "{}", text);
+        variants.insert(discriminant(&insn));
+    }
+    // All but the long load
+    assert_eq!(variants.len(), 49);
+
+    let long = Insn::LoadLongA(0xBEEF);
+    let bytes = rom(&long.to_string());
+    assert_eq!(bytes, [0xF0, 0x00, 0xBE, 0xEF]);
+    let decoded = Insn::decode_long(u16::from_be_bytes([bytes[0], bytes[1]]), u16::from_be_bytes([bytes[2], bytes[3]]));
+    assert_eq!(decoded, Some(long));
+}
+
+#[test]
+fn syntax() {
+    // Case-insensitive, with comments and the short shift forms
+    let src = "  ld v1, 0x2A ; comment\ncls\nShr V3\nshl v4, v5";
+    assert_eq!(rom(src), [0x61, 0x2A, 0x00, 0xE0, 0x83, 0x36, 0x84, 0x5E]);
+    // Numbers in binary and negative bytes
+    assert_eq!(rom("LD V0, 0b1010\nADD V1, -1"), [0x60, 0x0A, 0x71, 0xFF]);
+}
+
+#[test]
+fn labels() {
+    let program = assemble(
+        Platform::Chip8,
+        "start:  CALL sub\n\
+         loop:   JP loop\n\
+         sub:    LD I, data + 1\n\
+         \x20       RET\n\
+         data:   db 1, 2\n",
+    )
+    .unwrap();
+    // Forward and backward references
+    assert_eq!(program.rom, [0x22, 0x04, 0x12, 0x02, 0xA2, 0x09, 0x00, 0xEE, 1, 2]);
+    let labels: Vec<(&str, u16)> = program.labels.iter().map(|(n, a)| (n.as_str(), *a)).collect();
+    assert_eq!(labels, [("data", 0x208), ("loop", 0x202), ("start", 0x200), ("sub", 0x204)]);
+}
+
+#[test]
+fn equ() {
+    let src = "X equ Y + 1\n\
+               Y equ 4\n\
+               BASE equ table - 2\n\
+               \x20   LD V0, X\n\
+               \x20   LD I, BASE\n\
+               table: db X - Y";
+    assert_eq!(rom(src), [0x60, 0x05, 0xA2, 0x02, 1]);
+    // Constants are not labels
+    assert!(assemble(Platform::Chip8, src).unwrap().labels.keys().eq(["table"]));
+}
+
+#[test]
+fn org() {
+    let program = assemble(Platform::Chip8, "JP next\norg 0x208\nnext: CLS\nhere: org 0x20C\ndb 7").unwrap();
+    // The gap is filled with zeros
+    assert_eq!(program.rom, [0x12, 0x08, 0, 0, 0, 0, 0, 0, 0x00, 0xE0, 0, 0, 7]);
+    assert_eq!(program.labels["here"], 0x20C);
+
+    let mut asm = Assembler::new(Platform::Chip8);
+    asm.set_origin(0x600);
+    assert_eq!(asm.assemble_str("test.asm", "l: JP l").unwrap().rom, [0x16, 0x00]);
+}
+
+#[test]
+fn db_dw_sprite() {
+    assert_eq!(rom("db \"A;B, C\", 0, 0xFF, -128"), [b'A', b';', b'B', b',', b' ', b'C', 0, 0xFF, 0x80]);
+    assert_eq!(rom("l: dw 0x1234, l, -1"), [0x12, 0x34, 0x02, 0x00, 0xFF, 0xFF]);
+    assert_eq!(rom("sprite \"#..##..#\", \"XXXX....\""), [0x99, 0xF0]);
+    assert_eq!(rom("sprite \"################\", \"#..............#\""), [0xFF, 0xFF, 0x80, 0x01]);
+}
+
+#[test]
+fn include() {
+    let dir = test_dir("include");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("main.asm"), "CALL draw\ninclude \"lib/draw.asm\"\nJP end\nend: EXIT\n").unwrap();
+    // Relative to the including file
+    fs::write(dir.join("lib/draw.asm"), "draw: include \"sprite.asm\"\n").unwrap();
+    let program = Assembler::new(Platform::SuperChip).assemble_file(dir.join("main.asm"));
+    let draw = dir.join("lib/draw.asm").display().to_string();
+    assert_eq!(
+        program.unwrap_err().iter().map(Diagnostic::to_string).collect::<Vec<_>>(),
+        [
+            format!("{}:1: error: label not allowed on include", draw),
+            format!("{}:1: error: undefined symbol 'draw'", dir.join("main.asm").display()),
+        ]
+    );
+
+    fs::write(dir.join("lib/draw.asm"), "draw: LD I, sprite\nRET\ninclude \"sprite.asm\"\n").unwrap();
+    fs::write(dir.join("lib/sprite.asm"), "sprite: db 0x3C\n").unwrap();
+    let program = Assembler::new(Platform::SuperChip).assemble_file(dir.join("main.asm")).unwrap();
+    assert_eq!(program.rom, [0x22, 0x02, 0xA2, 0x06, 0x00, 0xEE, 0x3C, 0x12, 0x09, 0x00, 0xFD]);
+    assert_eq!(program.labels["sprite"], 0x206);
+
+    // Recursive includes are stopped
+    fs::write(dir.join("loop.asm"), "include \"loop.asm\"\n").unwrap();
+    let errors = Assembler::new(Platform::Chip8).assemble_file(dir.join("loop.asm")).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.starts_with("includes nested too deeply"), "{}", errors[0]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn diagnostics() {
+    // All the errors are reported, with their line
+    assert_eq!(
+        errors(
+            Platform::Chip8,
+            "start: JP nowhere\n\
+             start: CLS\n\
+             \x20      LD V0, 256\n\
+             \x20      DRW V0, V1\n\
+             \x20      FOO\n\
+             \x20      SCR\n\
+             \x20      LD I, LONG 0x1234\n\
+             \x20      db \"é\"\n\
+             A equ B\n\
+             B equ A\n\
+             \x20      LD V0, A",
+        ),
+        [
+            "test.asm:2: error: symbol 'start' is already defined",
+            "test.asm:1: error: undefined symbol 'nowhere'",
+            "test.asm:3: error: value 256 of '256' out of range [-128, 255]",
+            "test.asm:4: error: invalid operands for DRW",
+            "test.asm:5: error: unknown instruction 'FOO'",
+            "test.asm:6: error: 'SCR' requires the SuperChip platform",
+            "test.asm:7: error: 'LD I, LONG 0x1234' requires the XoChip platform",
+            "test.asm:8: error: non-ASCII string \"é\"",
+            "test.asm:11: error: recursive definition in 'B'",
+        ]
+    );
+
+    // Reported while reading the sources, before the other errors
+    let include = errors(Platform::Chip8, "JP nowhere\ninclude \"missing.asm\"");
+    assert_eq!(include.len(), 2);
+    assert!(include[0].starts_with("test.asm:2: error: cannot include 'missing.asm': "), "{}", include[0]);
+
+    // Syntax errors
+    assert_eq!(
+        errors(Platform::Chip8, "1abc: CLS\nLD V0, 2 +\ndb \"open"),
+        [
+            "test.asm:1: error: invalid label name '1abc'",
+            "test.asm:2: error: incomplete expression '2 +'",
+            "test.asm:3: error: invalid string \"open",
+        ]
+    );
+
+    // Addresses
+    assert_eq!(
+        errors(Platform::XoChip, "JP 0x1000\norg 0x100\ndb 1\norg 0xFFFF\ndw 0"),
+        [
+            "test.asm:1: error: value 4096 of '4096' out of range [0, 4095]",
+            "test.asm:3: error: address 0x100 is below the origin",
+            "test.asm:5: error: program does not fit in memory",
+        ]
+    );
+
+    let missing = std::env::temp_dir().join("chip8-asm-missing.asm");
+    let errors = Assembler::new(Platform::Chip8).assemble_file(&missing).unwrap_err();
+    assert_eq!((errors[0].file.as_str(), errors[0].line), (missing.display().to_string().as_str(), 0));
+}
+
+#[test]
+fn selftest_rom() {
+    // The program run by the emulator tests is up to date
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../chip8/tests/roms");
+    let program = Assembler::new(Platform::Chip8).assemble_file(dir.join("selftest.asm")).unwrap();
+    assert_eq!(program.rom, fs::read(dir.join("selftest.ch8")).unwrap());
+    assert_eq!(program.labels["fail"], 0x284);
+}
@@ -0,0 +1,566 @@
+//
+// Comparison of two emulator runs
+//
+// Two `Chip8Emulator` are run in lockstep (e.g. with different quirks), or
+// two traces are read side by side (e.g. written by two versions of the
+// emulator), until the first cycle where their state differs. The report
+// lists the differences along with the last executed instructions and the
+// disassembly around the diverging instruction.
+//
+// In lockstep, the registers are compared after every instruction, the
+// memory only after the instructions writing it and the framebuffer after
+// the instructions drawing: both emulators executed the same instructions
+// so far, any divergence shows up at the instruction causing it.
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::io::{self, BufRead};
+
+use chip8::cpu::{CpuError, CpuSnapshot, CpuStatus};
+use chip8::debug::InsnClass;
+use chip8::movie::{Movie, MoviePlayer};
+use chip8::trace::{TraceEvent, TraceHook, TraceRecord};
+use chip8::{Chip8Emulator, Insn, Platform, MEMORY_SIZE};
+
+/// Default number of instructions shown before the divergence
+pub const DEFAULT_CONTEXT: usize = 8;
+
+/// Instructions disassembled before and after the diverging one
+const LISTING_LINES: u16 = 4;
+
+/// Names of the compared runs, in reports
+const SIDES: [&str; 2] = ["A", "B"];
+
+/// A state difference, values of A then B
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Difference {
+    Status(CpuStatus, CpuStatus),
+    Error(Option<CpuError>, Option<CpuError>),
+    Pc(u16, u16),
+    Opcode(u32, u32),
+    V(u8, u8, u8),
+    I(u16, u16),
+    Sp(u16, u16),
+    Stack(usize, u16, u16),
+    /// First differing byte and number of differing bytes
+    Memory { addr: usize, a: u8, b: u8, count: usize },
+    Resolution((u32, u32), (u32, u32)),
+    /// First differing pixel and number of differing pixels
+    Framebuffer { x: u32, y: u32, a: u32, b: u32, count: usize },
+    /// One of the traces has no more records (0 for A, 1 for B)
+    TraceEnded(usize),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Status(a, b) => write!(f, "status: {:?} != {:?}", a, b),
+            Difference::Error(a, b) => {
+                let show = |e: &Option<CpuError>| e.as_ref().map_or("none".to_string(), |e| e.to_string());
+                write!(f, "error: {} != {}", show(a), show(b))
+            }
+            Difference::Pc(a, b) => write!(f, "PC: {:04X} != {:04X}", a, b),
+            Difference::Opcode(a, b) => write!(f, "opcode: {:04X} != {:04X}", a, b),
+            Difference::V(r, a, b) => write!(f, "V{:X}: {:02X} != {:02X}", r, a, b),
+            Difference::I(a, b) => write!(f, "I: {:04X} != {:04X}", a, b),
+            Difference::Sp(a, b) => write!(f, "SP: {} != {}", a, b),
+            Difference::Stack(k, a, b) => write!(f, "stack[{}]: {:04X} != {:04X}", k, a, b),
+            Difference::Memory { addr, a, b, count } => {
+                write!(f, "memory[{:04X}]: {:02X} != {:02X} ({} bytes differ)", addr, a, b, count)
+            }
+            Difference::Resolution(a, b) => write!(f, "resolution: {}x{} != {}x{}", a.0, a.1, b.0, b.1),
+            Difference::Framebuffer { x, y, a, b, count } => {
+                write!(f, "pixel ({}, {}): {:06X} != {:06X} ({} pixels differ)", x, y, a, b, count)
+            }
+            Difference::TraceEnded(side) => write!(f, "trace {} ended", SIDES[*side]),
+        }
+    }
+}
+
+/// An executed instruction
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Step {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u32,
+}
+
+impl Step {
+    pub fn insn(&self) -> Option<Insn> {
+        decode(self.opcode)
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<10}  {:04X}  {:<8}  ", self.cycle, self.pc, format_opcode(self.opcode))?;
+        match self.insn() {
+            Some(insn) => write!(f, "{}", insn),
+            None => write!(f, "???"),
+        }
+    }
+}
+
+fn format_opcode(opcode: u32) -> String {
+    if opcode > 0xFFFF {
+        format!("{:08X}", opcode)
+    } else {
+        format!("{:04X}", opcode)
+    }
+}
+
+fn decode(opcode: u32) -> Option<Insn> {
+    if opcode > 0xFFFF {
+        Insn::decode_long((opcode >> 16) as u16, opcode as u16)
+    } else {
+        Insn::decode(opcode as u16)
+    }
+}
+
+/// The last executed instructions
+#[derive(Debug, Clone)]
+pub struct History {
+    steps: VecDeque<Step>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            steps: VecDeque::with_capacity(capacity + 1),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.steps.push_back(step);
+        if self.steps.len() > self.capacity {
+            self.steps.pop_front();
+        }
+    }
+
+    pub fn last(&self) -> Option<&Step> {
+        self.steps.back()
+    }
+
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.steps.iter()
+    }
+}
+
+impl TraceHook for History {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.push(Step {
+            cycle: event.after.cycles,
+            pc: event.pc,
+            opcode: event.opcode,
+        });
+    }
+}
+
+/// First divergence between two runs
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Cycle of the diverging instruction
+    pub cycle: u64,
+    pub differences: Vec<Difference>,
+    /// Last instructions of A and B, the diverging one included
+    pub history: [Vec<Step>; 2],
+    /// Disassembly around the diverging instruction, when the memory is
+    /// known
+    pub listing: [Option<String>; 2],
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "first divergence at cycle {}:", self.cycle)?;
+        for d in self.differences.iter() {
+            writeln!(f, "    {}", d)?;
+        }
+        for (side, name) in SIDES.iter().enumerate() {
+            writeln!(f, "\n{}: last instructions", name)?;
+            for step in self.history[side].iter() {
+                writeln!(f, "    {}", step)?;
+            }
+            if let Some(listing) = &self.listing[side] {
+                writeln!(f, "\n{}: disassembly", name)?;
+                f.write_str(listing)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Diverged(Box<Divergence>),
+    /// No divergence in the given number of cycles, or traces of this
+    /// length
+    Identical(u64),
+    /// Both CPUs halted in the same state
+    Halted(u64),
+    /// Both CPUs failed with the same error
+    Failed(u64, CpuError),
+}
+
+/// Disassemble `lines` instructions before and after `pc`, marking it
+pub fn listing(memory: &[u8], pc: u16, platform: Platform, lines: u16) -> String {
+    let word = |addr: u16| -> Option<u16> {
+        let addr = addr as usize;
+        Some(u16::from_be_bytes([*memory.get(addr)?, *memory.get(addr + 1)?]))
+    };
+    let mut out = String::new();
+    let first = pc.saturating_sub(2 * lines);
+    let last = pc.saturating_add(2 * lines);
+    for addr in (first..=last).step_by(2) {
+        let w = match word(addr) {
+            Some(w) => w,
+            None => break,
+        };
+        let long = w == 0xF000 && platform >= Platform::XoChip;
+        let (opcode, insn) = match word(addr.wrapping_add(2)) {
+            Some(next) if long => (((w as u32) << 16) | next as u32, Insn::decode_long(w, next)),
+            _ => (w as u32, Insn::decode(w)),
+        };
+        let mark = if addr == pc { "=>" } else { "  " };
+        let _ = write!(out, "  {} {:04X}  {:<8}  ", mark, addr, format_opcode(opcode));
+        let _ = match insn {
+            Some(insn) => writeln!(out, "{}", insn),
+            None => writeln!(out, "???"),
+        };
+    }
+    out
+}
+
+/// Differences between the CPU states
+pub fn compare_cpus(a: &CpuSnapshot, b: &CpuSnapshot) -> Vec<Difference> {
+    let mut diffs = Vec::new();
+    if a.status != b.status {
+        diffs.push(Difference::Status(a.status, b.status));
+    }
+    if a.pc != b.pc {
+        diffs.push(Difference::Pc(a.pc, b.pc));
+    }
+    for (r, (x, y)) in a.v.iter().zip(b.v.iter()).enumerate() {
+        if x != y {
+            diffs.push(Difference::V(r as u8, *x, *y));
+        }
+    }
+    if a.i != b.i {
+        diffs.push(Difference::I(a.i, b.i));
+    }
+    if a.sp != b.sp {
+        diffs.push(Difference::Sp(a.sp, b.sp));
+    }
+    // Entries above the stack pointer are stale
+    let depth = a.sp.min(b.sp) as usize;
+    for (k, (x, y)) in a.stack.iter().zip(b.stack.iter()).enumerate().take(depth) {
+        if x != y {
+            diffs.push(Difference::Stack(k, *x, *y));
+        }
+    }
+    diffs
+}
+
+/// Difference between two memories, if any
+pub fn compare_memory(a: &[u8], b: &[u8]) -> Option<Difference> {
+    if a == b {
+        return None;
+    }
+    let mut diffs = a.iter().zip(b.iter()).enumerate().filter(|(_, (x, y))| x != y);
+    let (addr, (x, y)) = diffs.next()?;
+    Some(Difference::Memory {
+        addr,
+        a: *x,
+        b: *y,
+        count: 1 + diffs.count(),
+    })
+}
+
+/// Difference between the screens of two emulators, if any
+pub fn compare_screens(a: &Chip8Emulator, b: &Chip8Emulator) -> Option<Difference> {
+    let (sa, sb) = (&a.peripherals().screen, &b.peripherals().screen);
+    if sa.dims() != sb.dims() {
+        return Some(Difference::Resolution(sa.dims(), sb.dims()));
+    }
+    let (fa, fb) = (sa.data(), sb.data());
+    if fa == fb {
+        return None;
+    }
+    let (w, h) = sa.dims();
+    let mut diffs = fa.iter().zip(fb.iter()).enumerate().filter(|(_, (x, y))| x != y);
+    let (k, (x, y)) = diffs.next()?;
+    let row = k as u32 / w;
+    Some(Difference::Framebuffer {
+        x: k as u32 % w,
+        y: if sa.is_inverted_y() { h - 1 - row } else { row },
+        a: *x,
+        b: *y,
+        count: 1 + diffs.count(),
+    })
+}
+
+/// Differences between the complete states of two emulators
+pub fn compare_emulators(a: &Chip8Emulator, b: &Chip8Emulator) -> Vec<Difference> {
+    let mut diffs = compare_cpus(&a.cpu().snapshot(), &b.cpu().snapshot());
+    diffs.extend(compare_memory(&a.peripherals().memory, &b.peripherals().memory));
+    diffs.extend(compare_screens(a, b));
+    diffs
+}
+
+fn writes_memory(insn: &Insn) -> bool {
+    matches!(insn, Insn::StoreBCD(_) | Insn::StoreRegs(_) | Insn::SaveRange(_, _))
+}
+
+/// Runs two emulators one instruction at a time
+pub struct Lockstep {
+    emus: [Chip8Emulator; 2],
+    players: [Option<MoviePlayer>; 2],
+    history: [History; 2],
+}
+
+impl Lockstep {
+    pub fn new(a: Chip8Emulator, b: Chip8Emulator) -> Self {
+        Lockstep {
+            emus: [a, b],
+            players: [None, None],
+            history: [History::new(DEFAULT_CONTEXT), History::new(DEFAULT_CONTEXT)],
+        }
+    }
+
+    /// Feed the keypad of both emulators from a movie
+    pub fn set_movie(&mut self, movie: &Movie) {
+        self.players = [Some(MoviePlayer::new(movie.clone())), Some(MoviePlayer::new(movie.clone()))];
+    }
+
+    /// Number of instructions shown before the divergence
+    pub fn set_context(&mut self, n: usize) {
+        self.history = [History::new(n), History::new(n)];
+    }
+
+    pub fn emulators(&self) -> &[Chip8Emulator; 2] {
+        &self.emus
+    }
+
+    /// Execute one instruction on both emulators
+    fn step(&mut self, side: usize) -> Result<(), CpuError> {
+        let (emu, history) = (&mut self.emus[side], &mut self.history[side]);
+        match &mut self.players[side] {
+            Some(player) => player.tick_traced(emu, 1, history)?,
+            None => emu.tick_traced(1, history)?,
+        };
+        Ok(())
+    }
+
+    fn divergence(&self, differences: Vec<Difference>) -> Box<Divergence> {
+        let mut listings = [None, None];
+        for (side, emu) in self.emus.iter().enumerate() {
+            if let Some(step) = self.history[side].last() {
+                let memory = &emu.peripherals().memory;
+                listings[side] = Some(listing(memory, step.pc, emu.platform(), LISTING_LINES));
+            }
+        }
+        Box::new(Divergence {
+            cycle: self.emus[0].cycles(),
+            differences,
+            history: [
+                self.history[0].steps().copied().collect(),
+                self.history[1].steps().copied().collect(),
+            ],
+            listing: listings,
+        })
+    }
+
+    /// Run until the first divergence, for at most `max_cycles`
+    pub fn run(&mut self, max_cycles: u64) -> Outcome {
+        for _ in 0..max_cycles {
+            let results = [self.step(0), self.step(1)];
+            let cycle = self.emus[0].cycles();
+            match results {
+                [Ok(()), Ok(())] => {}
+                [Err(a), Err(b)] if a == b => return Outcome::Failed(cycle, a),
+                [a, b] => {
+                    let mut diffs = vec![Difference::Error(a.err(), b.err())];
+                    diffs.extend(compare_emulators(&self.emus[0], &self.emus[1]));
+                    return Outcome::Diverged(self.divergence(diffs));
+                }
+            }
+            let [a, b] = &self.emus;
+            let mut diffs = compare_cpus(&a.cpu().snapshot(), &b.cpu().snapshot());
+            let insn = self.history[0].last().and_then(|s| s.insn());
+            if !diffs.is_empty() {
+                // Complete the report
+                diffs.extend(compare_memory(&a.peripherals().memory, &b.peripherals().memory));
+                diffs.extend(compare_screens(a, b));
+            } else if insn.as_ref().is_some_and(writes_memory) {
+                diffs.extend(compare_memory(&a.peripherals().memory, &b.peripherals().memory));
+            } else if insn.as_ref().is_some_and(|i| matches!(i.class(), InsnClass::DrawSprite | InsnClass::Screen)) {
+                diffs.extend(compare_screens(a, b));
+            }
+            if !diffs.is_empty() {
+                return Outcome::Diverged(self.divergence(diffs));
+            }
+            if *a.cpu().status() == CpuStatus::Halted {
+                return Outcome::Halted(cycle);
+            }
+        }
+        Outcome::Identical(self.emus[0].cycles())
+    }
+}
+
+/// Differences between two trace records
+pub fn compare_records(a: &TraceRecord, b: &TraceRecord) -> Vec<Difference> {
+    let mut diffs = Vec::new();
+    if a.pc != b.pc {
+        diffs.push(Difference::Pc(a.pc, b.pc));
+    }
+    if a.opcode != b.opcode {
+        diffs.push(Difference::Opcode(a.opcode, b.opcode));
+    }
+    for (r, (x, y)) in a.v.iter().zip(b.v.iter()).enumerate() {
+        if x != y {
+            diffs.push(Difference::V(r as u8, *x, *y));
+        }
+    }
+    if a.i != b.i {
+        diffs.push(Difference::I(a.i, b.i));
+    }
+    if a.sp != b.sp {
+        diffs.push(Difference::Sp(a.sp as u16, b.sp as u16));
+    }
+    diffs
+}
+
+/// Compare two traces record by record. `rom` (loaded at 0x200) is used
+/// to disassemble around the divergence.
+pub fn diff_traces<A, B>(a: A, b: B, rom: Option<(&[u8], Platform)>, context: usize) -> io::Result<Outcome>
+where
+    A: Iterator<Item = io::Result<TraceRecord>>,
+    B: Iterator<Item = io::Result<TraceRecord>>,
+{
+    let mut history = [History::new(context), History::new(context)];
+    let mut count = 0;
+    let mut traces = (a, b);
+    loop {
+        let records = [traces.0.next().transpose()?, traces.1.next().transpose()?];
+        for (side, r) in records.iter().enumerate() {
+            if let Some(r) = r {
+                history[side].push(Step {
+                    cycle: r.cycle,
+                    pc: r.pc,
+                    opcode: r.opcode,
+                });
+            }
+        }
+        let diffs = match records {
+            [None, None] => return Ok(Outcome::Identical(count)),
+            [Some(a), Some(b)] => compare_records(&a, &b),
+            [None, _] => vec![Difference::TraceEnded(0)],
+            [_, None] => vec![Difference::TraceEnded(1)],
+        };
+        count += 1;
+        if diffs.is_empty() {
+            continue;
+        }
+        let mut listings = [None, None];
+        if let Some((rom, platform)) = rom {
+            let mut memory = vec![0; MEMORY_SIZE];
+            let len = rom.len().min(MEMORY_SIZE - 0x200);
+            memory[0x200..0x200 + len].copy_from_slice(&rom[..len]);
+            for (side, r) in records.iter().enumerate() {
+                if let Some(r) = r {
+                    listings[side] = Some(listing(&memory, r.pc, platform, LISTING_LINES));
+                }
+            }
+        }
+        let cycle = records.iter().flatten().map(|r| r.cycle).next().unwrap_or(count);
+        return Ok(Outcome::Diverged(Box::new(Divergence {
+            cycle,
+            differences: diffs,
+            history: [
+                history[0].steps().copied().collect(),
+                history[1].steps().copied().collect(),
+            ],
+            listing: listings,
+        })));
+    }
+}
+
+/// Parse a line of the compat trace format, numbering it as `cycle`
+pub fn parse_compat_line(line: &str, cycle: u64) -> Option<TraceRecord> {
+    let mut record = TraceRecord {
+        cycle,
+        pc: 0,
+        opcode: 0,
+        v: [0; 16],
+        i: 0,
+        sp: 0,
+    };
+    let mut seen = 0u32;
+    for field in line.split_whitespace() {
+        let mut parts = field.splitn(2, ':');
+        let (key, value) = (parts.next()?, parts.next()?);
+        let value = u32::from_str_radix(value, 16).ok()?;
+        match key {
+            "PC" => record.pc = value as u16,
+            "OP" => record.opcode = value,
+            "I" => record.i = value as u16,
+            "SP" => record.sp = value as u8,
+            _ => {
+                let r = key.strip_prefix('V').and_then(|r| u8::from_str_radix(r, 16).ok())?;
+                *record.v.get_mut(r as usize)? = value as u8;
+                seen |= 1 << r;
+            }
+        }
+        seen |= match key {
+            "PC" => 1 << 16,
+            "OP" => 1 << 17,
+            "I" => 1 << 18,
+            _ => 0,
+        };
+    }
+    // SP is optional
+    if seen == (1 << 19) - 1 {
+        Some(record)
+    } else {
+        None
+    }
+}
+
+/// Iterates over the records of a trace in the compat format
+pub struct CompatReader<R: BufRead> {
+    lines: io::Lines<R>,
+    line: u64,
+    count: u64,
+}
+
+impl<R: BufRead> CompatReader<R> {
+    pub fn new(input: R) -> Self {
+        CompatReader {
+            lines: input.lines(),
+            line: 0,
+            count: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CompatReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.count += 1;
+            return Some(parse_compat_line(&line, self.count).ok_or_else(|| {
+                let msg = format!("line {}: invalid trace record", self.line);
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            }));
+        }
+    }
+}
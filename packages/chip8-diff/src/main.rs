@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+use structopt::StructOpt;
+
+use chip8::movie::Movie;
+use chip8::trace::{TraceReader, TraceRecord, TRACE_MAGIC};
+use chip8::{Chip8Emulator, Platform, Quirks};
+use chip8_diff::{diff_traces, CompatReader, Lockstep, Outcome};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "chip8diff",
+    about = "Find the first divergence between two runs of a Chip 8 ROM",
+    after_help = "Runs FILE.ch8 on two emulators A and B in lockstep, B differing by the \
+                  --*-b options.\nWith --traces, compares two trace files (binary or compat \
+                  format) instead."
+)]
+pub struct CliOpts {
+    /// CPU frequency
+    #[structopt(short = "h", long = "cpuhz", default_value = "500")]
+    emu_hz: u32,
+
+    /// Instruction set to emulate (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform", default_value = "chip8")]
+    platform: Platform,
+
+    /// Override the platform quirks (default, vip, chip48, schip, xochip)
+    #[structopt(short = "q", long = "quirks")]
+    quirks: Option<Quirks>,
+
+    /// CPU frequency of B
+    #[structopt(long = "cpuhz-b")]
+    emu_hz_b: Option<u32>,
+
+    /// Instruction set of B
+    #[structopt(long = "platform-b")]
+    platform_b: Option<Platform>,
+
+    /// Quirks of B
+    #[structopt(long = "quirks-b")]
+    quirks_b: Option<Quirks>,
+
+    /// Seed of the random number generator of both emulators
+    #[structopt(long = "seed", default_value = "4886718345")]
+    seed: u64,
+
+    /// Feed the keypad from a movie recorded with `chip8-emu --record`, the
+    /// platform, quirks and CPU frequency of A are taken from the movie
+    #[structopt(long = "movie")]
+    movie: Option<String>,
+
+    /// Maximum number of cycles to run
+    #[structopt(short = "n", long = "cycles", default_value = "10000000")]
+    cycles: u64,
+
+    /// Number of instructions shown before the divergence
+    #[structopt(short = "c", long = "context", default_value = "8")]
+    context: usize,
+
+    /// Compare two trace files written by `--trace` (the ROM is optional,
+    /// for the disassembly)
+    #[structopt(long = "traces", number_of_values = 2, value_names = &["A", "B"])]
+    traces: Vec<String>,
+
+    #[structopt(name = "FILE.ch8", required_unless = "traces")]
+    rom_path: Option<String>,
+}
+
+type Records = Box<dyn Iterator<Item = io::Result<TraceRecord>>>;
+
+/// Open a trace in the binary or compat format
+fn open_trace(path: &str) -> Result<Records, String> {
+    let error = |e: io::Error| format!("{}: {}", path, e);
+    let mut input = BufReader::new(File::open(path).map_err(error)?);
+    let records: Records = if input.fill_buf().map_err(error)?.starts_with(&TRACE_MAGIC) {
+        Box::new(TraceReader::new(input).map_err(error)?)
+    } else {
+        Box::new(CompatReader::new(input))
+    };
+    // Name the file in the read errors
+    let path = path.to_string();
+    Ok(Box::new(records.map(move |r| r.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e))))))
+}
+
+fn make_emulator(rom: &[u8], hz: u32, platform: Platform, quirks: Option<Quirks>, seed: u64) -> Chip8Emulator {
+    let mut emu = Chip8Emulator::new(hz);
+    emu.set_platform(platform);
+    if let Some(quirks) = quirks {
+        emu.set_quirks(quirks);
+    }
+    emu.load_rom(rom);
+    emu.set_cpu_rng_seed(seed);
+    emu
+}
+
+fn run(opts: &CliOpts) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let rom = match &opts.rom_path {
+        Some(path) => {
+            let mut data = Vec::new();
+            File::open(path)
+                .and_then(|mut f| f.read_to_end(&mut data))
+                .map_err(|e| format!("{}: {}", path, e))?;
+            Some(data)
+        }
+        None => None,
+    };
+    if let [a, b] = &opts.traces[..] {
+        let (ta, tb) = (open_trace(a)?, open_trace(b)?);
+        let rom = rom.as_ref().map(|data| (&data[..], opts.platform));
+        return Ok(diff_traces(ta, tb, rom, opts.context)?);
+    }
+    let rom = rom.unwrap();
+    let (a, b, movie) = match &opts.movie {
+        Some(path) => {
+            let movie = Movie::from_bytes(&std::fs::read(path)?)?;
+            let a = movie.make_emulator(&rom)?;
+            let b = make_emulator(
+                &rom,
+                opts.emu_hz_b.unwrap_or(movie.cpu_hz),
+                opts.platform_b.unwrap_or(movie.platform),
+                // A platform change brings its own quirks
+                opts.quirks_b.or(match opts.platform_b {
+                    Some(_) => None,
+                    None => Some(movie.quirks),
+                }),
+                movie.rng_seed,
+            );
+            (a, b, Some(movie))
+        }
+        None => {
+            let a = make_emulator(&rom, opts.emu_hz, opts.platform, opts.quirks, opts.seed);
+            let b = make_emulator(
+                &rom,
+                opts.emu_hz_b.unwrap_or(opts.emu_hz),
+                opts.platform_b.unwrap_or(opts.platform),
+                opts.quirks_b.or(opts.quirks),
+                opts.seed,
+            );
+            (a, b, None)
+        }
+    };
+    let mut lockstep = Lockstep::new(a, b);
+    lockstep.set_context(opts.context);
+    if let Some(movie) = &movie {
+        lockstep.set_movie(movie);
+    }
+    Ok(lockstep.run(opts.cycles))
+}
+
+fn main() {
+    let opts = CliOpts::from_args();
+    // Exit codes follow diff: 0 if identical, 1 on a divergence, 2 on errors
+    match run(&opts) {
+        Ok(Outcome::Diverged(d)) => {
+            print!("{}", d);
+            std::process::exit(1);
+        }
+        Ok(Outcome::Identical(n)) => println!("no divergence in {} cycles", n),
+        Ok(Outcome::Halted(n)) => println!("no divergence, both halted after {} cycles", n),
+        Ok(Outcome::Failed(n, e)) => println!("no divergence, both failed after {} cycles: {}", n, e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//
+// Divergences between traces and between emulators in lockstep
+use std::io::ErrorKind;
+
+use chip8::trace::TraceRecord;
+use chip8::{Chip8Emulator, Quirks};
+use chip8_diff::{diff_traces, CompatReader, Difference, Divergence, Lockstep, Outcome};
+
+/// Compat trace of an XO-CHIP program written by the chip8 tests
+const COMPAT: &str = include_str!("../../chip8/tests/golden/trace-compat.txt");
+
+fn records(text: &str) -> Vec<TraceRecord> {
+    CompatReader::new(text.as_bytes()).collect::<Result<_, _>>().unwrap()
+}
+
+fn diff(a: &str, b: &str) -> Outcome {
+    diff_traces(CompatReader::new(a.as_bytes()), CompatReader::new(b.as_bytes()), None, 2).unwrap()
+}
+
+fn diverged(outcome: Outcome) -> Box<Divergence> {
+    match outcome {
+        Outcome::Diverged(d) => d,
+        other => panic!("no divergence: {:?}", other),
+    }
+}
+
+#[test]
+fn compat_reader() {
+    let records = records(COMPAT);
+    assert_eq!(records.len(), 6);
+    assert_eq!(records.iter().map(|r| r.cycle).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+    // The long load, on 32 bits
+    assert_eq!((records[1].pc, records[1].opcode), (0x202, 0xF000_1234));
+    assert_eq!((records[3].v[1], records[3].i, records[3].sp), (0x05, 0x1234, 1));
+
+    let e = CompatReader::new("PC:0200 OP:6105 V0:00\n".as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!((e.kind(), e.to_string()), (ErrorKind::InvalidData, "line 1: invalid trace record".to_string()));
+}
+
+#[test]
+fn traces() {
+    assert!(matches!(diff(COMPAT, COMPAT), Outcome::Identical(6)));
+
+    let changed = COMPAT.replacen("V1:0A", "V1:0B", 1);
+    let d = diverged(diff(COMPAT, &changed));
+    assert_eq!(d.cycle, 5);
+    assert_eq!(d.differences, [Difference::V(1, 0x0A, 0x0B)]);
+    // The last two instructions of each
+    assert_eq!(d.history[0].iter().map(|s| s.pc).collect::<Vec<_>>(), [0x20A, 0x20C]);
+
+    let shorter: String = COMPAT.lines().take(4).map(|l| format!("{}\n", l)).collect();
+    let d = diverged(diff(COMPAT, &shorter));
+    assert_eq!((d.cycle, d.differences.as_slice()), (5, &[Difference::TraceEnded(1)][..]));
+}
+
+#[test]
+fn lockstep() {
+    // LD V1, 1; LD V2, 4; SHR V1, V2; JP 0x206
+    let rom = [0x61, 0x01, 0x62, 0x04, 0x81, 0x26, 0x12, 0x06];
+    let emulator = |shift_uses_vy| {
+        let mut emu = Chip8Emulator::new(500);
+        emu.set_quirks(Quirks { shift_uses_vy, ..Quirks::legacy() });
+        emu.load_rom(&rom);
+        emu
+    };
+    let mut same = Lockstep::new(emulator(true), emulator(true));
+    assert!(matches!(same.run(100), Outcome::Identical(100)));
+
+    let mut lockstep = Lockstep::new(emulator(true), emulator(false));
+    let d = diverged(lockstep.run(100));
+    assert_eq!(d.cycle, 3);
+    // 4 >> 1 and 1 >> 1, with the shifted out bit in VF
+    assert_eq!(d.differences, [Difference::V(1, 2, 0), Difference::V(0xF, 0, 1)]);
+    assert!(d.to_string().starts_with("first divergence at cycle 3:\n    V1: 02 != 00\n"), "{}", d);
+}
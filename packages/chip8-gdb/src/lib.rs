@@ -0,0 +1,444 @@
+//
+// GDB remote serial protocol stub
+//
+// Serves a `Chip8Emulator` to a debugger speaking the GDB remote protocol
+// (https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html), over
+// TCP. Supported: registers and memory reads and writes, software and
+// hardware breakpoints, watchpoints (Z0 to Z4), continue, step and
+// interruption with Ctrl-C.
+//
+// Registers, in this order (multi-byte values are big-endian, like the
+// CHIP-8 memory):
+//
+//   number  name     size
+//   0-15    v0-vf    1
+//   16      i        2
+//   17      pc       2
+//   18      sp       1
+//   19-34   s0-s15   2     return stack
+//
+// The layout is also described by the `target.xml` feature document.
+use std::convert::TryInto;
+use std::io;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use chip8::cpu::{CpuError, CpuErrorKind, CpuStatus};
+use chip8::debug::{WatchKind, Watchpoint};
+use chip8::{Chip8Emulator, StopReason, CHIP8_PERIPH_HZ, MEMORY_SIZE};
+
+mod packet;
+
+use packet::{decode_hex_bytes, encode_hex_bytes, parse_hex, Connection, Incoming};
+
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_STACK: usize = 19;
+const REG_COUNT: usize = REG_STACK + 16;
+
+/// Stop reply sent when the target is interrupted (SIGINT)
+const SIGINT_REPLY: &str = "S02";
+/// Stop reply sent on breakpoints and steps (SIGTRAP)
+const SIGTRAP_REPLY: &str = "S05";
+
+pub struct GdbStub {
+    emu: Chip8Emulator,
+    realtime: bool,
+    /// Reply to `?`, updated on each stop
+    last_stop: String,
+}
+
+/// What to do after a packet
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    /// End the session, optionally replying first
+    Close(Option<String>),
+}
+
+impl GdbStub {
+    pub fn new(emu: Chip8Emulator) -> Self {
+        GdbStub {
+            emu,
+            realtime: true,
+            last_stop: SIGTRAP_REPLY.to_string(),
+        }
+    }
+
+    pub fn emulator(&self) -> &Chip8Emulator {
+        &self.emu
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Chip8Emulator {
+        &mut self.emu
+    }
+
+    /// Run at the CPU frequency when continuing (the default), or as fast
+    /// as possible
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.realtime = realtime;
+    }
+
+    /// Serve a client until it detaches, kills the target or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut conn = Connection::new(stream);
+        loop {
+            let packet = match conn.receive()? {
+                None => return Ok(()),
+                // Already stopped
+                Some(Incoming::Interrupt) => continue,
+                Some(Incoming::Packet(p)) => p,
+            };
+            match self.handle(&packet) {
+                Action::Reply(reply) => {
+                    conn.send(reply.as_bytes())?;
+                    if packet == b"QStartNoAckMode" {
+                        conn.no_ack = true;
+                    }
+                }
+                Action::Continue => {
+                    let reply = self.run(&mut conn)?;
+                    self.send_stop(&mut conn, reply)?;
+                }
+                Action::Step => {
+                    let reply = self.step();
+                    self.send_stop(&mut conn, reply)?;
+                }
+                Action::Close(reply) => {
+                    if let Some(reply) = reply {
+                        conn.send(reply.as_bytes())?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn send_stop(&mut self, conn: &mut Connection, reply: String) -> io::Result<()> {
+        conn.send(reply.as_bytes())?;
+        self.last_stop = reply;
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Action {
+        let (cmd, args) = match packet.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => return Action::Reply(String::new()),
+        };
+        let reply = match cmd {
+            b'?' => self.last_stop.clone(),
+            b'g' => encode_hex_bytes(&self.read_registers()),
+            b'G' => ok_or_error(decode_hex_bytes(args).and_then(|regs| self.write_registers(&regs))),
+            b'p' => match parse_hex(args).and_then(|n| self.read_register(n as usize)) {
+                Some(value) => encode_hex_bytes(&value),
+                None => error(),
+            },
+            b'P' => ok_or_error(self.write_register_packet(args)),
+            b'm' => match parse_addr_len(args).and_then(|(addr, len)| self.read_memory(addr, len)) {
+                Some(data) => encode_hex_bytes(data),
+                None => error(),
+            },
+            b'M' => ok_or_error(self.write_memory_packet(args)),
+            b'Z' | b'z' => match self.breakpoint_packet(args, cmd == b'Z') {
+                Some(ok) => ok_or_error(ok),
+                // Unsupported type
+                None => String::new(),
+            },
+            b'c' | b's' => {
+                if !args.is_empty() {
+                    match parse_hex(args) {
+                        Some(addr) => self.emu.cpu_mut().set_pc(addr as u16),
+                        None => return Action::Reply(error()),
+                    }
+                }
+                return if cmd == b'c' { Action::Continue } else { Action::Step };
+            }
+            b'v' => return self.v_packet(args),
+            b'q' | b'Q' => self.query(packet),
+            b'H' => "OK".to_string(),
+            b'T' => "OK".to_string(),
+            b'D' => return Action::Close(Some("OK".to_string())),
+            b'k' => return Action::Close(None),
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn v_packet(&mut self, args: &[u8]) -> Action {
+        match args {
+            b"Cont?" => Action::Reply("vCont;c;C;s;S".to_string()),
+            // A single thread: only the first action matters
+            _ if args.starts_with(b"Cont;c") || args.starts_with(b"Cont;C") => Action::Continue,
+            _ if args.starts_with(b"Cont;s") || args.starts_with(b"Cont;S") => Action::Step,
+            _ => Action::Reply(String::new()),
+        }
+    }
+
+    fn query(&self, packet: &[u8]) -> String {
+        let packet = String::from_utf8_lossy(packet);
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+;swbreak+;hwbreak+".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let (offset, len) = match parse_addr_len(args.as_bytes()) {
+                Some(v) => v,
+                None => return error(),
+            };
+            let xml = target_xml();
+            let start = offset.min(xml.len());
+            let end = (start + len).min(xml.len());
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &xml[start..end]);
+        }
+        match &*packet {
+            "QStartNoAckMode" => "OK",
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            "qOffsets" => "Text=0;Data=0;Bss=0",
+            _ => "",
+        }
+        .to_string()
+    }
+
+    fn read_register(&self, n: usize) -> Option<Vec<u8>> {
+        let cpu = self.emu.cpu();
+        let value = match n {
+            0..=15 => vec![cpu.gprs()[n]],
+            REG_I => cpu.reg_i().to_be_bytes().to_vec(),
+            REG_PC => cpu.pc().to_be_bytes().to_vec(),
+            REG_SP => vec![cpu.sp() as u8],
+            REG_STACK..=34 => cpu.stack()[n - REG_STACK].to_be_bytes().to_vec(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn read_registers(&self) -> Vec<u8> {
+        (0..REG_COUNT).flat_map(|n| self.read_register(n).unwrap()).collect()
+    }
+
+    /// Write a register from its encoded value
+    fn write_register(&mut self, n: usize, value: &[u8]) -> Option<()> {
+        let word = |v: &[u8]| -> Option<u16> { Some(u16::from_be_bytes(v.try_into().ok()?)) };
+        let cpu = self.emu.cpu_mut();
+        match n {
+            0..=15 if value.len() == 1 => cpu.set_gpr(n as u8, value[0]).ok()?,
+            REG_I => cpu.set_reg_i(word(value)?),
+            REG_PC => cpu.set_pc(word(value)?),
+            REG_SP if value.len() == 1 => cpu.set_sp(value[0] as u16).ok()?,
+            REG_STACK..=34 => cpu.stack_mut()[n - REG_STACK] = word(value)?,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Write all the registers, or none if a value is invalid
+    fn write_registers(&mut self, data: &[u8]) -> Option<()> {
+        if data.len() != self.read_registers().len() {
+            return None;
+        }
+        let saved = self.emu.cpu().snapshot();
+        let mut offset = 0;
+        for n in 0..REG_COUNT {
+            let size = self.read_register(n)?.len();
+            if self.write_register(n, &data[offset..offset + size]).is_none() {
+                self.emu.cpu_mut().restore(&saved).unwrap();
+                return None;
+            }
+            offset += size;
+        }
+        Some(())
+    }
+
+    /// `P n=value`
+    fn write_register_packet(&mut self, args: &[u8]) -> Option<()> {
+        let eq = args.iter().position(|b| *b == b'=')?;
+        let n = parse_hex(&args[..eq])? as usize;
+        let value = decode_hex_bytes(&args[eq + 1..])?;
+        self.write_register(n, &value)
+    }
+
+    /// Memory addressable by the platform
+    fn memory_size(&self) -> usize {
+        self.emu.cpu().platform().memory_size()
+    }
+
+    /// Memory from `addr`, truncated at the end of the address space
+    fn read_memory(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        let size = self.memory_size();
+        if addr >= size {
+            return None;
+        }
+        Some(&self.emu.peripherals().memory[addr..(addr + len).min(size)])
+    }
+
+    /// `M addr,len:data`
+    fn write_memory_packet(&mut self, args: &[u8]) -> Option<()> {
+        let colon = args.iter().position(|b| *b == b':')?;
+        let (addr, len) = parse_addr_len(&args[..colon])?;
+        let data = decode_hex_bytes(&args[colon + 1..])?;
+        if data.len() != len || addr + len > self.memory_size() {
+            return None;
+        }
+        self.emu.peripherals_mut().memory[addr..addr + len].copy_from_slice(&data);
+        Some(())
+    }
+
+    /// `Z type,addr,kind` and `z type,addr,kind`. Returns None for
+    /// unsupported types.
+    fn breakpoint_packet(&mut self, args: &[u8], insert: bool) -> Option<Option<()>> {
+        let mut fields = args.split(|b| *b == b',');
+        let kind = match fields.next()? {
+            b"0" | b"1" => None,
+            b"2" => Some(WatchKind::Write),
+            b"3" => Some(WatchKind::Read),
+            b"4" => Some(WatchKind::Access),
+            _ => return None,
+        };
+        let size = self.memory_size() as u64;
+        let parsed = (|| {
+            let addr = parse_hex(fields.next()?)?;
+            let len = parse_hex(fields.next()?.split(|b| *b == b';').next()?)?;
+            if addr >= size || len > size {
+                return None;
+            }
+            Some((addr as u16, len as u16))
+        })();
+        let (addr, len) = match parsed {
+            Some(v) => v,
+            None => return Some(None),
+        };
+        let debugger = self.emu.debugger_mut();
+        let done = match kind {
+            None if insert => debugger.add_breakpoint(addr),
+            None => {
+                debugger.remove_breakpoint(addr);
+                true
+            }
+            Some(kind) => {
+                let wp = Watchpoint { addr, len, kind };
+                if insert {
+                    debugger.add_watchpoint(wp)
+                } else {
+                    debugger.remove_watchpoint(&wp);
+                    true
+                }
+            }
+        };
+        Some(if done { Some(()) } else { None })
+    }
+
+    fn is_halted(&self) -> bool {
+        *self.emu.cpu().status() == CpuStatus::Halted
+    }
+
+    /// Execute until a stop condition, or an interruption from the client
+    fn run(&mut self, conn: &mut Connection) -> io::Result<String> {
+        let frame = Duration::from_secs(1) / CHIP8_PERIPH_HZ;
+        let mut deadline = Instant::now();
+        self.emu.resume();
+        loop {
+            if self.is_halted() {
+                return Ok("W00".to_string());
+            }
+            // Whole frames as in the frontends, following the timing model
+            match self.emu.run_frame() {
+                Ok(f) if f.stop == StopReason::Completed => {}
+                r => return Ok(self.stop_reply(r.map(|f| f.stop))),
+            }
+            if conn.poll_interrupt()? {
+                return Ok(SIGINT_REPLY.to_string());
+            }
+            if self.realtime {
+                deadline += frame;
+                let now = Instant::now();
+                if deadline > now {
+                    std::thread::sleep(deadline - now);
+                } else {
+                    deadline = now;
+                }
+            }
+        }
+    }
+
+    fn step(&mut self) -> String {
+        if self.is_halted() {
+            return "W00".to_string();
+        }
+        self.emu.step_into();
+        let r = self.emu.tick(1);
+        if self.is_halted() {
+            return "W00".to_string();
+        }
+        self.stop_reply(r)
+    }
+
+    fn stop_reply(&self, r: Result<StopReason, CpuError>) -> String {
+        match r {
+            Ok(StopReason::Breakpoint(_)) => "T05swbreak:;".to_string(),
+            Ok(StopReason::Watchpoint { addr, write, .. }) => {
+                let access = self.emu.debugger().watchpoints().any(|wp| {
+                    wp.kind == WatchKind::Access && wp.addr <= addr && (addr as u32) < wp.addr as u32 + wp.len as u32
+                });
+                let name = match (access, write) {
+                    (true, _) => "awatch",
+                    (false, true) => "watch",
+                    (false, false) => "rwatch",
+                };
+                format!("T05{}:{:x};", name, addr)
+            }
+            Ok(_) => SIGTRAP_REPLY.to_string(),
+            // SIGSEGV
+            Err(e) if matches!(e.kind, CpuErrorKind::MemoryError { .. }) => "S0b".to_string(),
+            // SIGILL
+            Err(_) => "S04".to_string(),
+        }
+    }
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn ok_or_error(r: Option<()>) -> String {
+    match r {
+        Some(()) => "OK".to_string(),
+        None => error(),
+    }
+}
+
+/// `addr,len`, both at most `MEMORY_SIZE` so that their sum cannot overflow
+fn parse_addr_len(args: &[u8]) -> Option<(usize, usize)> {
+    let comma = args.iter().position(|b| *b == b',')?;
+    let addr = parse_hex(&args[..comma])?;
+    let len = parse_hex(&args[comma + 1..])?;
+    if addr > MEMORY_SIZE as u64 || len > MEMORY_SIZE as u64 {
+        return None;
+    }
+    Some((addr as usize, len as usize))
+}
+
+/// Register description for the client
+pub fn target_xml() -> String {
+    let mut regs = String::new();
+    for k in 0..16 {
+        regs += &format!("    <reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>\n", k);
+    }
+    regs += "    <reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n";
+    regs += "    <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n";
+    regs += "    <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\n";
+    for k in 0..16 {
+        regs += &format!("    <reg name=\"s{}\" bitsize=\"16\" type=\"code_ptr\" group=\"stack\"/>\n", k);
+    }
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  <feature name=\"org.chip8.cpu\">\n{}  </feature>\n</target>\n",
+        regs
+    )
+}
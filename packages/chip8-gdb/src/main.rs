@@ -0,0 +1,65 @@
+use std::net::TcpListener;
+
+use structopt::StructOpt;
+
+use chip8::{Chip8Emulator, Platform, Quirks};
+use chip8_gdb::GdbStub;
+
+const DEFAULT_CPU_HZ: u32 = 500;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chip8gdb", about = "GDB remote protocol server for Chip 8 ROMs")]
+pub struct CliOpts {
+    /// CPU frequency
+    #[structopt(short = "h", long = "cpuhz", default_value = "500")]
+    emu_hz: u32,
+
+    /// Instruction set to emulate (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform", default_value = "chip8")]
+    platform: Platform,
+
+    /// Override the platform quirks (default, vip, chip48, schip, xochip)
+    #[structopt(short = "q", long = "quirks")]
+    quirks: Option<Quirks>,
+
+    /// TCP port, on localhost
+    #[structopt(long = "port", default_value = "1234")]
+    port: u16,
+
+    /// Run as fast as possible instead of the CPU frequency
+    #[structopt(long = "fast")]
+    fast: bool,
+
+    #[structopt(name = "FILE.ch8")]
+    rom_path: String,
+}
+
+fn run(opts: &CliOpts) -> Result<(), Box<dyn std::error::Error>> {
+    let rom = std::fs::read(&opts.rom_path)?;
+    let mut emu = Chip8Emulator::new(DEFAULT_CPU_HZ);
+    emu.set_platform(opts.platform);
+    if let Some(quirks) = opts.quirks {
+        emu.set_quirks(quirks);
+    }
+    emu.set_cpu_hz(opts.emu_hz);
+    emu.load_rom(&rom);
+    emu.set_cpu_rng_seed(0x1234_56789);
+
+    let listener = TcpListener::bind(("127.0.0.1", opts.port))?;
+    println!("info: waiting for a debugger on 127.0.0.1:{}", opts.port);
+    let (stream, addr) = listener.accept()?;
+    println!("info: connection from {}", addr);
+    let mut stub = GdbStub::new(emu);
+    stub.set_realtime(!opts.fast);
+    stub.serve(stream)?;
+    println!("info: debugger disconnected");
+    Ok(())
+}
+
+fn main() {
+    let opts = CliOpts::from_args();
+    if let Err(e) = run(&opts) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,148 @@
+//
+// Framing of the GDB remote serial protocol
+//
+// Packets are sent as `$<data>#<checksum>`, the checksum being the sum of
+// the data bytes modulo 256 in two hex digits. Each packet is acknowledged
+// with `+` (or `-` to request a retransmission), unless the client switched
+// to the no-ack mode. A lone 0x03 byte interrupts the target.
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+pub const INTERRUPT: u8 = 0x03;
+
+pub enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    pending: VecDeque<u8>,
+    pub no_ack: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            pending: VecDeque::new(),
+            no_ack: false,
+        }
+    }
+
+    /// Blocking read of a byte, None at the end of the stream
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            let mut buf = [0; 4096];
+            let n = self.stream.read(&mut buf)?;
+            self.pending.extend(&buf[..n]);
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /// Wait for the next packet or interrupt, None once the client is gone
+    pub fn receive(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+                Some(b'$') => {}
+                // Acknowledgements, and noise
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut sum = [0; 2];
+            for b in sum.iter_mut() {
+                *b = match self.read_byte()? {
+                    Some(b) => b,
+                    None => return Ok(None),
+                };
+            }
+            if self.no_ack {
+                return Ok(Some(Incoming::Packet(data)));
+            }
+            let valid = parse_hex(&sum) == Some(checksum(&data) as u64);
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            if valid {
+                return Ok(Some(Incoming::Packet(data)));
+            }
+        }
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(format!("#{:02x}", checksum(data)).as_bytes());
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // Wait for the acknowledgement, retransmit on '-'
+            loop {
+                match self.read_byte()? {
+                    Some(b'+') => return Ok(()),
+                    Some(b'-') => break,
+                    None => return Err(ErrorKind::UnexpectedEof.into()),
+                    Some(_) => continue,
+                }
+            }
+        }
+    }
+
+    /// Non-blocking check for an interrupt request from the client. Other
+    /// incoming bytes are kept for `receive`.
+    pub fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let r = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => self.pending.extend(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        r?;
+        match self.pending.iter().position(|b| *b == INTERRUPT) {
+            Some(k) => {
+                self.pending.remove(k);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+pub fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    let s = std::str::from_utf8(s).ok()?;
+    u64::from_str_radix(s, 16).ok()
+}
+
+pub fn decode_hex_bytes(s: &[u8]) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.chunks(2).map(|c| parse_hex(c).map(|v| v as u8)).collect()
+}
+
+pub fn encode_hex_bytes(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
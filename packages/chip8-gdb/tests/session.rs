@@ -0,0 +1,187 @@
+//
+// Sessions with a client over TCP, packet by packet
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chip8::{Chip8Emulator, Platform};
+use chip8_gdb::GdbStub;
+
+/// Store V0 at 0x300, then count in V1 forever, the carries in V2
+const ROM: &[u8] = &[
+    0x60, 0x05, // 0x200: LD V0, 5
+    0xA3, 0x00, // 0x202: LD I, 0x300
+    0xF0, 0x55, // 0x204: LD [I], V0
+    0x71, 0x01, // 0x206: ADD V1, 1
+    0x12, 0x0A, // 0x208: JP 0x20A
+    0x41, 0x00, // 0x20A: SNE V1, 0
+    0x72, 0x01, // 0x20C: ADD V2, 1
+    0x12, 0x06, // 0x20E: JP 0x206
+];
+
+struct Client {
+    stream: TcpStream,
+    server: JoinHandle<()>,
+}
+
+impl Client {
+    fn connect(platform: Platform) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut emu = Chip8Emulator::new(500);
+            emu.set_platform(platform);
+            emu.load_rom(ROM);
+            let mut stub = GdbStub::new(emu);
+            stub.set_realtime(false);
+            let (stream, _) = listener.accept().unwrap();
+            stub.serve(stream).unwrap();
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Client { stream, server }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut b = [0];
+        self.stream.read_exact(&mut b).unwrap();
+        b[0]
+    }
+
+    /// Send a packet, returns the reply
+    fn send(&mut self, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(self.read_byte(), b'+', "{}", data);
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                b => reply.push(b),
+            }
+        }
+        let sum = [self.read_byte(), self.read_byte()];
+        let expected = reply.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(std::str::from_utf8(&sum).unwrap(), format!("{:02x}", expected));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn close(mut self) {
+        write!(self.stream, "$k#6b").unwrap();
+        assert_eq!(self.read_byte(), b'+');
+        self.server.join().unwrap();
+    }
+}
+
+#[test]
+fn registers() {
+    let mut c = Client::connect(Platform::Chip8);
+    assert_eq!(c.send("?"), "S05");
+    // V0-VF, I, PC, SP and the stack
+    let regs = c.send("g");
+    assert_eq!(regs, format!("{}0000020000{}", "00".repeat(16), "0000".repeat(16)));
+
+    assert_eq!(c.send("p11"), "0200");
+    assert_eq!(c.send("P1=2a"), "OK");
+    assert_eq!(c.send("P10=0abc"), "OK");
+    assert_eq!(c.send("p1"), "2a");
+    assert_eq!(c.send("p10"), "0abc");
+    assert_eq!(c.send("p23"), "E01");
+    assert_eq!(c.send("P0=1234"), "E01");
+    assert_eq!(c.send("P12=ff"), "E01");
+    assert_eq!(c.send("Pzz=00"), "E01");
+
+    // All at once, or none on error
+    let regs = format!("{}0123020202{}", "11".repeat(16), "0000".repeat(16));
+    assert_eq!(c.send(&format!("G{}", regs)), "OK");
+    assert_eq!(c.send("g"), regs);
+    assert_eq!(c.send(&format!("G{}", &regs[2..])), "E01");
+    let bad_sp = format!("{}0123020290{}", "22".repeat(16), "0000".repeat(16));
+    assert_eq!(c.send(&format!("G{}", bad_sp)), "E01");
+    assert_eq!(c.send("g"), regs);
+    c.close();
+}
+
+#[test]
+fn memory() {
+    let mut c = Client::connect(Platform::Chip8);
+    assert_eq!(c.send("m200,4"), "6005a300");
+    assert_eq!(c.send("M300,2:abcd"), "OK");
+    assert_eq!(c.send("m2ff,4"), "00abcd00");
+    // Truncated at the end of the 4 KiB of the platform
+    assert_eq!(c.send("mffe,10"), "0000");
+    assert_eq!(c.send("m1000,1"), "E01");
+    assert_eq!(c.send("M1000,1:00"), "E01");
+    assert_eq!(c.send("Mfff,2:0000"), "E01");
+    // Lengths and addresses which would overflow
+    assert_eq!(c.send("m1,ffffffffffffffff"), "E01");
+    assert_eq!(c.send("mffffffffffffffff,1"), "E01");
+    assert_eq!(c.send("M1,ffffffffffffffff:00"), "E01");
+    assert_eq!(c.send("Mffffffffffffffff,1:00"), "E01");
+    // Malformed
+    assert_eq!(c.send("M300,2:ab"), "E01");
+    assert_eq!(c.send("M300,1:xy"), "E01");
+    assert_eq!(c.send("m300"), "E01");
+    assert_eq!(c.send("m300,2"), "abcd");
+    c.close();
+
+    // XO-CHIP addresses 64 KiB
+    let mut c = Client::connect(Platform::XoChip);
+    assert_eq!(c.send("Mfffe,2:1234"), "OK");
+    assert_eq!(c.send("mfffe,4"), "1234");
+    assert_eq!(c.send("m10000,1"), "E01");
+    c.close();
+}
+
+#[test]
+fn execution() {
+    let mut c = Client::connect(Platform::Chip8);
+    assert_eq!(c.send("s"), "S05");
+    assert_eq!(c.send("p11"), "0202");
+
+    // Watchpoint on the store, stopped after it
+    assert_eq!(c.send("Z2,300,1"), "OK");
+    assert_eq!(c.send("c"), "T05watch:300;");
+    assert_eq!(c.send("p11"), "0206");
+    assert_eq!(c.send("m300,1"), "05");
+    assert_eq!(c.send("z2,300,1"), "OK");
+
+    assert_eq!(c.send("Z0,208,2"), "OK");
+    assert_eq!(c.send("c"), "T05swbreak:;");
+    assert_eq!(c.send("p11"), "0208");
+    assert_eq!(c.send("?"), "T05swbreak:;");
+    // Once per loop
+    assert_eq!(c.send("c"), "T05swbreak:;");
+    assert_eq!(c.send("p1"), "02");
+    assert_eq!(c.send("z0,208,2"), "OK");
+
+    // Out of range, and unsupported types
+    assert_eq!(c.send("Z0,1000,2"), "E01");
+    assert_eq!(c.send("Z2,300,ffffffffffffffff"), "E01");
+    assert_eq!(c.send("Z9,300,1"), "");
+
+    // Runs until interrupted
+    write!(c.stream, "$c#63").unwrap();
+    assert_eq!(c.read_byte(), b'+');
+    thread::sleep(Duration::from_millis(50));
+    c.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(c.reply(), "S02");
+    assert_eq!(c.send("?"), "S02");
+    let pc = c.send("p11");
+    assert!(["0206", "0208", "020a", "020c", "020e"].contains(&pc.as_str()), "{}", pc);
+    // V1 went on counting
+    assert_ne!((c.send("p1"), c.send("p2")), ("02".to_string(), "00".to_string()));
+
+    // Resuming at another address
+    assert_eq!(c.send("Z0,204,2"), "OK");
+    assert_eq!(c.send("c200"), "T05swbreak:;");
+    assert_eq!(c.send("p11"), "0204");
+    c.close();
+}
@@ -0,0 +1,119 @@
+//
+// Evaluation of `:calc` expressions
+//
+// Like in Octo, binary operators have no precedence and are evaluated right
+// to left: `2 * 3 + 1` is `2 * (3 + 1)`. Parentheses must be separated by
+// whitespace, as any other token.
+//
+
+/// Evaluate the expression in `tokens`, `lookup` resolves identifiers
+pub fn eval<F>(tokens: &[&str], lookup: &F) -> Result<f64, String>
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    let mut pos = 0;
+    let v = expr(tokens, &mut pos, lookup)?;
+    match tokens.get(pos) {
+        None => Ok(v),
+        Some(t) => Err(format!("unexpected '{}' in expression", t)),
+    }
+}
+
+fn expr<F>(tokens: &[&str], pos: &mut usize, lookup: &F) -> Result<f64, String>
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    let lhs = term(tokens, pos, lookup)?;
+    let op = match tokens.get(*pos) {
+        Some(&op) if is_binary(op) => op,
+        _ => return Ok(lhs),
+    };
+    *pos += 1;
+    let rhs = expr(tokens, pos, lookup)?;
+    Ok(binary(op, lhs, rhs))
+}
+
+fn term<F>(tokens: &[&str], pos: &mut usize, lookup: &F) -> Result<f64, String>
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    let t = match tokens.get(*pos) {
+        Some(&t) => t,
+        None => return Err("incomplete expression".to_string()),
+    };
+    *pos += 1;
+    if t == "(" {
+        let v = expr(tokens, pos, lookup)?;
+        if tokens.get(*pos) != Some(&")") {
+            return Err("missing ')' in expression".to_string());
+        }
+        *pos += 1;
+        return Ok(v);
+    }
+    if let Some(f) = unary(t) {
+        return Ok(f(term(tokens, pos, lookup)?));
+    }
+    if let Some(n) = crate::lexer::parse_number(t) {
+        return Ok(n as f64);
+    }
+    match t {
+        "PI" => Ok(std::f64::consts::PI),
+        "E" => Ok(std::f64::consts::E),
+        _ => lookup(t).ok_or_else(|| format!("undefined name '{}' in expression", t)),
+    }
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(
+        op,
+        "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "pow" | "min" | "max" | "<" | ">" | "<="
+            | ">=" | "==" | "!="
+    )
+}
+
+fn binary(op: &str, a: f64, b: f64) -> f64 {
+    let int = |f: fn(i64, i64) -> i64| f(a as i64, b as i64) as f64;
+    let cmp = |c: bool| if c { 1.0 } else { 0.0 };
+    match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        "&" => int(|x, y| x & y),
+        "|" => int(|x, y| x | y),
+        "^" => int(|x, y| x ^ y),
+        "<<" => int(|x, y| x.wrapping_shl(y as u32)),
+        ">>" => int(|x, y| x.wrapping_shr(y as u32)),
+        "pow" => a.powf(b),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "<" => cmp(a < b),
+        ">" => cmp(a > b),
+        "<=" => cmp(a <= b),
+        ">=" => cmp(a >= b),
+        "==" => cmp(a == b),
+        "!=" => cmp(a != b),
+        _ => unreachable!(),
+    }
+}
+
+fn unary(op: &str) -> Option<fn(f64) -> f64> {
+    let f: fn(f64) -> f64 = match op {
+        "-" => |x| -x,
+        "~" => |x| !(x as i64) as f64,
+        "!" => |x| if x == 0.0 { 1.0 } else { 0.0 },
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "exp" => f64::exp,
+        "log" => f64::ln,
+        "abs" => f64::abs,
+        "sqrt" => f64::sqrt,
+        "sign" => f64::signum,
+        "ceil" => f64::ceil,
+        "floor" => f64::floor,
+        _ => return None,
+    };
+    Some(f)
+}
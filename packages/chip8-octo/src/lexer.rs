@@ -0,0 +1,56 @@
+//
+// Octo tokens are separated by whitespace, comments start with `#` and run
+// to the end of the line.
+//
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+pub fn tokenize(src: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (k, line) in src.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(c) => &line[..c],
+            None => line,
+        };
+        for word in code.split_whitespace() {
+            tokens.push(Token {
+                text: word.to_string(),
+                line: k + 1,
+            });
+        }
+    }
+    tokens
+}
+
+/// Parse a numeric literal: decimal, `0x` hexadecimal or `0b` binary,
+/// optionally negative.
+pub fn parse_number(s: &str) -> Option<i64> {
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, s),
+    };
+    let v = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if neg { -v } else { v })
+}
+
+/// Parse a register name (`v0`-`vf`, case-insensitive)
+pub fn parse_register(s: &str) -> Option<u8> {
+    let b = s.as_bytes();
+    if b.len() == 2 && (b[0] == b'v' || b[0] == b'V') {
+        (b[1] as char).to_digit(16).map(|r| r as u8)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,742 @@
+//
+// Compiler for the Octo language
+//
+// * https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md
+//
+// Statements are compiled in a single pass to `Insn`s, which are encoded
+// directly in a memory image. References to labels defined later in the
+// source are recorded and patched once the whole program is compiled.
+//
+// Supported directives: `:`, `:alias`, `:const`, `:unpack`, `:next`, `:org`,
+// `:macro`, `:calc`, `:byte`, `:call` and `:breakpoint` (ignored). Control
+// flow: `if ... then`, `if ... begin ... else ... end` and
+// `loop ... while ... again`. Like Octo, the `<`, `>`, `<=` and `>=`
+// comparisons are expanded to a subtraction in VF (or the register aliased
+// as `compare-temp`) followed by a skip on the flag.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use chip8::{Insn, Platform, MEMORY_SIZE};
+
+mod calc;
+mod lexer;
+
+use lexer::{parse_number, parse_register, Token};
+
+/// Load address of CHIP-8 programs
+const ORIGIN: u16 = 0x200;
+
+/// Bound on macro expansions, to stop runaway recursive macros
+const MAX_EXPANSIONS: usize = 100_000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Compiled program
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// Bytes to load at 0x200
+    pub rom: Vec<u8>,
+    /// Address of the labels
+    pub labels: BTreeMap<String, u16>,
+}
+
+impl Program {
+    /// Format the labels as a symbol map, one `0xADDR name` line per label,
+    /// as read by `chip8::disasm::parse_symbols`.
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<_> = self.labels.iter().map(|(name, addr)| (*addr, name)).collect();
+        symbols.sort();
+        symbols.iter().map(|(addr, name)| format!("0x{:04X} {}\n", addr, name)).collect()
+    }
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Operand referring to an address
+enum Target {
+    Resolved(u16),
+    Forward(String),
+}
+
+/// Open control flow block
+enum Flow {
+    /// `begin`, with the address of the jump to the `else` or `end`
+    If(u16),
+    /// `else`, with the address of the jump to the `end`
+    Else(u16),
+    /// `loop`, with its start address and the jumps of its `while`s
+    Loop(u16, Vec<u16>),
+}
+
+/// Pair of skip instructions testing a condition
+struct Condition {
+    /// Instructions computing the condition, before the skip
+    setup: Vec<Insn>,
+    /// Skips the next instruction if the condition is true
+    skip_if_true: Insn,
+    /// Skips the next instruction if the condition is false
+    skip_if_false: Insn,
+}
+
+pub struct Compiler {
+    platform: Platform,
+    tokens: Vec<Token>,
+    pos: usize,
+    image: Vec<u8>,
+    here: u16,
+    end: usize,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    /// Forward references: instruction address, label and source line
+    fixups: Vec<(u16, String, usize)>,
+    flow: Vec<Flow>,
+    next_label: Option<String>,
+    expansions: usize,
+}
+
+impl Compiler {
+    /// Create a compiler accepting the instructions of `platform`
+    pub fn new(platform: Platform) -> Self {
+        Compiler {
+            platform,
+            tokens: Vec::new(),
+            pos: 0,
+            // Padded, so that patching an instruction can always read 4 bytes
+            image: vec![0; MEMORY_SIZE + 2],
+            here: ORIGIN,
+            end: ORIGIN as usize,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+            next_label: None,
+            expansions: 0,
+        }
+    }
+
+    /// Compile a program. Execution starts at the `main` label: unless the
+    /// program starts with it, a jump to `main` is inserted at 0x200.
+    pub fn compile(mut self, src: &str) -> Result<Program, Error> {
+        self.tokens = lexer::tokenize(src);
+        let starts_with_main = self.tokens.len() >= 2 && self.tokens[0].text == ":" && self.tokens[1].text == "main";
+        if !starts_with_main {
+            self.emit_target(Insn::Jump(0), Target::Forward("main".to_string()))?;
+        }
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+        }
+        if let Some(f) = self.flow.last() {
+            let what = match f {
+                Flow::If(_) | Flow::Else(_) => "missing 'end'",
+                Flow::Loop(_, _) => "missing 'again'",
+            };
+            return Err(self.error(what.to_string()));
+        }
+        if let Some(name) = self.next_label.take() {
+            return Err(self.error(format!("no instruction follows ':next {}'", name)));
+        }
+        for (addr, name, line) in std::mem::take(&mut self.fixups) {
+            let target = match self.labels.get(&name) {
+                Some(a) => *a,
+                None => {
+                    return Err(Error {
+                        line,
+                        message: format!("undefined name '{}'", name),
+                    })
+                }
+            };
+            self.patch(addr, target).map_err(|message| Error { line, message })?;
+        }
+        Ok(Program {
+            rom: self.image[ORIGIN as usize..self.end].to_vec(),
+            labels: self.labels.into_iter().collect(),
+        })
+    }
+
+    //
+    // Token stream
+    //
+
+    /// Line of the last consumed token
+    fn line(&self) -> usize {
+        match self.pos {
+            0 => self.tokens.first().map(|t| t.line).unwrap_or(1),
+            k => self.tokens[(k - 1).min(self.tokens.len() - 1)].line,
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error {
+            line: self.line(),
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, Error> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.text.clone())
+            }
+            None => Err(self.error("unexpected end of file".to_string())),
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        let t = self.next()?;
+        if t != s {
+            return Err(self.error(format!("expected '{}', found '{}'", s, t)));
+        }
+        Ok(())
+    }
+
+    /// Consume the next token if it is `s`
+    fn accept(&mut self, s: &str) -> bool {
+        if self.peek() == Some(s) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        let t = self.next()?;
+        if parse_number(&t).is_some() || t.starts_with(':') || t == "{" || t == "}" {
+            return Err(self.error(format!("invalid name '{}'", t)));
+        }
+        Ok(t)
+    }
+
+    /// Tokens up to the matching `}`, the `{` being already consumed
+    fn block(&mut self) -> Result<Vec<Token>, Error> {
+        let mut depth = 1;
+        let start = self.pos;
+        loop {
+            match self.next()?.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(self.tokens[start..self.pos - 1].to_vec());
+            }
+        }
+    }
+
+    //
+    // Operands
+    //
+
+    fn register(&mut self) -> Result<u8, Error> {
+        let t = self.next()?;
+        self.as_register(&t)
+            .ok_or_else(|| self.error(format!("expected a register, found '{}'", t)))
+    }
+
+    fn as_register(&self, t: &str) -> Option<u8> {
+        parse_register(t).or_else(|| self.aliases.get(t).cloned())
+    }
+
+    fn lookup(&self, name: &str) -> Option<f64> {
+        if name == "HERE" {
+            return Some(self.here as f64);
+        }
+        self.consts
+            .get(name)
+            .cloned()
+            .or_else(|| self.labels.get(name).map(|a| *a as f64))
+    }
+
+    fn calc(&mut self) -> Result<f64, Error> {
+        let body = self.block()?;
+        let words: Vec<&str> = body.iter().map(|t| t.text.as_str()).collect();
+        calc::eval(&words, &|name| self.lookup(name)).map_err(|msg| self.error(msg))
+    }
+
+    /// Number, constant, defined label or `{ expression }`
+    fn number(&mut self) -> Result<i64, Error> {
+        let t = self.next()?;
+        if t == "{" {
+            return Ok(self.calc()? as i64);
+        }
+        match parse_number(&t).or_else(|| self.lookup(&t).map(|v| v as i64)) {
+            Some(v) => Ok(v),
+            None => Err(self.error(format!("undefined name '{}'", t))),
+        }
+    }
+
+    fn ranged(&mut self, min: i64, max: i64) -> Result<i64, Error> {
+        let v = self.number()?;
+        if v < min || v > max {
+            return Err(self.error(format!("value {} out of range [{}, {}]", v, min, max)));
+        }
+        Ok(v)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        self.ranged(-0x80, 0xFF).map(|v| v as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, Error> {
+        self.ranged(0, 0xF).map(|v| v as u8)
+    }
+
+    /// Address operand, which may refer to a label defined later
+    fn target(&mut self) -> Result<Target, Error> {
+        match self.peek() {
+            Some(t) if t != "{" && parse_number(t).is_none() && self.lookup(t).is_none() => {
+                Ok(Target::Forward(self.next()?))
+            }
+            _ => Ok(Target::Resolved(self.ranged(0, 0xFFFF)? as u16)),
+        }
+    }
+
+    //
+    // Code generation
+    //
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let addr = self.here as usize;
+        if addr + bytes.len() > MEMORY_SIZE {
+            return Err(self.error("program does not fit in memory".to_string()));
+        }
+        self.image[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self.here = (addr + bytes.len()) as u16;
+        self.end = self.end.max(addr + bytes.len());
+        Ok(())
+    }
+
+    fn emit(&mut self, insn: Insn) -> Result<(), Error> {
+        if insn.platform() > self.platform {
+            return Err(self.error(format!("'{}' requires the {:?} platform", insn.octo(), insn.platform())));
+        }
+        if let Some(name) = self.next_label.take() {
+            self.define_label(name, self.here.wrapping_add(1))?;
+        }
+        let mut bytes = [0u8; 4];
+        let n = insn.write_bytes(&mut bytes);
+        self.write_bytes(&bytes[..n])
+    }
+
+    fn emit_target(&mut self, insn: Insn, target: Target) -> Result<(), Error> {
+        let addr = self.here;
+        match target {
+            Target::Resolved(a) => {
+                let insn = with_target(insn, a).map_err(|msg| self.error(msg))?;
+                self.emit(insn)
+            }
+            Target::Forward(name) => {
+                self.emit(insn)?;
+                self.fixups.push((addr, name, self.line()));
+                Ok(())
+            }
+        }
+    }
+
+    /// Set the address operand of the instruction at `addr`
+    fn patch(&mut self, addr: u16, target: u16) -> Result<(), String> {
+        let k = addr as usize;
+        let w0 = u16::from_be_bytes([self.image[k], self.image[k + 1]]);
+        let w1 = u16::from_be_bytes([self.image[k + 2], self.image[k + 3]]);
+        let insn = Insn::decode_long(w0, w1).expect("patched instruction is valid");
+        let insn = with_target(insn, target)?;
+        insn.write_bytes(&mut self.image[k..]);
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String, addr: u16) -> Result<(), Error> {
+        if self.labels.contains_key(&name) || self.consts.contains_key(&name) {
+            return Err(self.error(format!("name '{}' is already defined", name)));
+        }
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    //
+    // Statements
+    //
+
+    fn statement(&mut self) -> Result<(), Error> {
+        let t = self.next()?;
+        if let Some(r) = self.as_register(&t) {
+            return self.register_statement(r);
+        }
+        if let Some(n) = parse_number(&t) {
+            if !(-0x80..=0xFF).contains(&n) {
+                return Err(self.error(format!("byte {} out of range", n)));
+            }
+            return self.write_bytes(&[n as u8]);
+        }
+        match t.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let r = self.register()?;
+                self.aliases.insert(name, r);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let v = self.number()?;
+                self.consts.insert(name, v as f64);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let v = self.calc()?;
+                self.consts.insert(name, v);
+            }
+            ":unpack" => {
+                let hi = self.nibble()? as u16;
+                let a = self.ranged(0, 0xFFF)? as u16;
+                let (v0, v1) = match self.aliases.get("unpack-hi").zip(self.aliases.get("unpack-lo")) {
+                    Some((hi, lo)) => (*hi, *lo),
+                    None => (0, 1),
+                };
+                self.emit(Insn::LoadI(v0, ((hi << 4) | (a >> 8)) as u8))?;
+                self.emit(Insn::LoadI(v1, a as u8))?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.next_label = Some(name);
+            }
+            ":org" => {
+                self.here = self.ranged(0, MEMORY_SIZE as i64 - 1)? as u16;
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut args = Vec::new();
+                while !self.accept("{") {
+                    args.push(self.name()?);
+                }
+                let body = self.block()?;
+                self.macros.insert(name, Macro { args, body });
+            }
+            ":byte" => {
+                let b = self.byte()?;
+                self.write_bytes(&[b])?;
+            }
+            ":call" => {
+                let target = self.target()?;
+                self.emit_target(Insn::Call(0), target)?;
+            }
+            ":breakpoint" => {
+                self.name()?;
+            }
+            ";" | "return" => self.emit(Insn::Ret)?,
+            "clear" => self.emit(Insn::Cls)?,
+            "exit" => self.emit(Insn::Exit)?,
+            "hires" => self.emit(Insn::HiRes)?,
+            "lores" => self.emit(Insn::LoRes)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Insn::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Insn::ScrollUp(n))?;
+            }
+            "scroll-left" => self.emit(Insn::ScrollLeft)?,
+            "scroll-right" => self.emit(Insn::ScrollRight)?,
+            "audio" => self.emit(Insn::LoadAudio)?,
+            "plane" => {
+                // As with the assembler, the mask is truncated to the
+                // existing planes by the CPU
+                let n = self.nibble()?;
+                self.emit(Insn::SelectPlanes(n))?;
+            }
+            "jump" => {
+                let target = self.target()?;
+                self.emit_target(Insn::Jump(0), target)?;
+            }
+            "jump0" => {
+                let target = self.target()?;
+                self.emit_target(Insn::JumpV0(0), target)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Insn::DrawSprite(x, y, n))?;
+            }
+            "bcd" => {
+                let r = self.register()?;
+                self.emit(Insn::StoreBCD(r))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let insn = if self.accept("-") {
+                    let y = self.register()?;
+                    if t == "save" { Insn::SaveRange(x, y) } else { Insn::LoadRange(x, y) }
+                } else if t == "save" {
+                    Insn::StoreRegs(x)
+                } else {
+                    Insn::LoadRegs(x)
+                };
+                self.emit(insn)?;
+            }
+            "saveflags" => {
+                let r = self.register()?;
+                self.emit(Insn::StoreFlags(r))?;
+            }
+            "loadflags" => {
+                let r = self.register()?;
+                self.emit(Insn::LoadFlags(r))?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let r = self.register()?;
+                self.emit(match t.as_str() {
+                    "delay" => Insn::SetDelayTimer(r),
+                    "buzzer" => Insn::SetSoundTimer(r),
+                    _ => Insn::SetPitch(r),
+                })?;
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => match self.flow.pop() {
+                Some(Flow::If(jump)) => {
+                    let addr = self.here;
+                    self.emit(Insn::Jump(0))?;
+                    self.patch(jump, self.here).map_err(|msg| self.error(msg))?;
+                    self.flow.push(Flow::Else(addr));
+                }
+                _ => return Err(self.error("'else' without 'begin'".to_string())),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::If(jump)) | Some(Flow::Else(jump)) => {
+                    self.patch(jump, self.here).map_err(|msg| self.error(msg))?;
+                }
+                _ => return Err(self.error("'end' without 'begin'".to_string())),
+            },
+            "loop" => self.flow.push(Flow::Loop(self.here, Vec::new())),
+            "while" => {
+                let cond = self.condition()?;
+                for insn in cond.setup {
+                    self.emit(insn)?;
+                }
+                self.emit(cond.skip_if_true)?;
+                let addr = self.here;
+                self.emit(Insn::Jump(0))?;
+                match self.flow.iter_mut().rev().find(|f| matches!(f, Flow::Loop(_, _))) {
+                    Some(Flow::Loop(_, breaks)) => breaks.push(addr),
+                    _ => return Err(self.error("'while' outside of a loop".to_string())),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop(start, breaks)) => {
+                    self.emit(Insn::Jump(0))?;
+                    self.patch(self.here - 2, start).map_err(|msg| self.error(msg))?;
+                    for addr in breaks {
+                        self.patch(addr, self.here).map_err(|msg| self.error(msg))?;
+                    }
+                }
+                _ => return Err(self.error("'again' without 'loop'".to_string())),
+            },
+            "native" => return Err(self.error("'native' calls are not supported".to_string())),
+            _ if self.macros.contains_key(&t) => self.expand(&t)?,
+            _ if t.starts_with(':') || t == "{" || t == "}" => {
+                return Err(self.error(format!("unexpected '{}'", t)));
+            }
+            // Bare names are subroutine calls
+            _ => {
+                self.pos -= 1;
+                let target = self.target()?;
+                self.emit_target(Insn::Call(0), target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), Error> {
+        let op = self.next()?;
+        let t = self.next()?;
+        let y = self.as_register(&t);
+        let insn = match (op.as_str(), y) {
+            (":=", Some(y)) => Insn::Move(x, y),
+            ("+=", Some(y)) => Insn::Add(x, y),
+            ("-=", Some(y)) => Insn::Sub(x, y),
+            ("=-", Some(y)) => Insn::SubN(x, y),
+            ("|=", Some(y)) => Insn::Or(x, y),
+            ("&=", Some(y)) => Insn::And(x, y),
+            ("^=", Some(y)) => Insn::Xor(x, y),
+            (">>=", Some(y)) => Insn::Shr(x, y),
+            ("<<=", Some(y)) => Insn::Shl(x, y),
+            (":=", None) if t == "key" => Insn::WaitForKey(x),
+            (":=", None) if t == "delay" => Insn::LoadTimer(x),
+            (":=", None) if t == "random" => Insn::RndAnd(x, self.byte()?),
+            (":=", None) | ("+=", None) | ("-=", None) => {
+                self.pos -= 1;
+                let n = self.byte()?;
+                match op.as_str() {
+                    ":=" => Insn::LoadI(x, n),
+                    "+=" => Insn::AddI(x, n),
+                    _ => Insn::AddI(x, n.wrapping_neg()),
+                }
+            }
+            _ => return Err(self.error(format!("invalid operation 'v{:x} {} {}'", x, op, t))),
+        };
+        self.emit(insn)
+    }
+
+    fn index_statement(&mut self) -> Result<(), Error> {
+        let op = self.next()?;
+        match op.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let big = self.next()? == "bighex";
+                    let r = self.register()?;
+                    self.emit(if big { Insn::BigSpriteLoc(r) } else { Insn::SpriteLoc(r) })
+                }
+                Some("long") => {
+                    self.pos += 1;
+                    let target = self.target()?;
+                    self.emit_target(Insn::LoadLongA(0), target)
+                }
+                _ => {
+                    let target = self.target()?;
+                    self.emit_target(Insn::LoadA(0), target)
+                }
+            },
+            "+=" => {
+                let r = self.register()?;
+                self.emit(Insn::AddA(r))
+            }
+            _ => Err(self.error(format!("invalid operation 'i {}'", op))),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), Error> {
+        let cond = self.condition()?;
+        let begin = match self.next()?.as_str() {
+            "then" => false,
+            "begin" => true,
+            t => return Err(self.error(format!("expected 'then' or 'begin', found '{}'", t))),
+        };
+        for insn in cond.setup {
+            self.emit(insn)?;
+        }
+        if !begin {
+            return self.emit(cond.skip_if_false);
+        }
+        self.emit(cond.skip_if_true)?;
+        self.flow.push(Flow::If(self.here));
+        self.emit(Insn::Jump(0))
+    }
+
+    fn condition(&mut self) -> Result<Condition, Error> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let mut setup = Vec::new();
+        let (skip_if_true, skip_if_false) = match op.as_str() {
+            "key" => (Insn::SkipKeyPressed(x), Insn::SkipKeyNPressed(x)),
+            "-key" => (Insn::SkipKeyNPressed(x), Insn::SkipKeyPressed(x)),
+            "==" | "!=" => {
+                let (eq, neq) = match self.peek().and_then(|t| self.as_register(t)) {
+                    Some(y) => {
+                        self.pos += 1;
+                        (Insn::SkipEq(x, y), Insn::SkipNeq(x, y))
+                    }
+                    None => {
+                        let n = self.byte()?;
+                        (Insn::SkipEqI(x, n), Insn::SkipNeqI(x, n))
+                    }
+                };
+                if op == "==" { (eq, neq) } else { (neq, eq) }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                let t = self.aliases.get("compare-temp").cloned().unwrap_or(0xF);
+                setup.push(match self.peek().and_then(|t| self.as_register(t)) {
+                    Some(y) => {
+                        self.pos += 1;
+                        Insn::Move(t, y)
+                    }
+                    None => Insn::LoadI(t, self.byte()?),
+                });
+                // VF is set when there is no borrow: after `t -= vx` if
+                // vx <= y, after `t =- vx` if vx >= y
+                setup.push(if op == ">" || op == "<=" { Insn::Sub(t, x) } else { Insn::SubN(t, x) });
+                if op == ">" || op == "<" {
+                    (Insn::SkipEqI(0xF, 0), Insn::SkipEqI(0xF, 1))
+                } else {
+                    (Insn::SkipNeqI(0xF, 0), Insn::SkipNeqI(0xF, 1))
+                }
+            }
+            _ => return Err(self.error(format!("invalid condition operator '{}'", op))),
+        };
+        Ok(Condition {
+            setup,
+            skip_if_true,
+            skip_if_false,
+        })
+    }
+
+    /// Replace a macro call by the body of the macro
+    fn expand(&mut self, name: &str) -> Result<(), Error> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("too many expansions of macro '{}'", name)));
+        }
+        let nargs = self.macros[name].args.len();
+        let mut args = Vec::with_capacity(nargs);
+        for _ in 0..nargs {
+            args.push(self.next()?);
+        }
+        let line = self.line();
+        let m = &self.macros[name];
+        let body: Vec<Token> = m
+            .body
+            .iter()
+            .map(|t| {
+                let text = match m.args.iter().position(|a| *a == t.text) {
+                    Some(k) => args[k].clone(),
+                    None => t.text.clone(),
+                };
+                // Errors are reported on the line of the call
+                Token { text, line }
+            })
+            .collect();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+}
+
+/// Replace the address operand of an instruction
+fn with_target(insn: Insn, a: u16) -> Result<Insn, String> {
+    let long = matches!(insn, Insn::LoadLongA(_));
+    if !long && a > 0xFFF {
+        return Err(format!("address 0x{:04X} out of range, use 'i := long'", a));
+    }
+    Ok(match insn {
+        Insn::Jump(_) => Insn::Jump(a),
+        Insn::JumpV0(_) => Insn::JumpV0(a),
+        Insn::Call(_) => Insn::Call(a),
+        Insn::LoadA(_) => Insn::LoadA(a),
+        Insn::LoadLongA(_) => Insn::LoadLongA(a),
+        _ => unreachable!("instruction without address operand"),
+    })
+}
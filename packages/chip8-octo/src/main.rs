@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use chip8::Platform;
+use chip8_octo::Compiler;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chip8octo", about = "Octo compiler")]
+pub struct CliOpts {
+    /// Instruction set accepted (chip8, schip, xochip)
+    #[structopt(short = "p", long = "platform", default_value = "xochip")]
+    platform: Platform,
+
+    /// Output ROM (default: input file with the .ch8 extension)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Write the address of the labels to this file, for the emulator
+    /// `--symbols` option
+    #[structopt(short = "s", long = "symbols", parse(from_os_str))]
+    symbols: Option<PathBuf>,
+
+    #[structopt(name = "FILE.8o", parse(from_os_str))]
+    input: PathBuf,
+}
+
+fn write_file(path: &PathBuf, data: &[u8]) {
+    if let Err(e) = fs::write(path, data) {
+        eprintln!("error: cannot write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let opts = CliOpts::from_args();
+    let src = match fs::read_to_string(&opts.input) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", opts.input.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let program = match Compiler::new(opts.platform).compile(&src) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}:{}: error: {}", opts.input.display(), e.line, e.message);
+            std::process::exit(1);
+        }
+    };
+    let output = opts.output.clone().unwrap_or_else(|| opts.input.with_extension("ch8"));
+    write_file(&output, &program.rom);
+    if let Some(path) = &opts.symbols {
+        write_file(path, program.symbol_map().as_bytes());
+    }
+    println!("{}: {} bytes", output.display(), program.rom.len());
+}
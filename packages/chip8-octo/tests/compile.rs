@@ -0,0 +1,238 @@
+//
+// Octo compiler: emitted bytes and labels of each construct
+use chip8::{Chip8Emulator, Insn, Platform};
+use chip8_octo::{Compiler, Error, Program};
+
+fn compile(src: &str) -> Program {
+    Compiler::new(Platform::XoChip).compile(src).unwrap()
+}
+
+fn error(platform: Platform, src: &str) -> String {
+    Compiler::new(platform).compile(src).map(|_| ()).unwrap_err().to_string()
+}
+
+fn labels(program: &Program) -> Vec<(&str, u16)> {
+    program.labels.iter().map(|(n, a)| (n.as_str(), *a)).collect()
+}
+
+#[test]
+fn alias_const() {
+    let program = compile(
+        ": main
+           :alias x v3
+           :const SPEED 7
+           x := SPEED
+           x += x
+           v2 := x",
+    );
+    assert_eq!(program.rom, [0x63, 0x07, 0x83, 0x34, 0x82, 0x30]);
+    // Neither are labels
+    assert_eq!(program.symbol_map(), "0x0200 main\n");
+}
+
+#[test]
+fn forward_labels() {
+    let program = compile(
+        ": main
+           draw
+           jump main
+         : draw
+           i := sprite
+           sprite v0 v1 2
+           return
+         : sprite 0x81 0x42",
+    );
+    assert_eq!(program.rom, [0x22, 0x04, 0x12, 0x00, 0xA2, 0x0A, 0xD0, 0x12, 0x00, 0xEE, 0x81, 0x42]);
+    assert_eq!(labels(&program), [("draw", 0x204), ("main", 0x200), ("sprite", 0x20A)]);
+    assert_eq!(program.symbol_map(), "0x0200 main\n0x0204 draw\n0x020A sprite\n");
+
+    // Without `main` first, a jump to it is inserted
+    let program = compile(": helper ; : main helper");
+    assert_eq!(program.rom, [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+}
+
+#[test]
+fn if_then_else() {
+    let program = compile(
+        ": main
+           if v0 == 5 then v1 := 1
+           if v0 != v2 begin
+             v1 := 2
+           else
+             v1 := 3
+           end",
+    );
+    assert_eq!(
+        program.rom,
+        [
+            0x40, 0x05, 0x61, 0x01, // skip if v0 != 5
+            0x90, 0x20, 0x12, 0x0C, // skip if v0 != v2, else jump to `else`
+            0x61, 0x02, 0x12, 0x0E, // jump to `end`
+            0x61, 0x03,
+        ]
+    );
+}
+
+#[test]
+fn loop_while() {
+    let program = compile(
+        ": main
+           loop
+             v0 += 1
+             while v0 != 10
+             v1 += v0
+           again
+           v2 := 1",
+    );
+    assert_eq!(program.rom, [0x70, 0x01, 0x40, 0x0A, 0x12, 0x0A, 0x81, 0x04, 0x12, 0x00, 0x62, 0x01]);
+}
+
+#[test]
+fn comparisons() {
+    let program = compile(
+        ": main
+           if v1 > 5 then v2 := 1
+           if v1 <= v3 begin v2 := 2 end
+           loop while v1 < v2 again
+           :alias compare-temp ve
+           if v1 >= 3 then v2 := 4",
+    );
+    assert_eq!(
+        program.rom,
+        [
+            // vf := 5, vf -= v1, skip unless v1 > 5
+            0x6F, 0x05, 0x8F, 0x15, 0x3F, 0x01, 0x62, 0x01,
+            // vf := v3, vf -= v1, skip if v1 <= v3
+            0x8F, 0x30, 0x8F, 0x15, 0x4F, 0x00, 0x12, 0x12, 0x62, 0x02,
+            // vf := v2, vf =- v1, skip if v1 < v2
+            0x8F, 0x20, 0x8F, 0x17, 0x3F, 0x00, 0x12, 0x1C, 0x12, 0x12,
+            // With another temporary register
+            0x6E, 0x03, 0x8E, 0x17, 0x4F, 0x01, 0x62, 0x04,
+        ]
+    );
+}
+
+#[test]
+fn comparisons_run() {
+    // Each comparison, through `then`, `begin` and `while`
+    let values = [0u8, 1, 5, 6, 127, 128, 254, 255];
+    type Op = fn(u8, u8) -> bool;
+    let ops: [(&str, Op); 4] = [("<", |a, b| a < b), (">", |a, b| a > b), ("<=", |a, b| a <= b), (">=", |a, b| a >= b)];
+    for (op, expected) in ops.iter() {
+        for a in values.iter() {
+            for b in values.iter() {
+                let src = format!(
+                    ": main
+                       v1 := {a} v2 := {b}
+                       if v1 {op} v2 then v3 := 1
+                       if v1 {op} {b} begin v4 := 1 else v4 := 2 end
+                       :alias compare-temp v6
+                       loop while v1 {op} v2 v5 += 1 again
+                     : halt jump halt",
+                );
+                let program = Compiler::new(Platform::Chip8).compile(&src).unwrap();
+                let mut emu = Chip8Emulator::new(500);
+                emu.load_rom(&program.rom);
+                emu.tick(100).unwrap();
+                let v = |r| emu.cpu().gpr(r).unwrap();
+                let what = format!("{} {} {}", a, op, b);
+                let truth = expected(*a, *b);
+                assert_eq!(v(3), truth as u8, "{}: then", what);
+                assert_eq!(v(4), if truth { 1 } else { 2 }, "{}: begin", what);
+                assert_eq!(v(5) > 0, truth, "{}: while", what);
+                assert_eq!((v(1), v(2)), (*a, *b), "{}: operands", what);
+            }
+        }
+    }
+}
+
+#[test]
+fn macro_calc() {
+    let program = compile(
+        ":macro twice REG { REG += REG }
+         :calc WIDTH { 8 * 4 }
+         : main
+           v0 := WIDTH
+           twice v0
+           twice v1
+         :calc HALF { WIDTH / 2 + 1 }
+           v2 := HALF
+         :calc AFTER { HERE + 2 }
+           i := AFTER",
+    );
+    // Jump to main, then HALF is WIDTH / (2 + 1) (right to left)
+    assert_eq!(program.rom, [0x12, 0x02, 0x60, 0x20, 0x80, 0x04, 0x81, 0x14, 0x62, 0x0A, 0xA2, 0x0C]);
+    assert_eq!(program.symbol_map(), "0x0202 main\n");
+}
+
+#[test]
+fn next_org() {
+    let program = compile(
+        ": main
+           :next counter v0 := 0
+           i := counter
+         :org 0x300
+         : table 1 2 3",
+    );
+    assert_eq!(program.rom[..4], [0x60, 0x00, 0xA2, 0x01]);
+    // The gap is filled with zeros
+    assert_eq!(program.rom.len(), 0x103);
+    assert!(program.rom[4..0x100].iter().all(|b| *b == 0));
+    assert_eq!(program.rom[0x100..], [1, 2, 3]);
+    assert_eq!(program.symbol_map(), "0x0200 main\n0x0201 counter\n0x0300 table\n");
+}
+
+#[test]
+fn long_index() {
+    let program = compile(
+        ": main
+           i := long data
+           i := long 0x1234
+         :org 0x2000
+         : data 0xAB",
+    );
+    assert_eq!(program.rom[..8], [0xF0, 0x00, 0x20, 0x00, 0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(program.rom.len(), 0x1E01);
+    assert_eq!(labels(&program), [("data", 0x2000), ("main", 0x200)]);
+
+    assert_eq!(
+        error(Platform::SuperChip, ": main i := long 0x1234"),
+        "line 1: 'i := long 0x1234' requires the XoChip platform"
+    );
+    assert_eq!(
+        error(Platform::XoChip, ": main\ni := data\n:org 0x2000 : data"),
+        "line 2: address 0x2000 out of range, use 'i := long'"
+    );
+}
+
+#[test]
+fn disassembly_round_trip() {
+    // The Octo syntax printed for each instruction compiles back to it, the
+    // skips being followed by a `clear`
+    let insns = (0..=0xFFFF).filter_map(Insn::decode).chain(Some(Insn::LoadLongA(0x1234)));
+    for insn in insns {
+        let src = format!(": main\n{}\nclear", insn.octo());
+        let program = Compiler::new(Platform::XoChip).compile(&src).unwrap_or_else(|e| panic!("{}: {}", src, e));
+        let mut bytes = [0; 4];
+        let n = insn.write_bytes(&mut bytes);
+        assert_eq!(program.rom[..n], bytes[..n], "{}", src);
+        assert_eq!(program.rom[n..], [0x00, 0xE0], "{}", src);
+    }
+}
+
+#[test]
+fn errors() {
+    let cases = [
+        (": main\njump nowhere", "line 2: undefined name 'nowhere'"),
+        (": main\nif v0 == 1 begin\nv1 := 1", "line 3: missing 'end'"),
+        (": main\nloop\nv1 := 1", "line 3: missing 'again'"),
+        (": main\nelse", "line 2: 'else' without 'begin'"),
+        (": main\nif v0 < 1", "line 2: unexpected end of file"),
+        (": main\nif v0 =< 1 then v0 := 1", "line 2: invalid condition operator '=<'"),
+        (": main\n: main", "line 2: name 'main' is already defined"),
+    ];
+    for (src, message) in cases.iter() {
+        let e: Error = Compiler::new(Platform::Chip8).compile(src).unwrap_err();
+        assert_eq!(e.to_string(), *message, "{}", src);
+    }
+}
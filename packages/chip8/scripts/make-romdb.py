@@ -0,0 +1,178 @@
+#!/usr/bin/env python3
+"""Generate the built-in ROM database (src/romdb/table.rs) from the ROMs
+bundled in assets/roms.
+
+The platform, quirks and CPU frequency are guessed from the ROM name and its
+.txt sidecar: programs written for the COSMAC VIP get the VIP quirks and
+speed, the later ones (HP48 ports, DOS and emulator era) run faster, and the
+"System" line of the sidecar gives the platform. Key hints and per-ROM fixes
+are maintained by hand in HINTS below.
+"""
+
+import argparse
+import pathlib
+import re
+import zlib
+
+# Only the 64x32 CHIP-8 programs are indexed, the 'hires' directory holds
+# programs for the 64x64 VIP mode which is not emulated.
+SUBDIRS = ['revival-pack/games', 'revival-pack/demos', 'revival-pack/programs']
+
+# Undated programs
+DEFAULT_CPU_HZ = 500
+# Written for the COSMAC VIP interpreter, about 8 instructions per frame
+VIP_CPU_HZ = 500
+# HP48 ports and later programs, tuned on faster interpreters
+LATER_CPU_HZ = 700
+# Authors of undated programs from 1990 or later
+LATER_AUTHORS = ['David Winter', 'Hans Christian Egeberg', 'Hap', 'Revival Studios']
+
+VIP_QUIRKS = 'Quirks::cosmac_vip()'
+DEFAULT_QUIRKS = 'Quirks::legacy()'
+
+MOVE_4_6 = [(4, 'left'), (6, 'right')]
+MOVE_2_4_6_8 = [(2, 'up'), (4, 'left'), (6, 'right'), (8, 'down')]
+
+# Per title overrides: keys, quirks (a Rust expression), platform and cpu_hz
+HINTS = {
+    '15 Puzzle [Roger Ivie]': {'keys': MOVE_2_4_6_8},
+    'Astro Dodge [Revival Studios, 2008]': {'keys': MOVE_2_4_6_8 + [(5, 'start')]},
+    'Blinky [Hans Christian Egeberg, 1991]': {'cpu_hz': 1000},
+    'Blinky [Hans Christian Egeberg] (alt)': {'cpu_hz': 1000},
+    'Blitz [David Winter]': {
+        'keys': [(5, 'drop bomb')],
+        # The plane wraps to the top of the screen otherwise
+        'quirks': 'Quirks { clip_sprites: true, ..Quirks::legacy() }',
+    },
+    'Breakout (Brix hack) [David Winter, 1997]': {'keys': MOVE_4_6},
+    'Brix [Andreas Gustafsson, 1990]': {'keys': MOVE_4_6},
+    'Brick (Brix hack, 1990)': {'keys': MOVE_4_6},
+    'Connect 4 [David Winter]': {'keys': MOVE_4_6 + [(5, 'drop coin')]},
+    'Guess [David Winter]': {'keys': [(5, 'yes')]},
+    'Guess [David Winter] (alt)': {'keys': [(5, 'yes')]},
+    'Hidden [David Winter, 1996]': {'keys': MOVE_2_4_6_8 + [(5, 'select')]},
+    'Kaleidoscope [Joseph Weisbecker, 1978]': {'keys': MOVE_2_4_6_8 + [(0, 'repeat pattern')]},
+    'Lunar Lander (Udo Pernisz, 1979)': {'keys': [(2, 'thrust'), (4, 'left'), (6, 'right')]},
+    'Merlin [David Winter]': {'keys': [(4, 'top left'), (5, 'top right'), (1, 'bottom left'), (2, 'bottom right')]},
+    'Most Dangerous Game [Peter Maruhnic]': {'keys': MOVE_2_4_6_8 + [(0, 'end turn')]},
+    'Nim [Carmelo Cortez, 1978]': {'keys': [(0xF, 'play first')]},
+    'Pong [Paul Vervalin, 1990]': {'keys': [(1, 'left up'), (4, 'left down'), (0xC, 'right up'), (0xD, 'right down')]},
+    'Reversi [Philip Baltzer]': {'keys': [(5, 'place marker'), (0xF, 'pass')]},
+    'Rush Hour [Hap, 2006]': {'keys': [(7, 'left'), (9, 'right')]},
+    'Sequence Shoot [Joyce Weisbecker]': {'keys': [(0xC, 'shoot 1'), (0xD, 'shoot 2'), (0xE, 'shoot 3'), (0xF, 'shoot 4')]},
+    'Slide [Joyce Weisbecker]': {'keys': [(0, 'stop puck')]},
+    'Space Intercept [Joseph Weisbecker, 1978]': {'keys': [(1, 'large UFO'), (2, 'small UFO'), (4, 'launch left'), (5, 'launch'), (6, 'launch right')]},
+    'Space Invaders [David Winter]': {'keys': MOVE_4_6 + [(5, 'shoot')]},
+    'Space Invaders [David Winter] (alt)': {'keys': MOVE_4_6 + [(5, 'shoot')]},
+    'Spooky Spot [Joseph Weisbecker, 1978]': {'keys': [(0, 'ask')]},
+    'Submarine [Carmelo Cortez, 1978]': {'keys': [(5, 'fire')]},
+    'Syzygy [Roy Trevino, 1990]': {'keys': [(1, 'left'), (2, 'right'), (6, 'up'), (9, 'down')]},
+    'Tank': {'keys': MOVE_2_4_6_8 + [(5, 'fire')]},
+    'Tetris [Fran Dachille, 1991]': {'keys': [(4, 'rotate'), (5, 'left'), (6, 'right'), (1, 'drop')]},
+    'UFO [Lutz V, 1992]': {'keys': [(4, 'shoot left'), (5, 'shoot up'), (6, 'shoot right')]},
+    'Delay Timer Test [Matthew Mikolay, 2010]': {'keys': [(2, 'increase'), (8, 'decrease'), (5, 'start timer')]},
+}
+
+
+def normalize(name):
+    return re.sub(r'[^a-z0-9]', '', name.lower())
+
+
+def find_sidecar(rom):
+    sidecars = {normalize(p.stem): p for p in rom.parent.glob('*.txt')}
+    return sidecars.get(normalize(rom.stem))
+
+
+def read_sidecar(rom):
+    sidecar = find_sidecar(rom)
+    return sidecar.read_text(errors='replace') if sidecar is not None else ''
+
+
+def title_years(rom):
+    return [int(y) for y in re.findall(r'\b(19\d\d|20\d\d)\b', rom.stem)]
+
+
+def is_vip_program(rom):
+    years = title_years(rom)
+    if years:
+        return max(years) <= 1981
+    return 'VIP' in read_sidecar(rom)
+
+
+def is_later_program(rom):
+    years = title_years(rom)
+    if years:
+        return min(years) >= 1990
+    return ('199x' in rom.stem or 'HP48' in read_sidecar(rom)
+            or any(f'[{a}' in rom.stem for a in LATER_AUTHORS))
+
+
+def guess_cpu_hz(rom):
+    if is_vip_program(rom):
+        return VIP_CPU_HZ
+    if is_later_program(rom):
+        return LATER_CPU_HZ
+    return DEFAULT_CPU_HZ
+
+
+def guess_platform(rom):
+    """Platform named by the "System : ..." line of the sidecar. Programs
+    released for several systems are indexed in their CHIP-8 version."""
+    match = re.search(r'^System\s*:\s*(.*)$', read_sidecar(rom), re.MULTILINE)
+    systems = normalize(match.group(1)) if match else ''
+    if 'xochip' in systems:
+        return 'XoChip'
+    if 'superchip' in systems and 'chip8' not in systems.replace('superchip8', ''):
+        return 'SuperChip'
+    return 'Chip8'
+
+
+def rust_str(s):
+    return '"' + s.replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+
+def main():
+    parser = argparse.ArgumentParser(description='Generate the CHIP8 ROM database')
+    parser.add_argument('-o', '--output', metavar='FILE.rs', required=True,
+                        help='Output Rust source')
+    parser.add_argument('root', help='Source directory searched for .ch8 ROMs')
+    args = parser.parse_args()
+
+    root = pathlib.Path(args.root)
+    entries = {}
+    roms = sorted(f for d in SUBDIRS for f in (root / d).glob('*.ch8'))
+    # Prefer the annotated names when a ROM is present several times
+    roms.sort(key=lambda f: '[' not in f.stem)
+    for f in roms:
+        crc = zlib.crc32(f.read_bytes())
+        if crc in entries:
+            continue
+        title = f.stem
+        hints = HINTS.get(title, {})
+        quirks = hints.get('quirks', VIP_QUIRKS if is_vip_program(f) else DEFAULT_QUIRKS)
+        keys = ', '.join(f'KeyHint {{ key: 0x{k:X}, action: {rust_str(a)} }}'
+                         for k, a in hints.get('keys', []))
+        entries[crc] = (
+            f'    RomInfo {{\n'
+            f'        crc32: 0x{crc:08x},\n'
+            f'        title: {rust_str(title)},\n'
+            f'        platform: Platform::{hints.get("platform", guess_platform(f))},\n'
+            f'        quirks: {quirks},\n'
+            f'        cpu_hz: {hints.get("cpu_hz", guess_cpu_hz(f))},\n'
+            f'        keys: &[{keys}],\n'
+            f'    }},\n')
+
+    with open(args.output, 'w') as out:
+        out.write('// Generated by scripts/make-romdb.py, do not edit.\n')
+        out.write('use crate::cpu::Platform;\n')
+        out.write('use crate::quirks::Quirks;\n')
+        out.write('use super::{KeyHint, RomInfo};\n\n')
+        out.write('/// Sorted by CRC32\n')
+        out.write(f'pub const ROM_TABLE: [RomInfo; {len(entries)}] = [\n')
+        for crc in sorted(entries):
+            out.write(entries[crc])
+        out.write('];\n')
+
+
+if __name__ == "__main__":
+    main()
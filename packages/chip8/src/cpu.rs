@@ -5,10 +5,16 @@
 // * https://hackaday.io/project/19121-andxor-dc25-badge/log/53223-chip8-schip-game-emulation
 // * https://www.onlinegdb.com/ryyYBu2m8
 // * https://blog.scottlogic.com/2017/12/13/chip8-emulator-webassembly-rust.html
-use core::convert::TryInto;
+use core::fmt;
 use core::ops::Shl;
+use core::str::FromStr;
 
 use crate::Chip8Peripherals;
+use crate::emu::{BIG_SPRITE_ADDR, MEMORY_SIZE};
+use crate::quirks::{IndexQuirk, Quirks};
+use crate::screen::SCREEN_PLANES;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::trace::{TraceEvent, TraceHook};
 
 type Word = u8;
 type Addr = u16;
@@ -42,13 +48,43 @@ fn bool_to_bit(b: bool) -> u8 {
     if b { 1 } else { 0 }
 }
 
-const SCREEN_W: usize = 64;
-const SCREEN_H: usize = 32;
-
 //
 // Chip8 API
 //
 
+/// Instruction set variant emulated by the CPU. Variants are ordered, each
+/// one being a superset of the previous.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl FromStr for Platform {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err("unknown platform (expected: chip8, schip, xochip)"),
+        }
+    }
+}
+
+impl Platform {
+    /// Size of the address space: 4 KiB, or 64 KiB on XO-CHIP
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => MEMORY_SIZE,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Insn {
     Cls,
@@ -85,6 +121,25 @@ pub enum Insn {
     StoreBCD(Reg),
     StoreRegs(Reg),
     LoadRegs(Reg),
+    // SUPER-CHIP extensions
+    ScrollDown(Word),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LoRes,
+    HiRes,
+    BigSpriteLoc(Reg),
+    StoreFlags(Reg),
+    LoadFlags(Reg),
+    // XO-CHIP extensions
+    ScrollUp(Word),
+    SaveRange(Reg, Reg),
+    LoadRange(Reg, Reg),
+    /// Load a 16-bit address into I, the address follows the opcode
+    LoadLongA(u16),
+    SelectPlanes(Word),
+    LoadAudio,
+    SetPitch(Reg),
 }
 
 
@@ -97,12 +152,17 @@ impl Insn {
         let ry = get_hb1(insn) as Reg;
         match get_hb3(insn) {
             0 => {
-                if insn == 0x00E0 {
-                    Some(Insn::Cls)
-                } else if insn == 0x00EE {
-                    Some(Insn::Ret)
-                } else {
-                    None
+                match insn {
+                    0x00E0 => Some(Insn::Cls),
+                    0x00EE => Some(Insn::Ret),
+                    0x00FB => Some(Insn::ScrollRight),
+                    0x00FC => Some(Insn::ScrollLeft),
+                    0x00FD => Some(Insn::Exit),
+                    0x00FE => Some(Insn::LoRes),
+                    0x00FF => Some(Insn::HiRes),
+                    _ if insn & 0xFFF0 == 0x00C0 => Some(Insn::ScrollDown(get_hb0(insn) as u8)),
+                    _ if insn & 0xFFF0 == 0x00D0 => Some(Insn::ScrollUp(get_hb0(insn) as u8)),
+                    _ => None
                 }
             }
             1 => {
@@ -118,10 +178,12 @@ impl Insn {
                 Some(Insn::SkipNeqI(rx, get_b0(insn)))
             }
             5 => {
-                if get_hb0(insn) != 0 {
-                    return None;
+                match get_hb0(insn) {
+                    0 => Some(Insn::SkipEq(rx, ry)),
+                    2 => Some(Insn::SaveRange(rx, ry)),
+                    3 => Some(Insn::LoadRange(rx, ry)),
+                    _ => None
                 }
-                Some(Insn::SkipEq(rx, ry))
             }
             6 => {
                 Some(Insn::LoadI(rx, get_b0(insn)))
@@ -130,8 +192,6 @@ impl Insn {
                 Some(Insn::AddI(rx, get_b0(insn)))
             }
             8 => {
-                let rx = rx;
-                let ry = ry;
                 match get_hb0(insn) {
                     0 => Some(Insn::Move(rx, ry)),
                     1 => Some(Insn::Or(rx, ry)),
@@ -172,24 +232,275 @@ impl Insn {
             }
             0xF => {
                 match get_b0(insn) {
+                    // Note: F000 needs the next word, see `decode_long`
+                    0x01 => Some(Insn::SelectPlanes(rx)),
+                    0x02 if rx == 0 => Some(Insn::LoadAudio),
                     0x07 => Some(Insn::LoadTimer(rx)),
                     0x0A => Some(Insn::WaitForKey(rx)),
                     0x15 => Some(Insn::SetDelayTimer(rx)),
                     0x18 => Some(Insn::SetSoundTimer(rx)),
                     0x1E => Some(Insn::AddA(rx)),
                     0x29 => Some(Insn::SpriteLoc(rx)),
+                    0x30 => Some(Insn::BigSpriteLoc(rx)),
                     0x33 => Some(Insn::StoreBCD(rx)),
+                    0x3A => Some(Insn::SetPitch(rx)),
                     0x55 => Some(Insn::StoreRegs(rx)),
                     0x65 => Some(Insn::LoadRegs(rx)),
+                    0x75 => Some(Insn::StoreFlags(rx)),
+                    0x85 => Some(Insn::LoadFlags(rx)),
                     _ => None
                 }
             }
             _ => None
         }
     }
+
+    /// Decode an instruction given the word that follows it, needed for the
+    /// XO-CHIP 4-byte long load (F000 NNNN).
+    pub fn decode_long(insn: u16, next: u16) -> Option<Self> {
+        if insn == 0xF000 {
+            Some(Insn::LoadLongA(next))
+        } else {
+            Self::decode(insn)
+        }
+    }
+
+    /// Size of the encoded instruction, in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Insn::LoadLongA(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Oldest platform on which the instruction is available
+    pub fn platform(&self) -> Platform {
+        match self {
+            Insn::ScrollDown(_)
+            | Insn::ScrollRight
+            | Insn::ScrollLeft
+            | Insn::Exit
+            | Insn::LoRes
+            | Insn::HiRes
+            | Insn::BigSpriteLoc(_)
+            | Insn::StoreFlags(_)
+            | Insn::LoadFlags(_) => Platform::SuperChip,
+            Insn::ScrollUp(_)
+            | Insn::SaveRange(_, _)
+            | Insn::LoadRange(_, _)
+            | Insn::LoadLongA(_)
+            | Insn::SelectPlanes(_)
+            | Insn::LoadAudio
+            | Insn::SetPitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// Opcode of the instruction, inverse of `decode`. For `LoadLongA`,
+    /// this is the first word only (F000), the address follows.
+    pub fn encode(&self) -> u16 {
+        let xy = |x: Reg, y: Reg| ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4);
+        let xnn = |x: Reg, nn: Word| ((x as u16 & 0xF) << 8) | nn as u16;
+        let x = |x: Reg| (x as u16 & 0xF) << 8;
+        match *self {
+            Insn::Cls => 0x00E0,
+            Insn::Ret => 0x00EE,
+            Insn::Jump(a) => 0x1000 | (a & 0xFFF),
+            Insn::JumpV0(a) => 0xB000 | (a & 0xFFF),
+            Insn::Call(a) => 0x2000 | (a & 0xFFF),
+            Insn::SkipEqI(r, v) => 0x3000 | xnn(r, v),
+            Insn::SkipNeqI(r, v) => 0x4000 | xnn(r, v),
+            Insn::SkipEq(rx, ry) => 0x5000 | xy(rx, ry),
+            Insn::SkipNeq(rx, ry) => 0x9000 | xy(rx, ry),
+            Insn::LoadI(r, v) => 0x6000 | xnn(r, v),
+            Insn::AddI(r, v) => 0x7000 | xnn(r, v),
+            Insn::Move(rx, ry) => 0x8000 | xy(rx, ry),
+            Insn::Or(rx, ry) => 0x8001 | xy(rx, ry),
+            Insn::And(rx, ry) => 0x8002 | xy(rx, ry),
+            Insn::Xor(rx, ry) => 0x8003 | xy(rx, ry),
+            Insn::Add(rx, ry) => 0x8004 | xy(rx, ry),
+            Insn::Sub(rx, ry) => 0x8005 | xy(rx, ry),
+            Insn::Shr(rx, ry) => 0x8006 | xy(rx, ry),
+            Insn::SubN(rx, ry) => 0x8007 | xy(rx, ry),
+            Insn::Shl(rx, ry) => 0x800E | xy(rx, ry),
+            Insn::LoadA(a) => 0xA000 | (a & 0xFFF),
+            Insn::AddA(r) => 0xF01E | x(r),
+            Insn::RndAnd(r, v) => 0xC000 | xnn(r, v),
+            Insn::DrawSprite(rx, ry, n) => 0xD000 | xy(rx, ry) | (n as u16 & 0xF),
+            Insn::SkipKeyPressed(r) => 0xE09E | x(r),
+            Insn::SkipKeyNPressed(r) => 0xE0A1 | x(r),
+            Insn::LoadTimer(r) => 0xF007 | x(r),
+            Insn::WaitForKey(r) => 0xF00A | x(r),
+            Insn::SetDelayTimer(r) => 0xF015 | x(r),
+            Insn::SetSoundTimer(r) => 0xF018 | x(r),
+            Insn::SpriteLoc(r) => 0xF029 | x(r),
+            Insn::StoreBCD(r) => 0xF033 | x(r),
+            Insn::StoreRegs(r) => 0xF055 | x(r),
+            Insn::LoadRegs(r) => 0xF065 | x(r),
+            Insn::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Insn::ScrollRight => 0x00FB,
+            Insn::ScrollLeft => 0x00FC,
+            Insn::Exit => 0x00FD,
+            Insn::LoRes => 0x00FE,
+            Insn::HiRes => 0x00FF,
+            Insn::BigSpriteLoc(r) => 0xF030 | x(r),
+            Insn::StoreFlags(r) => 0xF075 | x(r),
+            Insn::LoadFlags(r) => 0xF085 | x(r),
+            Insn::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Insn::SaveRange(rx, ry) => 0x5002 | xy(rx, ry),
+            Insn::LoadRange(rx, ry) => 0x5003 | xy(rx, ry),
+            Insn::LoadLongA(_) => 0xF000,
+            Insn::SelectPlanes(n) => 0xF001 | x(n),
+            Insn::LoadAudio => 0xF002,
+            Insn::SetPitch(r) => 0xF03A | x(r),
+        }
+    }
+
+    /// Raw opcode, the two words of the XO-CHIP long instructions
+    pub fn opcode(&self) -> u32 {
+        match *self {
+            Insn::LoadLongA(addr) => (self.encode() as u32) << 16 | addr as u32,
+            _ => self.encode() as u32,
+        }
+    }
+
+    /// Write the big-endian encoding of the instruction, returns the number
+    /// of bytes written (see `size`).
+    pub fn write_bytes(&self, out: &mut [u8]) -> usize {
+        out[..2].copy_from_slice(&self.encode().to_be_bytes());
+        if let Insn::LoadLongA(addr) = *self {
+            out[2..4].copy_from_slice(&addr.to_be_bytes());
+        }
+        self.size() as usize
+    }
+
+    /// Format the instruction with the Octo assembly syntax
+    pub fn octo(&self) -> OctoInsn<'_> {
+        OctoInsn(self)
+    }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// Conventional mnemonics, from Cowgod's Chip-8 technical reference
+/// (e.g. `DRW V1, V2, 5`, `LD I, 0x2A0`).
+impl fmt::Display for Insn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Insn::Cls => write!(f, "CLS"),
+            Insn::Ret => write!(f, "RET"),
+            Insn::Jump(a) => write!(f, "JP 0x{:03X}", a),
+            Insn::JumpV0(a) => write!(f, "JP V0, 0x{:03X}", a),
+            Insn::Call(a) => write!(f, "CALL 0x{:03X}", a),
+            Insn::SkipEqI(r, v) => write!(f, "SE V{:X}, 0x{:02X}", r, v),
+            Insn::SkipNeqI(r, v) => write!(f, "SNE V{:X}, 0x{:02X}", r, v),
+            Insn::SkipEq(rx, ry) => write!(f, "SE V{:X}, V{:X}", rx, ry),
+            Insn::SkipNeq(rx, ry) => write!(f, "SNE V{:X}, V{:X}", rx, ry),
+            Insn::LoadI(r, v) => write!(f, "LD V{:X}, 0x{:02X}", r, v),
+            Insn::AddI(r, v) => write!(f, "ADD V{:X}, 0x{:02X}", r, v),
+            Insn::Move(rx, ry) => write!(f, "LD V{:X}, V{:X}", rx, ry),
+            Insn::Or(rx, ry) => write!(f, "OR V{:X}, V{:X}", rx, ry),
+            Insn::And(rx, ry) => write!(f, "AND V{:X}, V{:X}", rx, ry),
+            Insn::Xor(rx, ry) => write!(f, "XOR V{:X}, V{:X}", rx, ry),
+            Insn::Add(rx, ry) => write!(f, "ADD V{:X}, V{:X}", rx, ry),
+            Insn::Sub(rx, ry) => write!(f, "SUB V{:X}, V{:X}", rx, ry),
+            Insn::Shr(rx, ry) => write!(f, "SHR V{:X}, V{:X}", rx, ry),
+            Insn::SubN(rx, ry) => write!(f, "SUBN V{:X}, V{:X}", rx, ry),
+            Insn::Shl(rx, ry) => write!(f, "SHL V{:X}, V{:X}", rx, ry),
+            Insn::LoadA(a) => write!(f, "LD I, 0x{:03X}", a),
+            Insn::AddA(r) => write!(f, "ADD I, V{:X}", r),
+            Insn::RndAnd(r, v) => write!(f, "RND V{:X}, 0x{:02X}", r, v),
+            Insn::DrawSprite(rx, ry, n) => write!(f, "DRW V{:X}, V{:X}, {}", rx, ry, n),
+            Insn::SkipKeyPressed(r) => write!(f, "SKP V{:X}", r),
+            Insn::SkipKeyNPressed(r) => write!(f, "SKNP V{:X}", r),
+            Insn::LoadTimer(r) => write!(f, "LD V{:X}, DT", r),
+            Insn::WaitForKey(r) => write!(f, "LD V{:X}, K", r),
+            Insn::SetDelayTimer(r) => write!(f, "LD DT, V{:X}", r),
+            Insn::SetSoundTimer(r) => write!(f, "LD ST, V{:X}", r),
+            Insn::SpriteLoc(r) => write!(f, "LD F, V{:X}", r),
+            Insn::StoreBCD(r) => write!(f, "LD B, V{:X}", r),
+            Insn::StoreRegs(r) => write!(f, "LD [I], V{:X}", r),
+            Insn::LoadRegs(r) => write!(f, "LD V{:X}, [I]", r),
+            Insn::ScrollDown(n) => write!(f, "SCD {}", n),
+            Insn::ScrollRight => write!(f, "SCR"),
+            Insn::ScrollLeft => write!(f, "SCL"),
+            Insn::Exit => write!(f, "EXIT"),
+            Insn::LoRes => write!(f, "LOW"),
+            Insn::HiRes => write!(f, "HIGH"),
+            Insn::BigSpriteLoc(r) => write!(f, "LD HF, V{:X}", r),
+            Insn::StoreFlags(r) => write!(f, "LD R, V{:X}", r),
+            Insn::LoadFlags(r) => write!(f, "LD V{:X}, R", r),
+            Insn::ScrollUp(n) => write!(f, "SCU {}", n),
+            Insn::SaveRange(rx, ry) => write!(f, "SAVE V{:X}, V{:X}", rx, ry),
+            Insn::LoadRange(rx, ry) => write!(f, "LOAD V{:X}, V{:X}", rx, ry),
+            Insn::LoadLongA(a) => write!(f, "LD I, LONG 0x{:04X}", a),
+            Insn::SelectPlanes(n) => write!(f, "PLANE {}", n),
+            Insn::LoadAudio => write!(f, "AUDIO"),
+            Insn::SetPitch(r) => write!(f, "LD PITCH, V{:X}", r),
+        }
+    }
+}
+
+/// Octo syntax of an instruction, see `Insn::octo`. Skips are written as the
+/// equivalent `if ... then` conditions.
+pub struct OctoInsn<'a>(&'a Insn);
+
+impl fmt::Display for OctoInsn<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.0 {
+            Insn::Cls => write!(f, "clear"),
+            Insn::Ret => write!(f, "return"),
+            Insn::Jump(a) => write!(f, "jump 0x{:03X}", a),
+            Insn::JumpV0(a) => write!(f, "jump0 0x{:03X}", a),
+            Insn::Call(a) => write!(f, ":call 0x{:03X}", a),
+            Insn::SkipEqI(r, v) => write!(f, "if v{:x} != 0x{:02X} then", r, v),
+            Insn::SkipNeqI(r, v) => write!(f, "if v{:x} == 0x{:02X} then", r, v),
+            Insn::SkipEq(rx, ry) => write!(f, "if v{:x} != v{:x} then", rx, ry),
+            Insn::SkipNeq(rx, ry) => write!(f, "if v{:x} == v{:x} then", rx, ry),
+            Insn::LoadI(r, v) => write!(f, "v{:x} := 0x{:02X}", r, v),
+            Insn::AddI(r, v) => write!(f, "v{:x} += 0x{:02X}", r, v),
+            Insn::Move(rx, ry) => write!(f, "v{:x} := v{:x}", rx, ry),
+            Insn::Or(rx, ry) => write!(f, "v{:x} |= v{:x}", rx, ry),
+            Insn::And(rx, ry) => write!(f, "v{:x} &= v{:x}", rx, ry),
+            Insn::Xor(rx, ry) => write!(f, "v{:x} ^= v{:x}", rx, ry),
+            Insn::Add(rx, ry) => write!(f, "v{:x} += v{:x}", rx, ry),
+            Insn::Sub(rx, ry) => write!(f, "v{:x} -= v{:x}", rx, ry),
+            Insn::Shr(rx, ry) => write!(f, "v{:x} >>= v{:x}", rx, ry),
+            Insn::SubN(rx, ry) => write!(f, "v{:x} =- v{:x}", rx, ry),
+            Insn::Shl(rx, ry) => write!(f, "v{:x} <<= v{:x}", rx, ry),
+            Insn::LoadA(a) => write!(f, "i := 0x{:03X}", a),
+            Insn::AddA(r) => write!(f, "i += v{:x}", r),
+            Insn::RndAnd(r, v) => write!(f, "v{:x} := random 0x{:02X}", r, v),
+            Insn::DrawSprite(rx, ry, n) => write!(f, "sprite v{:x} v{:x} {}", rx, ry, n),
+            Insn::SkipKeyPressed(r) => write!(f, "if v{:x} -key then", r),
+            Insn::SkipKeyNPressed(r) => write!(f, "if v{:x} key then", r),
+            Insn::LoadTimer(r) => write!(f, "v{:x} := delay", r),
+            Insn::WaitForKey(r) => write!(f, "v{:x} := key", r),
+            Insn::SetDelayTimer(r) => write!(f, "delay := v{:x}", r),
+            Insn::SetSoundTimer(r) => write!(f, "buzzer := v{:x}", r),
+            Insn::SpriteLoc(r) => write!(f, "i := hex v{:x}", r),
+            Insn::StoreBCD(r) => write!(f, "bcd v{:x}", r),
+            Insn::StoreRegs(r) => write!(f, "save v{:x}", r),
+            Insn::LoadRegs(r) => write!(f, "load v{:x}", r),
+            Insn::ScrollDown(n) => write!(f, "scroll-down {}", n),
+            Insn::ScrollRight => write!(f, "scroll-right"),
+            Insn::ScrollLeft => write!(f, "scroll-left"),
+            Insn::Exit => write!(f, "exit"),
+            Insn::LoRes => write!(f, "lores"),
+            Insn::HiRes => write!(f, "hires"),
+            Insn::BigSpriteLoc(r) => write!(f, "i := bighex v{:x}", r),
+            Insn::StoreFlags(r) => write!(f, "saveflags v{:x}", r),
+            Insn::LoadFlags(r) => write!(f, "loadflags v{:x}", r),
+            Insn::ScrollUp(n) => write!(f, "scroll-up {}", n),
+            Insn::SaveRange(rx, ry) => write!(f, "save v{:x} - v{:x}", rx, ry),
+            Insn::LoadRange(rx, ry) => write!(f, "load v{:x} - v{:x}", rx, ry),
+            Insn::LoadLongA(a) => write!(f, "i := long 0x{:04X}", a),
+            Insn::SelectPlanes(n) => write!(f, "plane {}", n),
+            Insn::LoadAudio => write!(f, "audio"),
+            Insn::SetPitch(r) => write!(f, "pitch := v{:x}", r),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum CpuStatus {
     Running,
     WaitEvent,
@@ -198,6 +509,8 @@ pub enum CpuStatus {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Chip8Cpu {
+    platform: Platform,
+    quirks: Quirks,
     status: CpuStatus,
     gpr: [Word; 16],
     reg_i: u16,
@@ -207,19 +520,120 @@ pub struct Chip8Cpu {
     cycles: u64,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum CpuError {
+/// Copy of the complete CPU state, for inspection or to restore it later
+/// with `Chip8Cpu::restore`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CpuSnapshot {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub status: CpuStatus,
+    pub v: [Word; 16],
+    pub i: u16,
+    pub pc: Addr,
+    pub sp: Addr,
+    pub stack: [u16; 16],
+    pub cycles: u64,
+}
+
+/// Rejected write of a CPU register
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RegisterError {
+    /// There is no such general purpose register (V0 to VF)
+    InvalidRegister(u8),
+    /// The stack pointer is larger than the stack
+    InvalidStackPointer(u16),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::InvalidRegister(r) => write!(f, "invalid register V{}", r),
+            RegisterError::InvalidStackPointer(sp) => write!(f, "invalid stack pointer {}", sp),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RegisterError {}
+
+/// Cause of a `CpuError`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CpuErrorKind {
     InvalidInstruction,
     NotImplemented,
-    MemoryError,
+    /// Access past the end of the address space, at `addr` (see
+    /// `Quirks::wrap_memory`)
+    MemoryError { addr: u32 },
     PopEmptyStack,
     StackOverflow,
     InvalidSprite,
 }
 
+impl fmt::Display for CpuErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuErrorKind::InvalidInstruction => write!(f, "invalid opcode"),
+            CpuErrorKind::NotImplemented => write!(f, "unimplemented opcode"),
+            CpuErrorKind::MemoryError { addr } => write!(f, "out of bounds memory access to 0x{:X}", addr),
+            CpuErrorKind::PopEmptyStack => write!(f, "return with an empty stack"),
+            CpuErrorKind::StackOverflow => write!(f, "stack overflow"),
+            CpuErrorKind::InvalidSprite => write!(f, "invalid font sprite"),
+        }
+    }
+}
+
+/// Number of return addresses kept in a `CpuError`
+pub const BACKTRACE_LEN: usize = 4;
+
+/// Failure of an instruction, with the state of the CPU when it happened
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CpuError {
+    pub kind: CpuErrorKind,
+    /// Address of the faulting instruction
+    pub pc: Addr,
+    /// Raw opcode of the instruction (see `Insn::opcode`), none if it could
+    /// not be fetched
+    pub opcode: Option<u32>,
+    /// Value of `Chip8Cpu::cycles`, the faulting instruction included
+    pub cycle: u64,
+    backtrace: [Addr; BACKTRACE_LEN],
+    depth: u8,
+}
+
+impl CpuError {
+    /// Innermost return addresses on the stack, the last call first
+    pub fn backtrace(&self) -> &[Addr] {
+        &self.backtrace[..self.depth as usize]
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind, self.opcode) {
+            (CpuErrorKind::InvalidInstruction | CpuErrorKind::NotImplemented, Some(op)) => {
+                write!(f, "{} 0x{:04X}", self.kind, op)?
+            }
+            (_, Some(op)) => write!(f, "{} by opcode 0x{:04X}", self.kind, op)?,
+            (_, None) => write!(f, "{}", self.kind)?,
+        }
+        write!(f, " at 0x{:03X} (cycle {})", self.pc, self.cycle)?;
+        for (k, addr) in self.backtrace().iter().enumerate() {
+            // Calls are 2 bytes long, the return address follows them
+            let sep = if k == 0 { ", called from" } else { " <" };
+            write!(f, "{} 0x{:03X}", sep, addr.wrapping_sub(2))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CpuError {}
+
 impl Chip8Cpu {
     pub fn new(boot_addr: Addr) -> Self {
         Chip8Cpu {
+            platform: Platform::Chip8,
+            quirks: Quirks::default(),
             status: CpuStatus::Running,
             gpr: [0; 16],
             pc: boot_addr,
@@ -230,30 +644,176 @@ impl Chip8Cpu {
         }
     }
 
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Number of instructions executed since reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    pub fn status(&self) -> &CpuStatus {
+        &self.status
+    }
+
+    pub fn set_status(&mut self, status: CpuStatus) {
+        self.status = status;
+    }
+
+    pub fn pc(&self) -> Addr {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: Addr) {
+        self.pc = pc;
+    }
+
+    /// Stack depth
+    pub fn sp(&self) -> Addr {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: Addr) -> Result<(), RegisterError> {
+        if sp as usize > self.stack.len() {
+            return Err(RegisterError::InvalidStackPointer(sp));
+        }
+        self.sp = sp;
+        Ok(())
+    }
+
+    /// Return addresses, the first `sp` ones are in use
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut [u16; 16] {
+        &mut self.stack
+    }
+
+    pub fn reg_i(&self) -> u16 {
+        self.reg_i
+    }
+
+    pub fn set_reg_i(&mut self, value: u16) {
+        self.reg_i = value;
+    }
+
+    /// General purpose registers V0 to VF
+    pub fn gprs(&self) -> &[Word; 16] {
+        &self.gpr
+    }
+
+    /// Value of VX, None if there is no such register
+    pub fn gpr(&self, r: Reg) -> Option<Word> {
+        self.gpr.get(r as usize).cloned()
+    }
+
+    pub fn set_gpr(&mut self, r: Reg, value: Word) -> Result<(), RegisterError> {
+        let reg = self.gpr.get_mut(r as usize).ok_or(RegisterError::InvalidRegister(r))?;
+        *reg = value;
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            platform: self.platform,
+            quirks: self.quirks,
+            status: self.status,
+            v: self.gpr,
+            i: self.reg_i,
+            pc: self.pc,
+            sp: self.sp,
+            stack: self.stack,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restore a snapshot, the CPU is left unchanged if it is invalid
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) -> Result<(), RegisterError> {
+        if snapshot.sp as usize > self.stack.len() {
+            return Err(RegisterError::InvalidStackPointer(snapshot.sp));
+        }
+        self.platform = snapshot.platform;
+        self.quirks = snapshot.quirks;
+        self.status = snapshot.status;
+        self.gpr = snapshot.v;
+        self.reg_i = snapshot.i;
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.stack = snapshot.stack;
+        self.cycles = snapshot.cycles;
+        Ok(())
+    }
+
+    /// Value of VX, panics if there is no such register (see `gpr`)
     pub fn read_gpr(&self, r: Reg) -> Word {
         assert!(r < 16);
         self.gpr[r as usize]
     }
 
+    /// Set VX, panics if there is no such register (see `set_gpr`)
     pub fn write_gpr(&mut self, r: Reg, value: Word) {
         assert!(r < 16);
         self.gpr[r as usize] = value;
     }
 
-    pub fn write_vf(&mut self, value: Word) {
+    fn write_vf(&mut self, value: Word) {
         self.write_gpr(0xF, value);
     }
 
-    pub fn write_vf_flag(&mut self, value: bool) {
+    fn write_vf_flag(&mut self, value: bool) {
         self.write_gpr(0xF, bool_to_bit(value));
     }
 
+    /// Execute an instruction as if it was at PC, returns the address of the
+    /// next instruction if it is not the following one
     pub fn exec_insn(&mut self, insn: Insn, periph: &mut Chip8Peripherals) -> Result<Option<Addr>, CpuError> {
-        match insn {
-            Insn::Cls => periph.screen.clear(0),
+        self.execute(&insn, periph).map_err(|kind| self.error(kind, Some(insn.opcode())))
+    }
+
+    /// Error of the instruction at PC
+    fn error(&self, kind: CpuErrorKind, opcode: Option<u32>) -> CpuError {
+        let mut backtrace = [0; BACKTRACE_LEN];
+        let depth = (self.sp as usize).min(BACKTRACE_LEN);
+        for (dst, addr) in backtrace.iter_mut().zip(self.stack[..self.sp as usize].iter().rev()) {
+            *dst = *addr;
+        }
+        CpuError {
+            kind,
+            pc: self.pc,
+            opcode,
+            cycle: self.cycles,
+            backtrace,
+            depth: depth as u8,
+        }
+    }
+
+    fn execute(&mut self, insn: &Insn, periph: &mut Chip8Peripherals) -> Result<Option<Addr>, CpuErrorKind> {
+        if insn.platform() > self.platform {
+            return Err(CpuErrorKind::InvalidInstruction);
+        }
+        match *insn {
+            Insn::Cls => periph.screen.clear_planes(periph.screen.selected_planes()),
             Insn::Ret => {
                 if self.sp == 0 {
-                    return Err(CpuError::PopEmptyStack);
+                    return Err(CpuErrorKind::PopEmptyStack);
                 }
                 self.sp -= 1;
                 return Ok(Some(self.stack[self.sp as usize]));
@@ -262,69 +822,81 @@ impl Chip8Cpu {
                 return Ok(Some(target));
             }
             Insn::JumpV0(target) => {
-                let dst = self.read_gpr(0) as u16 + target;
+                let r = if self.quirks.jump_uses_vx { (target >> 8) as Reg } else { 0 };
+                let dst = self.read_gpr(r) as u16 + target;
                 return Ok(Some(dst));
             }
             Insn::Call(target) => {
                 if self.sp == 16 {
-                    return Err(CpuError::StackOverflow);
+                    return Err(CpuErrorKind::StackOverflow);
                 }
-                self.stack[self.sp as usize] = self.pc + 2;
+                self.stack[self.sp as usize] = self.pc.wrapping_add(2);
                 self.sp += 1;
                 return Ok(Some(target));
             }
             Insn::SkipEqI(r, value) => {
                 if self.read_gpr(r) == value {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::SkipNeqI(r, value) => {
                 if self.read_gpr(r) != value {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::SkipEq(rx, ry) => {
                 if self.read_gpr(rx) == self.read_gpr(ry) {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::SkipNeq(rx, ry) => {
                 if self.read_gpr(rx) != self.read_gpr(ry) {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::LoadI(r, v) => self.write_gpr(r, v),
             Insn::AddI(rx, v) => self.write_gpr(rx, self.read_gpr(rx).wrapping_add(v)),
             Insn::Move(rx, ry) => self.write_gpr(rx, self.read_gpr(ry)),
-            Insn::Or(rx, ry) => self.write_gpr(rx, self.read_gpr(rx) | self.read_gpr(ry)),
-            Insn::And(rx, ry) => self.write_gpr(rx, self.read_gpr(rx) & self.read_gpr(ry)),
-            Insn::Xor(rx, ry) => self.write_gpr(rx, self.read_gpr(rx) ^ self.read_gpr(ry)),
+            Insn::Or(rx, ry) => {
+                self.write_gpr(rx, self.read_gpr(rx) | self.read_gpr(ry));
+                self.logic_reset_vf();
+            }
+            Insn::And(rx, ry) => {
+                self.write_gpr(rx, self.read_gpr(rx) & self.read_gpr(ry));
+                self.logic_reset_vf();
+            }
+            Insn::Xor(rx, ry) => {
+                self.write_gpr(rx, self.read_gpr(rx) ^ self.read_gpr(ry));
+                self.logic_reset_vf();
+            }
             Insn::Add(rx, ry) => {
                 let r = self.read_gpr(rx) as u16 + self.read_gpr(ry) as u16;
-                self.write_vf_flag(r >= 256);
+                // The flag is written last, overriding the result if X is F
                 self.write_gpr(rx, r as u8);
+                self.write_vf_flag(r >= 256);
             }
             Insn::Sub(rx, ry) => {
                 let x = self.read_gpr(rx);
                 let y = self.read_gpr(ry);
-                self.write_vf_flag(x > y);
                 self.write_gpr(rx, x.wrapping_sub(y));
+                // VF is set when there is no borrow, so also if x == y
+                self.write_vf_flag(x >= y);
             }
             Insn::SubN(rx, ry) => {
                 let x = self.read_gpr(rx);
                 let y = self.read_gpr(ry);
-                self.write_vf_flag(y > x);
                 self.write_gpr(rx, y.wrapping_sub(x));
+                self.write_vf_flag(y >= x);
             }
-            Insn::Shr(r, _) => {
-                let x = self.gpr[r as usize];
-                self.write_vf(x & 1);
+            Insn::Shr(r, ry) => {
+                let x = self.read_gpr(self.shift_source(r, ry));
                 self.write_gpr(r, x >> 1);
+                self.write_vf(x & 1);
             }
-            Insn::Shl(r, _) => {
-                let x = self.read_gpr(r);
-                self.write_vf(x >> 7);
+            Insn::Shl(r, ry) => {
+                let x = self.read_gpr(self.shift_source(r, ry));
                 self.write_gpr(r, x.shl(1));
+                self.write_vf(x >> 7);
             }
             Insn::LoadA(value) => self.reg_i = value,
             Insn::AddA(r) => {
@@ -335,19 +907,44 @@ impl Chip8Cpu {
                 self.write_gpr(r, rnd & value);
             }
             Insn::DrawSprite(rx, ry, n) => {
-                let x = self.read_gpr(rx);
-                let y = self.read_gpr(ry);
-                let base = self.reg_i as usize;
+                let (w, h) = periph.screen.dims();
+                // The initial coordinates always wrap around
+                let x = self.read_gpr(rx) as u32 % w;
+                let y = self.read_gpr(ry) as u32 % h;
+                // Design note: SUPER-CHIP uses DXY0 for 16x16 sprites, stored
+                // as 16 rows of two bytes.
+                let (rows, cols) = if n == 0 && self.platform >= Platform::SuperChip {
+                    (16u32, 16u32)
+                } else {
+                    (n as u32, 8u32)
+                };
+                let row_bytes = cols as usize / 8;
+                // XO-CHIP: when several planes are selected, the sprite data
+                // for each plane is stored consecutively.
+                let planes = periph.screen.selected_planes().count_ones() as usize;
+                let mut sprite = self.mem_range(self.reg_i as usize, planes * rows as usize * row_bytes)?;
                 let mut erased = false;
-                for dy in 0u8..n {
-                    let mut w = periph.memory[base + dy as usize];
-                    // Design note: lowest bit is the 8-th pixel. Thus, we
-                    // reverse the iteration order.
-                    for dx in (0u8..8).rev() {
-                        let coords = (((x + dx) % SCREEN_W as u8) as i32, ((y + dy) % SCREEN_H as u8) as i32);
-                        let px_value = 0xFFFF_FFFF * (w & 1) as u32;
-                        w >>= 1;
-                        erased |= periph.screen.xor_pixel(coords, px_value as u32);
+                for plane in 0..SCREEN_PLANES {
+                    let mask = 1u8 << plane;
+                    if periph.screen.selected_planes() & mask == 0 {
+                        continue;
+                    }
+                    for dy in 0..rows {
+                        let mut bits = 0u16;
+                        for k in sprite.by_ref().take(row_bytes) {
+                            bits = (bits << 8) | periph.memory[k] as u16;
+                        }
+                        // Design note: lowest bit is the last pixel. Thus, we
+                        // reverse the iteration order.
+                        for dx in (0..cols).rev() {
+                            let (px, py) = (x + dx, y + dy);
+                            let visible = !self.quirks.clip_sprites || (px < w && py < h);
+                            if bits & 1 != 0 && visible {
+                                let coords = ((px % w) as i32, (py % h) as i32);
+                                erased |= periph.screen.xor_pixel(coords, mask);
+                            }
+                            bits >>= 1;
+                        }
                     }
                 }
                 self.write_vf_flag(erased);
@@ -355,13 +952,13 @@ impl Chip8Cpu {
             Insn::SkipKeyPressed(rx) => {
                 let key = self.read_gpr(rx);
                 if periph.keypad.key_state(key) == 1 {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::SkipKeyNPressed(rx) => {
                 let key = self.read_gpr(rx);
                 if periph.keypad.key_state(key) == 0 {
-                    return Ok(Some(self.pc + 4));
+                    return Ok(Some(self.skip_target(periph)));
                 }
             }
             Insn::LoadTimer(rx) => self.write_gpr(rx, periph.delay_timer as u8),
@@ -381,48 +978,262 @@ impl Chip8Cpu {
                 // TODO: has to be checked...
                 let x = self.read_gpr(rx);
                 if x >= 16 {
-                    return Err(CpuError::InvalidSprite);
+                    return Err(CpuErrorKind::InvalidSprite);
                 }
                 self.reg_i = 5 * x as u16;
             }
             Insn::StoreBCD(rx) => {
-                let mut x = self.read_gpr(rx);
-                for i in (0..3).rev() {
-                    periph.memory[self.reg_i as usize + i] = x % 10;
-                    x /= 10;
+                let x = self.read_gpr(rx);
+                let digits = [x / 100, x / 10 % 10, x % 10];
+                for (k, digit) in self.mem_range(self.reg_i as usize, 3)?.zip(digits) {
+                    periph.memory[k] = digit;
                 }
             }
             Insn::StoreRegs(n) => {
-                for i in 0..=(n as usize) {
-                    periph.memory[self.reg_i as usize + i] = self.gpr[i];
+                for (k, r) in self.mem_range(self.reg_i as usize, n as usize + 1)?.zip(0..=n) {
+                    periph.memory[k] = self.read_gpr(r);
                 }
+                self.load_store_update_i(n);
             }
             Insn::LoadRegs(n) => {
-                for i in 0..=(n as usize) {
-                    self.gpr[i] = periph.memory[self.reg_i as usize + i];
+                for (k, r) in self.mem_range(self.reg_i as usize, n as usize + 1)?.zip(0..=n) {
+                    self.write_gpr(r, periph.memory[k]);
+                }
+                self.load_store_update_i(n);
+            }
+            Insn::ScrollDown(n) => periph.screen.scroll_down(n as u32),
+            Insn::ScrollRight => periph.screen.scroll_right(4),
+            Insn::ScrollLeft => periph.screen.scroll_left(4),
+            Insn::Exit => {
+                self.status = CpuStatus::Halted;
+                return Ok(Some(self.pc));
+            }
+            Insn::LoRes => periph.screen.set_hires(false),
+            Insn::HiRes => periph.screen.set_hires(true),
+            Insn::BigSpriteLoc(rx) => {
+                let x = self.read_gpr(rx);
+                if x >= 16 {
+                    return Err(CpuErrorKind::InvalidSprite);
+                }
+                self.reg_i = BIG_SPRITE_ADDR + 10 * x as u16;
+            }
+            Insn::StoreFlags(n) => {
+                periph.rpl_flags[..=(n as usize)].copy_from_slice(&self.gpr[..=(n as usize)]);
+            }
+            Insn::LoadFlags(n) => {
+                self.gpr[..=(n as usize)].copy_from_slice(&periph.rpl_flags[..=(n as usize)]);
+            }
+            Insn::ScrollUp(n) => periph.screen.scroll_up(n as u32),
+            Insn::SaveRange(rx, ry) => {
+                // Registers are stored in the order given, possibly reversed
+                let len = ry.abs_diff(rx) as usize + 1;
+                for (k, r) in self.mem_range(self.reg_i as usize, len)?.zip(Self::reg_range(rx, ry)) {
+                    periph.memory[k] = self.read_gpr(r);
+                }
+            }
+            Insn::LoadRange(rx, ry) => {
+                let len = ry.abs_diff(rx) as usize + 1;
+                for (k, r) in self.mem_range(self.reg_i as usize, len)?.zip(Self::reg_range(rx, ry)) {
+                    self.write_gpr(r, periph.memory[k]);
                 }
             }
+            Insn::LoadLongA(addr) => self.reg_i = addr,
+            Insn::SelectPlanes(mask) => periph.screen.select_planes(mask),
+            Insn::LoadAudio => {
+                for (i, k) in self.mem_range(self.reg_i as usize, 16)?.enumerate() {
+                    periph.audio_pattern[i] = periph.memory[k];
+                }
+            }
+            Insn::SetPitch(rx) => periph.pitch = self.read_gpr(rx),
         }
         Ok(None)
     }
 
-    pub fn tick(&mut self, periph: &mut Chip8Peripherals) -> Result<(), CpuError> {
-        self.cycles += 1;
-        let pc = self.pc as usize;
-        if pc > 0x4096 {
-            return Err(CpuError::MemoryError);
+    /// Register shifted by `Shr`/`Shl`
+    fn shift_source(&self, rx: Reg, ry: Reg) -> Reg {
+        if self.quirks.shift_uses_vy { ry } else { rx }
+    }
+
+    fn logic_reset_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.write_vf(0);
+        }
+    }
+
+    fn load_store_update_i(&mut self, n: Reg) {
+        let inc = match self.quirks.load_store_index {
+            IndexQuirk::Unchanged => 0,
+            IndexQuirk::IncrementX => n as u16,
+            IndexQuirk::IncrementXPlusOne => n as u16 + 1,
+        };
+        self.reg_i = self.reg_i.wrapping_add(inc);
+    }
+
+    /// Registers from rx to ry (inclusive), in decreasing order if rx > ry
+    fn reg_range(rx: Reg, ry: Reg) -> impl Iterator<Item=Reg> {
+        let n = ry.abs_diff(rx);
+        (0..=n).map(move |k| if rx <= ry { rx + k } else { rx - k })
+    }
+
+    /// Indices in `Chip8Peripherals::memory` of the `len` bytes at `addr`.
+    /// Past the end of the address space, they wrap around or the access
+    /// fails as a whole, depending on `Quirks::wrap_memory`.
+    fn mem_range(&self, addr: usize, len: usize) -> Result<impl Iterator<Item=usize>, CpuErrorKind> {
+        let size = self.platform.memory_size();
+        if !self.quirks.wrap_memory && addr + len > size {
+            // First address out of bounds
+            let addr = addr.max(size) as u32;
+            return Err(CpuErrorKind::MemoryError { addr });
+        }
+        Ok((addr..addr + len).map(move |k| k % size))
+    }
+
+    fn read_insn_word(&self, periph: &Chip8Peripherals, addr: Addr) -> Result<u16, CpuErrorKind> {
+        let mut bytes = self.mem_range(addr as usize, 2)?;
+        let hi = periph.memory[bytes.next().unwrap()];
+        let lo = periph.memory[bytes.next().unwrap()];
+        Ok(u16::from_be_bytes([hi, lo]))
+    }
+
+    /// Address of the instruction following the next one, for skips. On
+    /// XO-CHIP, the next instruction may be 4 bytes long.
+    fn skip_target(&self, periph: &Chip8Peripherals) -> Addr {
+        let next = self.pc.wrapping_add(2);
+        // A next instruction out of bounds is skipped as a 2-byte one
+        let is_long = self.platform >= Platform::XoChip && self.read_insn_word(periph, next) == Ok(0xF000);
+        next.wrapping_add(if is_long { 4 } else { 2 })
+    }
+
+    /// Decode the instruction at PC, returns it along with its raw opcode
+    /// (32 bits for the XO-CHIP long instructions)
+    pub(crate) fn fetch(&self, periph: &Chip8Peripherals) -> Result<(Insn, u32), CpuError> {
+        let unreadable = |kind| self.error(kind, None);
+        let insn_raw = self.read_insn_word(periph, self.pc).map_err(unreadable)?;
+        let (insn, opcode) = if insn_raw == 0xF000 {
+            let next = self.read_insn_word(periph, self.pc.wrapping_add(2)).map_err(unreadable)?;
+            (Insn::decode_long(insn_raw, next), (insn_raw as u32) << 16 | next as u32)
+        } else {
+            (Insn::decode(insn_raw), insn_raw as u32)
+        };
+        match insn {
+            Some(insn) => Ok((insn, opcode)),
+            None => Err(self.error(CpuErrorKind::InvalidInstruction, Some(opcode))),
         }
-        let insn_raw = u16::from_be_bytes(periph.memory[pc..pc + 2].try_into().unwrap());
-        let insn = Insn::decode(insn_raw).ok_or(CpuError::InvalidInstruction)?;
+    }
 
-        // Debug: instruction tracing
-        // println!("{:4x}> {:4x}> {:?}", pc, insn_raw, insn);
+    /// Memory range accessed by an instruction about to be executed, as
+    /// (address, length, is a write). Instruction fetches are not included.
+    pub(crate) fn memory_access(&self, insn: &Insn, periph: &Chip8Peripherals) -> Option<(u16, u16, bool)> {
+        let i = self.reg_i;
+        match *insn {
+            Insn::DrawSprite(_, _, n) => {
+                let size = if n == 0 && self.platform >= Platform::SuperChip { 32 } else { n as u16 };
+                let planes = periph.screen.selected_planes().count_ones() as u16;
+                Some((i, size * planes, false))
+            }
+            Insn::StoreBCD(_) => Some((i, 3, true)),
+            Insn::StoreRegs(n) => Some((i, n as u16 + 1, true)),
+            Insn::LoadRegs(n) => Some((i, n as u16 + 1, false)),
+            Insn::SaveRange(rx, ry) => Some((i, ry.abs_diff(rx) as u16 + 1, true)),
+            Insn::LoadRange(rx, ry) => Some((i, ry.abs_diff(rx) as u16 + 1, false)),
+            Insn::LoadAudio => Some((i, 16, false)),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, periph: &mut Chip8Peripherals) -> Result<(), CpuError> {
+        if self.status == CpuStatus::Halted {
+            return Ok(());
+        }
+        self.cycles += 1;
+        let (insn, _) = self.fetch(periph)?;
+        let size = insn.size();
 
         let r = self.exec_insn(insn, periph)?;
         match r {
             Some(new_pc) => self.pc = new_pc,
-            None => self.pc += 2
+            None => self.pc = self.pc.wrapping_add(size)
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Same as `tick`, reporting the executed instruction to `hook`
+    pub fn tick_traced(&mut self, periph: &mut Chip8Peripherals, hook: &mut dyn TraceHook) -> Result<(), CpuError> {
+        if self.status == CpuStatus::Halted {
+            return Ok(());
+        }
+        let before = self.snapshot();
+        let fetched = self.fetch(periph);
+        self.tick(periph)?;
+        // The instruction was executed, so it was fetched successfully
+        if let Ok((insn, opcode)) = fetched {
+            hook.trace(&TraceEvent {
+                pc: before.pc,
+                opcode,
+                insn: &insn,
+                before: &before,
+                after: &self.snapshot(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.platform as u8);
+        w.bool(self.quirks.shift_uses_vy);
+        w.u8(self.quirks.load_store_index as u8);
+        w.bool(self.quirks.jump_uses_vx);
+        w.bool(self.quirks.clip_sprites);
+        w.bool(self.quirks.logic_resets_vf);
+        w.bool(self.quirks.wrap_memory);
+        w.u8(self.status as u8);
+        w.bytes(&self.gpr);
+        w.u16(self.reg_i);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u16(self.pc);
+        w.u16(self.sp);
+        w.u64(self.cycles);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.platform = match r.u8() {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(StateError::InvalidValue("platform")),
+        };
+        self.quirks = Quirks {
+            shift_uses_vy: r.bool("quirks.shift_uses_vy")?,
+            load_store_index: match r.u8() {
+                0 => IndexQuirk::Unchanged,
+                1 => IndexQuirk::IncrementX,
+                2 => IndexQuirk::IncrementXPlusOne,
+                _ => return Err(StateError::InvalidValue("quirks.load_store_index")),
+            },
+            jump_uses_vx: r.bool("quirks.jump_uses_vx")?,
+            clip_sprites: r.bool("quirks.clip_sprites")?,
+            logic_resets_vf: r.bool("quirks.logic_resets_vf")?,
+            wrap_memory: r.bool("quirks.wrap_memory")?,
+        };
+        self.status = match r.u8() {
+            0 => CpuStatus::Running,
+            1 => CpuStatus::WaitEvent,
+            2 => CpuStatus::Halted,
+            _ => return Err(StateError::InvalidValue("status")),
+        };
+        r.bytes(&mut self.gpr);
+        self.reg_i = r.u16();
+        for addr in self.stack.iter_mut() {
+            *addr = r.u16();
+        }
+        self.pc = r.u16();
+        self.sp = r.u16();
+        if self.sp as usize > self.stack.len() {
+            return Err(StateError::InvalidValue("sp"));
+        }
+        self.cycles = r.u64();
+        Ok(())
+    }
+}
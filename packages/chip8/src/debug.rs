@@ -0,0 +1,600 @@
+//
+// Debugger: breakpoints, watchpoints and stepping
+//
+// The conditions are checked by `Chip8Emulator::tick`, which stops and
+// returns a `StopReason` as soon as one of them is met. Breakpoints and
+// instruction classes stop *before* the instruction executes, watchpoints,
+// register conditions and steps stop *after* it.
+//
+// Fixed-size tables are used, so that the debugger works without an
+// allocator.
+use crate::cpu::{Chip8Cpu, CpuStatus, Insn};
+use crate::Chip8Peripherals;
+
+pub const MAX_BREAKPOINTS: usize = 16;
+pub const MAX_WATCHPOINTS: usize = 8;
+pub const MAX_REG_CONDITIONS: usize = 8;
+
+/// Kind of memory access watched
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes
+    Access,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    /// Number of bytes watched, from `addr`
+    pub len: u16,
+    pub kind: WatchKind,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RegCondition {
+    /// The register is modified
+    Changed,
+    /// The register is modified and takes the given value
+    Equals(u16),
+}
+
+/// Families of instructions, to break on all the instructions of a family
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InsnClass {
+    /// `Jump`, `JumpV0`
+    Jump,
+    Call,
+    /// `Ret`, `Exit`
+    Ret,
+    /// Conditional skips, including the keypad ones
+    Skip,
+    /// Register loads and arithmetic
+    Alu,
+    RndAnd,
+    /// Changes of the I register
+    Index,
+    /// Loads and stores between the registers and the memory
+    Memory,
+    DrawSprite,
+    /// Other screen instructions: clear, scroll, resolution, planes
+    Screen,
+    WaitForKey,
+    /// Delay and sound timers
+    Timer,
+    /// SUPER-CHIP persistent flags
+    Flags,
+    /// XO-CHIP audio pattern and pitch
+    Audio,
+}
+
+impl Insn {
+    pub fn class(&self) -> InsnClass {
+        match self {
+            Insn::Jump(_) | Insn::JumpV0(_) => InsnClass::Jump,
+            Insn::Call(_) => InsnClass::Call,
+            Insn::Ret | Insn::Exit => InsnClass::Ret,
+            Insn::SkipEqI(_, _)
+            | Insn::SkipNeqI(_, _)
+            | Insn::SkipEq(_, _)
+            | Insn::SkipNeq(_, _)
+            | Insn::SkipKeyPressed(_)
+            | Insn::SkipKeyNPressed(_) => InsnClass::Skip,
+            Insn::LoadI(_, _)
+            | Insn::AddI(_, _)
+            | Insn::Move(_, _)
+            | Insn::Or(_, _)
+            | Insn::And(_, _)
+            | Insn::Xor(_, _)
+            | Insn::Add(_, _)
+            | Insn::Sub(_, _)
+            | Insn::Shr(_, _)
+            | Insn::SubN(_, _)
+            | Insn::Shl(_, _) => InsnClass::Alu,
+            Insn::RndAnd(_, _) => InsnClass::RndAnd,
+            Insn::LoadA(_)
+            | Insn::LoadLongA(_)
+            | Insn::AddA(_)
+            | Insn::SpriteLoc(_)
+            | Insn::BigSpriteLoc(_) => InsnClass::Index,
+            Insn::StoreBCD(_)
+            | Insn::StoreRegs(_)
+            | Insn::LoadRegs(_)
+            | Insn::SaveRange(_, _)
+            | Insn::LoadRange(_, _) => InsnClass::Memory,
+            Insn::DrawSprite(_, _, _) => InsnClass::DrawSprite,
+            Insn::Cls
+            | Insn::ScrollDown(_)
+            | Insn::ScrollUp(_)
+            | Insn::ScrollRight
+            | Insn::ScrollLeft
+            | Insn::LoRes
+            | Insn::HiRes
+            | Insn::SelectPlanes(_) => InsnClass::Screen,
+            Insn::WaitForKey(_) => InsnClass::WaitForKey,
+            Insn::LoadTimer(_) | Insn::SetDelayTimer(_) | Insn::SetSoundTimer(_) => InsnClass::Timer,
+            Insn::StoreFlags(_) | Insn::LoadFlags(_) => InsnClass::Flags,
+            Insn::LoadAudio | Insn::SetPitch(_) => InsnClass::Audio,
+        }
+    }
+}
+
+/// Why `Chip8Emulator::tick` returned
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StopReason {
+    /// All the requested steps were executed
+    Completed,
+    /// PC reached a breakpoint, the instruction is not executed yet
+    Breakpoint(u16),
+    /// The instruction at `pc` accessed the watched address `addr`
+    Watchpoint { pc: u16, addr: u16, write: bool },
+    /// The instruction at `pc` met a register condition
+    Register { pc: u16, reg: Register },
+    /// The instruction at PC belongs to a watched class, it is not executed
+    /// yet
+    InsnClass(InsnClass),
+    /// The step requested with `Chip8Emulator::step_*` is complete
+    Step,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum StepMode {
+    None,
+    Into,
+    /// Stop when the stack depth is back to the given one, or lower
+    Over(u16),
+    /// Stop when the stack depth is lower than the given one
+    Out(u16),
+}
+
+/// State of the CPU before an instruction, to evaluate the conditions
+/// checked after it
+pub(crate) struct PreExec {
+    pc: u16,
+    regs: [u16; MAX_REG_CONDITIONS],
+    access: Option<(u16, u16, bool)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    breakpoints: [Option<u16>; MAX_BREAKPOINTS],
+    watchpoints: [Option<Watchpoint>; MAX_WATCHPOINTS],
+    reg_conditions: [Option<(Register, RegCondition)>; MAX_REG_CONDITIONS],
+    /// Bit set of the `InsnClass` to break on
+    classes: u16,
+    step: StepMode,
+    /// PC of the last stop before an instruction, which must not stop again
+    /// when resuming
+    resume_pc: Option<u16>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Insert `value` in the first free slot, returns false if the table is full
+fn insert<T: PartialEq>(table: &mut [Option<T>], value: T) -> bool {
+    if table.iter().any(|v| v.as_ref() == Some(&value)) {
+        return true;
+    }
+    match table.iter_mut().find(|v| v.is_none()) {
+        Some(slot) => {
+            *slot = Some(value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove `value`, returns false if it is not in the table
+fn remove<T: PartialEq>(table: &mut [Option<T>], value: &T) -> bool {
+    match table.iter_mut().find(|v| v.as_ref() == Some(value)) {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: [None; MAX_BREAKPOINTS],
+            watchpoints: [None; MAX_WATCHPOINTS],
+            reg_conditions: [None; MAX_REG_CONDITIONS],
+            classes: 0,
+            step: StepMode::None,
+            resume_pc: None,
+        }
+    }
+
+    /// Remove all the breakpoints, watchpoints and conditions
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// True if there is anything to check while running
+    pub fn is_active(&self) -> bool {
+        self.step != StepMode::None
+            || self.classes != 0
+            || self.breakpoints.iter().any(Option::is_some)
+            || self.watchpoints.iter().any(Option::is_some)
+            || self.reg_conditions.iter().any(Option::is_some)
+    }
+
+    /// Returns false if all the breakpoint slots are used
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        insert(&mut self.breakpoints, addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        remove(&mut self.breakpoints, &addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().flatten().cloned()
+    }
+
+    /// Returns false if all the watchpoint slots are used
+    pub fn add_watchpoint(&mut self, wp: Watchpoint) -> bool {
+        insert(&mut self.watchpoints, wp)
+    }
+
+    pub fn remove_watchpoint(&mut self, wp: &Watchpoint) -> bool {
+        remove(&mut self.watchpoints, wp)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter().flatten()
+    }
+
+    /// Returns false if all the condition slots are used
+    pub fn add_reg_condition(&mut self, reg: Register, cond: RegCondition) -> bool {
+        insert(&mut self.reg_conditions, (reg, cond))
+    }
+
+    pub fn remove_reg_condition(&mut self, reg: Register, cond: RegCondition) -> bool {
+        remove(&mut self.reg_conditions, &(reg, cond))
+    }
+
+    pub fn reg_conditions(&self) -> impl Iterator<Item = &(Register, RegCondition)> {
+        self.reg_conditions.iter().flatten()
+    }
+
+    /// Enable or disable breaking before each instruction of a class
+    pub fn break_on(&mut self, class: InsnClass, enable: bool) {
+        if enable {
+            self.classes |= 1 << class as u16;
+        } else {
+            self.classes &= !(1 << class as u16);
+        }
+    }
+
+    pub fn breaks_on(&self, class: InsnClass) -> bool {
+        self.classes & (1 << class as u16) != 0
+    }
+
+    pub(crate) fn resume(&mut self, cpu: &Chip8Cpu) {
+        self.resume_pc = Some(cpu.pc());
+    }
+
+    pub(crate) fn step_into(&mut self, cpu: &Chip8Cpu) {
+        self.resume(cpu);
+        self.step = StepMode::Into;
+    }
+
+    pub(crate) fn step_over(&mut self, cpu: &Chip8Cpu) {
+        self.resume(cpu);
+        self.step = StepMode::Over(cpu.sp());
+    }
+
+    pub(crate) fn step_out(&mut self, cpu: &Chip8Cpu) {
+        self.resume(cpu);
+        self.step = StepMode::Out(cpu.sp());
+    }
+
+    /// Checks done before executing the instruction at PC. Returns the
+    /// reason to stop, or the state needed by `after_insn`.
+    pub(crate) fn before_insn(&mut self, cpu: &Chip8Cpu, periph: &Chip8Peripherals) -> Result<PreExec, StopReason> {
+        let pc = cpu.pc();
+        // While waiting for a key (or halted), the same instruction is
+        // executed again and again: only stop the first time.
+        let resuming = self.resume_pc.take() == Some(pc)
+            || matches!(cpu.status(), CpuStatus::WaitEvent | CpuStatus::Halted);
+        // Invalid instructions are reported by the CPU
+        let insn = cpu.fetch(periph).ok().map(|(insn, _)| insn);
+        if !resuming {
+            let reason = if self.breakpoints.contains(&Some(pc)) {
+                Some(StopReason::Breakpoint(pc))
+            } else {
+                insn.as_ref()
+                    .map(Insn::class)
+                    .filter(|c| self.breaks_on(*c))
+                    .map(StopReason::InsnClass)
+            };
+            if let Some(reason) = reason {
+                self.resume_pc = Some(pc);
+                return Err(reason);
+            }
+        }
+        let mut regs = [0; MAX_REG_CONDITIONS];
+        for (v, cond) in regs.iter_mut().zip(self.reg_conditions.iter()) {
+            if let Some((reg, _)) = cond {
+                *v = read_reg(cpu, *reg);
+            }
+        }
+        let access = insn.and_then(|insn| cpu.memory_access(&insn, periph));
+        Ok(PreExec { pc, regs, access })
+    }
+
+    /// Checks done after executing an instruction
+    pub(crate) fn after_insn(&mut self, pre: &PreExec, cpu: &Chip8Cpu) -> Option<StopReason> {
+        let pc = pre.pc;
+        if let Some((start, len, write)) = pre.access {
+            let end = start as u32 + len as u32;
+            for wp in self.watchpoints.iter().flatten() {
+                let matches = match wp.kind {
+                    WatchKind::Read => !write,
+                    WatchKind::Write => write,
+                    WatchKind::Access => true,
+                };
+                let wp_end = wp.addr as u32 + wp.len as u32;
+                if matches && (start as u32) < wp_end && (wp.addr as u32) < end {
+                    let addr = start.max(wp.addr);
+                    return Some(StopReason::Watchpoint { pc, addr, write });
+                }
+            }
+        }
+        for (old, cond) in pre.regs.iter().zip(self.reg_conditions.iter()) {
+            if let Some((reg, cond)) = cond {
+                let new = read_reg(cpu, *reg);
+                let hit = match cond {
+                    RegCondition::Changed => new != *old,
+                    RegCondition::Equals(v) => new != *old && new == *v,
+                };
+                if hit {
+                    return Some(StopReason::Register { pc, reg: *reg });
+                }
+            }
+        }
+        let step_done = match self.step {
+            StepMode::None => false,
+            StepMode::Into => true,
+            StepMode::Over(depth) => cpu.sp() <= depth,
+            StepMode::Out(depth) => cpu.sp() < depth,
+        };
+        if step_done {
+            self.step = StepMode::None;
+            return Some(StopReason::Step);
+        }
+        None
+    }
+}
+
+fn read_reg(cpu: &Chip8Cpu, reg: Register) -> u16 {
+    match reg {
+        Register::V(r) => cpu.read_gpr(r & 0xF) as u16,
+        Register::I => cpu.reg_i(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Main program, calling a subroutine which stores and loads registers
+    const PROGRAM: &[(u16, Insn)] = &[
+        (0x200, Insn::LoadI(0, 5)),
+        (0x202, Insn::LoadA(0x300)),
+        (0x204, Insn::Call(0x20C)),
+        (0x206, Insn::LoadI(1, 7)),
+        (0x208, Insn::Jump(0x208)),
+        (0x20C, Insn::StoreRegs(1)),
+        (0x20E, Insn::LoadRegs(0)),
+        (0x210, Insn::Ret),
+    ];
+
+    struct Machine {
+        cpu: Chip8Cpu,
+        periph: Chip8Peripherals,
+        dbg: Debugger,
+    }
+
+    impl Machine {
+        fn new(program: &[(u16, Insn)]) -> Self {
+            let mut periph = Chip8Peripherals::new();
+            for (addr, insn) in program {
+                let addr = *addr as usize;
+                insn.write_bytes(&mut periph.memory[addr..addr + 4]);
+            }
+            Machine { cpu: Chip8Cpu::new(0x200), periph, dbg: Debugger::new() }
+        }
+
+        /// Execute up to `n` instructions the way `Chip8Emulator::tick`
+        /// does, returns the reason to stop and the number executed
+        fn run(&mut self, n: usize) -> (StopReason, usize) {
+            for i in 0..n {
+                let pre = match self.dbg.before_insn(&self.cpu, &self.periph) {
+                    Ok(pre) => pre,
+                    Err(reason) => return (reason, i),
+                };
+                self.cpu.tick(&mut self.periph).unwrap();
+                if let Some(reason) = self.dbg.after_insn(&pre, &self.cpu) {
+                    return (reason, i + 1);
+                }
+            }
+            (StopReason::Completed, n)
+        }
+
+        fn run_watching(wp: Watchpoint) -> (StopReason, usize) {
+            let mut m = Machine::new(PROGRAM);
+            assert!(m.dbg.add_watchpoint(wp));
+            m.run(10)
+        }
+    }
+
+    fn watch(addr: u16, len: u16, kind: WatchKind) -> Watchpoint {
+        Watchpoint { addr, len, kind }
+    }
+
+    #[test]
+    fn watchpoints() {
+        // STORE V0-V1 at 0x300 then LOAD V0 from 0x300
+        let store = |addr| StopReason::Watchpoint { pc: 0x20C, addr, write: true };
+        let load = StopReason::Watchpoint { pc: 0x20E, addr: 0x300, write: false };
+        assert_eq!(Machine::run_watching(watch(0x301, 1, WatchKind::Write)), (store(0x301), 4));
+        assert_eq!(Machine::run_watching(watch(0x2F0, 0x20, WatchKind::Write)), (store(0x300), 4));
+        assert_eq!(Machine::run_watching(watch(0x300, 1, WatchKind::Read)), (load, 5));
+        assert_eq!(Machine::run_watching(watch(0x301, 1, WatchKind::Read)).0, StopReason::Completed);
+        assert_eq!(Machine::run_watching(watch(0x2FF, 2, WatchKind::Access)), (store(0x300), 4));
+        // Just outside of the accesses, and a range past the end of memory
+        assert_eq!(Machine::run_watching(watch(0x302, 8, WatchKind::Access)).0, StopReason::Completed);
+        assert_eq!(Machine::run_watching(watch(0x2F0, 0x10, WatchKind::Access)).0, StopReason::Completed);
+        assert_eq!(Machine::run_watching(watch(0xFFF0, 0xFFFF, WatchKind::Access)).0, StopReason::Completed);
+
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_watchpoint(watch(0x300, 1, WatchKind::Access));
+        assert_eq!(m.run(10), (store(0x300), 4));
+        // Stopped after the instruction, the next one triggers it again
+        assert_eq!(m.cpu.pc(), 0x20E);
+        assert_eq!(m.run(10), (load, 1));
+        assert!(m.dbg.remove_watchpoint(&watch(0x300, 1, WatchKind::Access)));
+        assert!(!m.dbg.is_active());
+    }
+
+    #[test]
+    fn watchpoint_slots() {
+        let mut dbg = Debugger::new();
+        for addr in 0..MAX_WATCHPOINTS as u16 {
+            assert!(dbg.add_watchpoint(watch(addr, 1, WatchKind::Read)));
+        }
+        // Adding an existing one needs no slot
+        assert!(dbg.add_watchpoint(watch(0, 1, WatchKind::Read)));
+        assert!(!dbg.add_watchpoint(watch(0, 1, WatchKind::Write)));
+        assert_eq!(dbg.watchpoints().count(), MAX_WATCHPOINTS);
+    }
+
+    #[test]
+    fn register_conditions() {
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_reg_condition(Register::V(0), RegCondition::Equals(5));
+        assert_eq!(m.run(10), (StopReason::Register { pc: 0x200, reg: Register::V(0) }, 1));
+
+        // Loaded again with the same value: not a change
+        m.dbg.clear();
+        m.dbg.add_reg_condition(Register::V(0), RegCondition::Changed);
+        m.dbg.add_reg_condition(Register::V(1), RegCondition::Equals(8));
+        m.dbg.add_reg_condition(Register::I, RegCondition::Changed);
+        assert_eq!(m.run(10), (StopReason::Register { pc: 0x202, reg: Register::I }, 1));
+        assert!(m.dbg.remove_reg_condition(Register::I, RegCondition::Changed));
+        // V1 changes to 7, not 8
+        assert_eq!(m.run(10).0, StopReason::Completed);
+        assert_eq!(m.cpu.gpr(1), Some(7));
+    }
+
+    #[test]
+    fn insn_class() {
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.break_on(InsnClass::Call, true);
+        m.dbg.break_on(InsnClass::Ret, true);
+        assert!(m.dbg.breaks_on(InsnClass::Call) && !m.dbg.breaks_on(InsnClass::Jump));
+        // Stopped before the instruction
+        assert_eq!(m.run(10), (StopReason::InsnClass(InsnClass::Call), 2));
+        assert_eq!((m.cpu.pc(), m.cpu.sp()), (0x204, 0));
+        m.dbg.resume(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::InsnClass(InsnClass::Ret), 3));
+        assert_eq!(m.cpu.pc(), 0x210);
+
+        m.dbg.break_on(InsnClass::Ret, false);
+        m.dbg.break_on(InsnClass::Jump, true);
+        m.dbg.resume(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::InsnClass(InsnClass::Jump), 2));
+        assert_eq!(m.cpu.pc(), 0x208);
+    }
+
+    #[test]
+    fn step_over_out() {
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_breakpoint(0x204);
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x204), 2));
+
+        // Over the call: the whole subroutine runs
+        m.dbg.step_over(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Step, 4));
+        assert_eq!((m.cpu.pc(), m.cpu.sp()), (0x206, 0));
+        // Over another instruction: just that one
+        m.dbg.step_over(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Step, 1));
+        assert_eq!(m.cpu.pc(), 0x208);
+
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_breakpoint(0x204);
+        m.run(10);
+        m.dbg.step_into(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Step, 1));
+        assert_eq!(m.cpu.pc(), 0x20C);
+        m.dbg.step_into(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Step, 1));
+        // Out of the subroutine, right after the call
+        m.dbg.step_out(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Step, 2));
+        assert_eq!((m.cpu.pc(), m.cpu.sp()), (0x206, 0));
+
+        // A breakpoint in the subroutine stops the step
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_breakpoint(0x204);
+        m.dbg.add_breakpoint(0x20E);
+        m.run(10);
+        m.dbg.step_over(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x20E), 2));
+    }
+
+    #[test]
+    fn resume() {
+        // Jump to itself
+        let mut m = Machine::new(PROGRAM);
+        m.dbg.add_breakpoint(0x208);
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x208), 7));
+        // Going on executes it once, then it stops again
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x208), 1));
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x208), 1));
+
+        // Stopped after an instruction, on a breakpoint: it stops there,
+        // unless resumed
+        for resumed in [false, true] {
+            let mut m = Machine::new(PROGRAM);
+            m.dbg.add_breakpoint(0x20E);
+            m.dbg.add_watchpoint(watch(0x300, 1, WatchKind::Write));
+            assert_eq!(m.run(10).0, StopReason::Watchpoint { pc: 0x20C, addr: 0x300, write: true });
+            if resumed {
+                m.dbg.resume(&m.cpu);
+                assert_eq!(m.run(10), (StopReason::Completed, 10));
+            } else {
+                assert_eq!(m.run(10), (StopReason::Breakpoint(0x20E), 0));
+            }
+        }
+
+        // Waiting for a key executes the same instruction again: it only
+        // stops the first time
+        let mut m = Machine::new(&[(0x200, Insn::WaitForKey(0)), (0x202, Insn::Jump(0x200))]);
+        m.dbg.add_breakpoint(0x200);
+        m.dbg.break_on(InsnClass::WaitForKey, true);
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x200), 0));
+        m.dbg.resume(&m.cpu);
+        assert_eq!(m.run(10), (StopReason::Completed, 10));
+        // Once a key is pressed, it goes on and stops on the next visit
+        m.periph.keypad.key_pressed(3);
+        assert_eq!(m.run(10), (StopReason::Breakpoint(0x200), 2));
+        assert_eq!(m.cpu.gpr(0), Some(3));
+    }
+}
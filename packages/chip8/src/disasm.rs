@@ -0,0 +1,342 @@
+//
+// Static disassembler
+//
+// Follows the control flow from the entry point to separate code from data,
+// instead of decoding the ROM in fixed 2-byte steps. Sprite data is found by
+// tracking the last constant loaded into I before each `DrawSprite`.
+use core::convert::TryInto;
+use core::fmt;
+
+use crate::cpu::{Insn, Platform};
+use crate::emu::MEMORY_SIZE;
+
+// Per-byte analysis flags
+const INSN_START: u8 = 1;
+const INSN_BODY: u8 = 1 << 1;
+const PENDING: u8 = 1 << 2;
+const JUMP_TARGET: u8 = 1 << 3;
+const CALL_TARGET: u8 = 1 << 4;
+const DATA_REF: u8 = 1 << 5;
+const SPRITE: u8 = 1 << 6;
+const COMPUTED_JUMP: u8 = 1 << 7;
+
+/// Kind of label attached to an address
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LabelKind {
+    /// Entry point of a subroutine (`Call` target)
+    Subroutine,
+    /// Target of a `Jump` or of a skip
+    Jump,
+    /// Sprite data drawn by `DrawSprite`
+    Sprite,
+    /// Other address loaded into I
+    Data,
+}
+
+/// Label of an address, formatted as `sub_2A4`, `label_200`, ...
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Label(pub LabelKind, pub u16);
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.0 {
+            LabelKind::Subroutine => "sub",
+            LabelKind::Jump => "label",
+            LabelKind::Sprite => "sprite",
+            LabelKind::Data => "data",
+        };
+        write!(f, "{}_{:03X}", prefix, self.1)
+    }
+}
+
+/// Parse a symbol map, made of `0xADDR name` lines. Empty lines and lines
+/// starting with `#` are ignored. Errors give the (1-based) line number.
+pub fn parse_symbols(text: &str) -> impl Iterator<Item = Result<(u16, &str), usize>> {
+    text.lines()
+        .enumerate()
+        .map(|(k, line)| (k + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            let mut words = line.split_whitespace();
+            let addr = words.next().ok_or(n)?;
+            let addr = addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")).ok_or(n)?;
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| n)?;
+            match (words.next(), words.next()) {
+                (Some(name), None) => Ok((addr, name)),
+                _ => Err(n),
+            }
+        })
+}
+
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    base: u16,
+    platform: Platform,
+    flags: [u8; MEMORY_SIZE],
+    symbols: &'a [(u16, &'a str)],
+}
+
+impl<'a> Disassembly<'a> {
+    /// Analyze a ROM loaded at `base`, starting execution at `base`
+    pub fn analyze(rom: &'a [u8], base: u16, platform: Platform) -> Self {
+        let mut d = Disassembly {
+            rom,
+            base,
+            platform,
+            flags: [0; MEMORY_SIZE],
+            symbols: &[],
+        };
+        d.mark(base, JUMP_TARGET | PENDING);
+        // Design note: rather than keeping a work list, pending addresses
+        // are flagged and collected until a fixed point is reached.
+        loop {
+            let mut progress = false;
+            for addr in d.base as usize..d.end() {
+                if d.flags[addr] & PENDING != 0 {
+                    d.flags[addr] &= !PENDING;
+                    d.trace(addr as u16);
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        d
+    }
+
+    /// End of the ROM in memory, exclusive
+    fn end(&self) -> usize {
+        (self.base as usize + self.rom.len()).min(MEMORY_SIZE)
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        addr >= self.base && (addr as usize) < self.end()
+    }
+
+    fn mark(&mut self, addr: u16, flags: u8) {
+        if self.contains(addr) {
+            self.flags[addr as usize] |= flags;
+        }
+    }
+
+    fn byte(&self, addr: u16) -> Option<u8> {
+        self.rom.get(addr.checked_sub(self.base)? as usize).cloned()
+    }
+
+    fn word(&self, addr: u16) -> Option<u16> {
+        let k = addr.checked_sub(self.base)? as usize;
+        let bytes = self.rom.get(k..k + 2)?;
+        Some(u16::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Name addresses in the listing, instead of using generated labels
+    pub fn set_symbols(&mut self, symbols: &'a [(u16, &'a str)]) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbol(&self, addr: u16) -> Option<&'a str> {
+        self.symbols.iter().find(|(a, _)| *a == addr).map(|(_, name)| *name)
+    }
+
+    /// Decode the instruction at addr, if any
+    pub fn decode_at(&self, addr: u16) -> Option<Insn> {
+        let w = self.word(addr)?;
+        if w == 0xF000 && self.platform >= Platform::XoChip {
+            return Insn::decode_long(w, self.word(addr.checked_add(2)?)?);
+        }
+        Insn::decode(w)
+    }
+
+    /// Follow the linear flow of instructions from addr, up to the end of
+    /// memory
+    fn trace(&mut self, mut addr: u16) {
+        let mut last_i: Option<u16> = None;
+        while self.contains(addr) && self.flags[addr as usize] & INSN_START == 0 {
+            let insn = match self.decode_at(addr) {
+                Some(insn) => insn,
+                None => return,
+            };
+            // The whole instruction was read from the ROM, only the next
+            // address may be past the end of memory
+            let next = addr.checked_add(insn.size());
+            self.mark(addr, INSN_START);
+            for k in 1..insn.size() {
+                self.mark(addr + k, INSN_BODY);
+            }
+            match insn {
+                Insn::Jump(target) => {
+                    self.mark(target, JUMP_TARGET | PENDING);
+                    return;
+                }
+                Insn::JumpV0(_) => {
+                    self.mark(addr, COMPUTED_JUMP);
+                    return;
+                }
+                Insn::Ret | Insn::Exit => return,
+                Insn::Call(target) => self.mark(target, CALL_TARGET | PENDING),
+                Insn::SkipEqI(_, _)
+                | Insn::SkipNeqI(_, _)
+                | Insn::SkipEq(_, _)
+                | Insn::SkipNeq(_, _)
+                | Insn::SkipKeyPressed(_)
+                | Insn::SkipKeyNPressed(_) => {
+                    let skipped = next.and_then(|next| next.checked_add(self.decode_at(next).map_or(2, |i| i.size())));
+                    if let Some(target) = skipped {
+                        self.mark(target, JUMP_TARGET | PENDING);
+                    }
+                }
+                Insn::LoadA(a) | Insn::LoadLongA(a) => {
+                    self.mark(a, DATA_REF);
+                    last_i = Some(a);
+                }
+                Insn::DrawSprite(_, _, n) => {
+                    if let Some(a) = last_i {
+                        let len = match n {
+                            0 if self.platform >= Platform::SuperChip => 32,
+                            n => n as u16,
+                        };
+                        for k in 0..len {
+                            self.mark(a.wrapping_add(k), SPRITE);
+                        }
+                    }
+                }
+                Insn::AddA(_)
+                | Insn::SpriteLoc(_)
+                | Insn::BigSpriteLoc(_)
+                | Insn::StoreRegs(_)
+                | Insn::LoadRegs(_) => last_i = None,
+                _ => {}
+            }
+            addr = match next {
+                Some(next) => next,
+                None => return,
+            };
+        }
+    }
+
+    /// True if an instruction starts at addr
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.flags[addr as usize] & INSN_START != 0
+    }
+
+    /// True if addr belongs to an instruction
+    pub fn is_reachable(&self, addr: u16) -> bool {
+        self.flags[addr as usize] & (INSN_START | INSN_BODY) != 0
+    }
+
+    pub fn is_sprite(&self, addr: u16) -> bool {
+        self.flags[addr as usize] & SPRITE != 0
+    }
+
+    /// True for `JumpV0` instructions, whose target can not be resolved
+    /// statically
+    pub fn is_computed_jump(&self, addr: u16) -> bool {
+        self.flags[addr as usize] & COMPUTED_JUMP != 0
+    }
+
+    pub fn label(&self, addr: u16) -> Option<Label> {
+        let f = self.flags[addr as usize];
+        let kind = if f & CALL_TARGET != 0 {
+            LabelKind::Subroutine
+        } else if f & JUMP_TARGET != 0 {
+            LabelKind::Jump
+        } else if f & SPRITE != 0 && f & DATA_REF != 0 {
+            LabelKind::Sprite
+        } else if f & DATA_REF != 0 {
+            LabelKind::Data
+        } else {
+            return None;
+        };
+        Some(Label(kind, addr))
+    }
+
+    /// Format an address operand, using its label when available
+    fn operand(&self, addr: u16) -> Operand<'a> {
+        if let Some(name) = self.symbol(addr) {
+            return Operand::Symbol(name);
+        }
+        match self.label(addr) {
+            Some(l) if self.contains(addr) => Operand::Label(l),
+            _ => Operand::Addr(addr),
+        }
+    }
+
+    fn write_insn<W: fmt::Write>(&self, out: &mut W, insn: &Insn) -> fmt::Result {
+        match *insn {
+            Insn::Jump(a) => write!(out, "JP {}", self.operand(a)),
+            Insn::JumpV0(a) => write!(out, "JP V0, {}", self.operand(a)),
+            Insn::Call(a) => write!(out, "CALL {}", self.operand(a)),
+            Insn::LoadA(a) => write!(out, "LD I, {}", self.operand(a)),
+            Insn::LoadLongA(a) => write!(out, "LD I, LONG {}", self.operand(a)),
+            _ => write!(out, "{}", insn),
+        }
+    }
+
+    /// Write a labeled listing of the ROM. Bytes which are not reachable
+    /// code are emitted as `db` directives, sprites are drawn in comments.
+    pub fn write_listing<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        let mut pos = self.base as usize;
+        while pos < self.end() {
+            let addr = pos as u16;
+            if let Some(name) = self.symbol(addr) {
+                writeln!(out, "{}:", name)?;
+            } else if let Some(l) = self.label(addr) {
+                writeln!(out, "{}:", l)?;
+            }
+            if self.is_code(addr) {
+                let insn = self.decode_at(addr).unwrap();
+                write!(out, "  0x{:03X}: {:04X}  ", addr, insn.encode())?;
+                self.write_insn(out, &insn)?;
+                if self.is_computed_jump(addr) {
+                    write!(out, "  ; computed jump, target unknown")?;
+                }
+                writeln!(out)?;
+                pos += insn.size() as usize;
+            } else if self.is_sprite(addr) {
+                let b = self.byte(addr).unwrap();
+                write!(out, "  0x{:03X}: {:02X}    db 0x{:02X}  ; ", addr, b, b)?;
+                for k in (0..8).rev() {
+                    out.write_char(if (b >> k) & 1 != 0 { '#' } else { '.' })?;
+                }
+                writeln!(out)?;
+                pos += 1;
+            } else {
+                // Group unknown bytes, up to 8 per line, until the next label
+                // or code
+                write!(out, "  0x{:03X}:       db ", addr)?;
+                let start = pos;
+                while pos < self.end() && pos - start < 8 {
+                    let addr = pos as u16;
+                    if self.is_code(addr) || self.is_sprite(addr)
+                        || (pos != start && (self.label(addr).is_some() || self.symbol(addr).is_some())) {
+                        break;
+                    }
+                    if pos != start {
+                        write!(out, ", ")?;
+                    }
+                    write!(out, "0x{:02X}", self.byte(addr).unwrap())?;
+                    pos += 1;
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Operand<'a> {
+    Symbol(&'a str),
+    Label(Label),
+    Addr(u16),
+}
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Symbol(name) => write!(f, "{}", name),
+            Operand::Label(l) => write!(f, "{}", l),
+            Operand::Addr(a) => write!(f, "0x{:03X}", a),
+        }
+    }
+}
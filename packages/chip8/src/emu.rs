@@ -1,10 +1,27 @@
 use crate::{Chip8Cpu, Pcg32};
 use crate::screen::Screen;
 use crate::keypad::Keypad;
-use crate::cpu::CpuError;
+use crate::cpu::{CpuError, CpuStatus, Insn, Platform};
+use crate::debug::{Debugger, StopReason};
+use crate::quirks::Quirks;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timing::{self, TimingModel, VIP_FRAME_BUDGET};
+use crate::trace::TraceHook;
+#[cfg(feature = "romdb")]
+use crate::romdb::RomInfo;
+#[cfg(feature = "std")]
+use crate::rewind::{Rewind, RewindConfig};
 
 pub const CHIP8_PERIPH_HZ: u32 = 60;
 
+/// Size of `Chip8Peripherals::memory`, the largest address space. CHIP-8 and
+/// SUPER-CHIP programs only address the first 4 KiB (see
+/// `Platform::memory_size`), the accesses past it fail or wrap around.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// Default XO-CHIP pitch, the audio pattern is played at 4000Hz
+pub const DEFAULT_PITCH: u8 = 64;
+
 const SPRITE_DATA: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
     0x20, 0x60, 0x20, 0x20, 0x70,   // 1
@@ -24,32 +41,73 @@ const SPRITE_DATA: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80    // F
 ];
 
+/// Location of the SUPER-CHIP 8x10 font, right after the regular one
+pub const BIG_SPRITE_ADDR: u16 = 0x50;
+
+const BIG_SPRITE_DATA: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF,   // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF,   // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,   // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03,   // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,   // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,   // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,   // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,   // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,   // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,   // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,   // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,   // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0    // F
+];
+
 #[derive(Clone)]
 pub struct Chip8Peripherals {
-    pub memory: [u8; 4096],
+    pub memory: [u8; MEMORY_SIZE],
     pub screen: Screen,
     pub keypad: Keypad,
     pub delay_timer: u16,
     pub sound_timer: u16,
-    pub rng: Pcg32
+    pub rng: Pcg32,
+    /// SUPER-CHIP persistent user flags (HP48 RPL registers)
+    pub rpl_flags: [u8; 16],
+    /// XO-CHIP 1-bit audio samples, played while the sound timer is active
+    pub audio_pattern: [u8; 16],
+    /// XO-CHIP playback rate of the audio pattern
+    pub pitch: u8,
+}
+
+impl Default for Chip8Peripherals {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8Peripherals {
     pub fn new() -> Self {
-        let mut memory = [0u8; 4096];
+        let mut memory = [0u8; MEMORY_SIZE];
         // Place sprite data at the begining of memory
         for (w, r) in SPRITE_DATA.iter().zip(memory.iter_mut()) {
             *r = *w;
         }
+        let big_sprites = &mut memory[BIG_SPRITE_ADDR as usize..];
+        for (w, r) in BIG_SPRITE_DATA.iter().zip(big_sprites.iter_mut()) {
+            *r = *w;
+        }
         let mut screen = Screen::new();
-        screen.clear(0);
+        screen.clear();
         Chip8Peripherals {
             memory,
             screen,
             keypad: Keypad::new(),
             delay_timer: 0,
             sound_timer: 0,
-            rng: Pcg32::default()
+            rng: Pcg32::default(),
+            rpl_flags: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: DEFAULT_PITCH,
         }
     }
 
@@ -66,6 +124,31 @@ impl Chip8Peripherals {
     pub fn set_rng_seed(&mut self, seed: u64) {
         self.rng.reset(seed, 42);
     }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.memory);
+        self.screen.save_state(w);
+        self.keypad.save_state(w);
+        w.u16(self.delay_timer);
+        w.u16(self.sound_timer);
+        self.rng.save_state(w);
+        w.bytes(&self.rpl_flags);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.memory);
+        self.screen.load_state(r)?;
+        self.keypad.load_state(r)?;
+        self.delay_timer = r.u16();
+        self.sound_timer = r.u16();
+        self.rng.load_state(r);
+        r.bytes(&mut self.rpl_flags);
+        r.bytes(&mut self.audio_pattern);
+        self.pitch = r.u8();
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -74,7 +157,22 @@ pub struct Chip8Emulator {
     periph_hz: u32,
     cpu: Chip8Cpu,
     periph: Chip8Peripherals,
+    /// Progress of the current frame: CPU steps times `periph_hz` with the
+    /// flat timing, machine cycles with the VIP one
     sim_ms: u32,
+    /// Fraction of a CPU step (flat timing) or of a machine cycle (VIP
+    /// timing) left over by `advance_ms`, in thousandths
+    advance_carry: u16,
+    /// Machine cycles run past the budget of `advance_ms` with the VIP
+    /// timing, taken from the next call
+    vip_debt: u32,
+    timing: TimingModel,
+    /// Frames ended since the creation, only compared to detect the end of
+    /// a frame
+    frames_ended: u64,
+    debugger: Debugger,
+    #[cfg(feature = "std")]
+    rewind: Option<Rewind>,
 }
 
 impl Chip8Emulator {
@@ -85,9 +183,24 @@ impl Chip8Emulator {
             periph_hz: 60,
             periph: Chip8Peripherals::new(),
             sim_ms: 0,
+            advance_carry: 0,
+            vip_debt: 0,
+            timing: TimingModel::Flat,
+            frames_ended: 0,
+            debugger: Debugger::new(),
+            #[cfg(feature = "std")]
+            rewind: None,
         }
     }
 
+    pub fn cpu(&self) -> &Chip8Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Chip8Cpu {
+        &mut self.cpu
+    }
+
     pub fn peripherals(&self) -> &Chip8Peripherals {
         &self.periph
     }
@@ -96,48 +209,465 @@ impl Chip8Emulator {
         &mut self.periph
     }
 
+    pub fn platform(&self) -> Platform {
+        self.cpu.platform()
+    }
+
+    /// Select the instruction set to emulate, along with its default quirks.
+    /// The screen is reset to the low resolution mode.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.cpu.set_platform(platform);
+        self.cpu.set_quirks(platform.default_quirks());
+        self.periph.screen.set_hires(false);
+        self.periph.screen.select_planes(1);
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        self.cpu.quirks()
+    }
+
+    /// Override the quirks, must be called after `set_platform`
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    pub fn cpu_hz(&self) -> u32 {
+        self.cpu_hz
+    }
+
     pub fn set_cpu_hz(&mut self, hz: u32) {
         self.cpu_hz = hz
     }
 
+    pub fn timing(&self) -> TimingModel {
+        self.timing
+    }
+
+    /// Select how instructions are scheduled against the 60Hz frames, the
+    /// current frame starts over
+    pub fn set_timing(&mut self, timing: TimingModel) {
+        self.timing = timing;
+        self.sim_ms = 0;
+        self.advance_carry = 0;
+        self.vip_debt = 0;
+    }
+
     pub fn set_cpu_rng_seed(&mut self, seed: u64) {
         self.periph.set_rng_seed(seed);
     }
 
+    /// Number of CPU steps executed since reset
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
     pub fn framebuffer(&self) -> &[u32] {
         self.periph.screen.data()
     }
 
+    /// Load a ROM at 0x200, the bytes past the end of memory are ignored
     pub fn load_rom(&mut self, data: &[u8]) {
-        let n = data.len();
-        self.periph.memory[0x200..0x200 + n].copy_from_slice(data);
+        let memory = &mut self.periph.memory[0x200..];
+        let n = data.len().min(memory.len());
+        memory[..n].copy_from_slice(&data[..n]);
+    }
+
+    /// Apply the recommended platform, quirks and CPU frequency of a known
+    /// ROM.
+    #[cfg(feature = "romdb")]
+    pub fn apply_rom_info(&mut self, info: &RomInfo) {
+        self.set_platform(info.platform);
+        self.set_quirks(info.quirks);
+        self.set_cpu_hz(info.cpu_hz);
+    }
+
+    /// Load a ROM and, if it is found in the built-in database, apply its
+    /// recommended settings.
+    #[cfg(feature = "romdb")]
+    pub fn load_rom_auto(&mut self, data: &[u8]) -> Option<&'static RomInfo> {
+        self.load_rom(data);
+        let info = crate::romdb::lookup(data)?;
+        self.apply_rom_info(info);
+        Some(info)
+    }
+
+    /// Write a snapshot of the complete emulator state (see `state.rs` for
+    /// the format). `out` must hold at least `STATE_SIZE` bytes, returns the
+    /// number of bytes written.
+    pub fn save_state(&self, out: &mut [u8]) -> Result<usize, StateError> {
+        let mut w = StateWriter::new(out)?;
+        w.u32(self.cpu_hz);
+        w.u32(self.periph_hz);
+        w.u32(self.sim_ms);
+        w.u16(self.advance_carry);
+        w.u32(self.vip_debt);
+        w.u8(self.timing as u8);
+        self.cpu.save_state(&mut w);
+        self.periph.save_state(&mut w);
+        Ok(w.finish())
+    }
+
+    /// Restore a snapshot written by `save_state`. The emulator is left
+    /// unchanged if the snapshot is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data)?;
+        let (cpu_hz, periph_hz, sim_ms) = (r.u32(), r.u32(), r.u32());
+        let (advance_carry, vip_debt) = (r.u16(), r.u32());
+        if cpu_hz == 0 || periph_hz == 0 {
+            return Err(StateError::InvalidValue("frequency"));
+        }
+        if advance_carry >= 1000 {
+            return Err(StateError::InvalidValue("advance carry"));
+        }
+        let timing = TimingModel::from_u8(r.u8()).ok_or(StateError::InvalidValue("timing"))?;
+        let mut cpu = self.cpu.clone();
+        cpu.load_state(&mut r)?;
+        let mut periph = self.periph.clone();
+        periph.load_state(&mut r)?;
+        self.cpu_hz = cpu_hz;
+        self.periph_hz = periph_hz;
+        self.sim_ms = sim_ms;
+        self.advance_carry = advance_carry;
+        self.vip_debt = vip_debt;
+        self.timing = timing;
+        self.cpu = cpu;
+        self.periph = periph;
+        Ok(())
+    }
+
+    /// Start recording the state history, for `rewind`
+    #[cfg(feature = "std")]
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        let mut rewind = Rewind::new(config);
+        rewind.push(&self.state_vec());
+        self.rewind = Some(rewind);
+    }
+
+    /// Stop recording the state history, and drop it
+    #[cfg(feature = "std")]
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn rewind_history(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// Go back in time by (about) `frames` frames, the accuracy depends on
+    /// the snapshot interval. Returns the number of frames actually
+    /// rewound, which is 0 if rewinding is disabled or the history is
+    /// exhausted.
+    #[cfg(feature = "std")]
+    pub fn rewind(&mut self, frames: u32) -> u32 {
+        let (state, rewound) = match self.rewind.as_mut().and_then(|r| r.seek_back(frames as u64)) {
+            Some(s) => s,
+            None => return 0,
+        };
+        self.load_state(&state).expect("rewind snapshots are valid");
+        rewound as u32
+    }
+
+    #[cfg(feature = "std")]
+    fn state_vec(&self) -> Vec<u8> {
+        let mut state = vec![0u8; crate::STATE_SIZE];
+        self.save_state(&mut state).unwrap();
+        state
+    }
+
+    /// Invoked on each peripheral tick (frame)
+    fn end_frame(&mut self) {
+        self.frames_ended += 1;
+        #[cfg(feature = "std")]
+        {
+            if let Some(mut rewind) = self.rewind.take() {
+                if rewind.next_frame() {
+                    rewind.push(&self.state_vec());
+                }
+                self.rewind = Some(rewind);
+            }
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Breakpoints, watchpoints and conditions checked by `tick`
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Make the next `tick` execute the instruction at PC, even if there is
+    /// a breakpoint on it
+    pub fn resume(&mut self) {
+        self.debugger.resume(&self.cpu);
+    }
+
+    /// Make the next `tick` stop after one instruction
+    pub fn step_into(&mut self) {
+        self.debugger.step_into(&self.cpu);
+    }
+
+    /// Make the next `tick` stop after one instruction, or at the return of
+    /// the subroutine if the instruction is a `Call`
+    pub fn step_over(&mut self) {
+        self.debugger.step_over(&self.cpu);
+    }
+
+    /// Make the next `tick` stop at the return of the current subroutine
+    pub fn step_out(&mut self) {
+        self.debugger.step_out(&self.cpu);
     }
 
     /// Advance the simulation by a given amount of milliseconds
-    pub fn advance_ms(&mut self, ms: u32) -> Result<(), CpuError> {
-        let cpu_hz = self.cpu_hz;
-        let cpu_steps = cpu_hz * (ms as u32) / 1000;
-        self.tick(cpu_steps as usize)
+    pub fn advance_ms(&mut self, ms: u32) -> Result<StopReason, CpuError> {
+        self.advance(ms, None)
+    }
+
+    /// Same as `advance_ms`, reporting the executed instructions to `hook`
+    pub fn advance_ms_traced(&mut self, ms: u32, hook: &mut dyn TraceHook) -> Result<StopReason, CpuError> {
+        self.advance(ms, Some(hook))
+    }
+
+    fn advance(&mut self, ms: u32, hook: Option<&mut dyn TraceHook>) -> Result<StopReason, CpuError> {
+        self.advance_with(ms, |emu, n, vip_budget| emu.run(n, vip_budget, false, hook))
+    }
+
+    /// Share out `ms` milliseconds: `run` is called with the instructions
+    /// to execute and, with the VIP timing, the machine cycles left to the
+    /// interpreter. The frames end in `run` as with `run_frame`, only the
+    /// fractions and the overshoot of the VIP budget are carried here.
+    pub(crate) fn advance_with<F>(&mut self, ms: u32, run: F) -> Result<StopReason, CpuError>
+    where
+        F: FnOnce(&mut Self, usize, Option<&mut i64>) -> Result<StopReason, CpuError>,
+    {
+        match self.timing {
+            TimingModel::Flat => {
+                let steps = self.advance_carry as u64 + ms as u64 * self.cpu_hz as u64;
+                self.advance_carry = (steps % 1000) as u16;
+                run(self, (steps / 1000) as usize, None)
+            }
+            TimingModel::CosmacVip => {
+                let cycles = self.advance_carry as u64 + ms as u64 * VIP_FRAME_BUDGET as u64 * self.periph_hz as u64;
+                self.advance_carry = (cycles % 1000) as u16;
+                let mut budget = (cycles / 1000) as i64 - self.vip_debt as i64;
+                let result = run(self, usize::MAX, Some(&mut budget));
+                // What is left after a debugger stop is dropped, as are the
+                // remaining steps with the flat timing
+                self.vip_debt = budget.min(0).unsigned_abs() as u32;
+                result
+            }
+        }
     }
 
     /// Advance the simulation by n ticks (CPU steps). The emulator will
-    /// invoke tick on peripherals if needed.
-    pub fn tick(&mut self, n: usize) -> Result<(), CpuError> {
-        let periph_hz = self.periph_hz;
-        let mut t = self.sim_ms;
-        {
-            let Chip8Emulator { periph, cpu, .. } = self;
-
-            for _ in 0..n {
-                cpu.tick(periph)?;
-                t += periph_hz;
-                if t >= self.cpu_hz {
-                    periph.tick();
-                    t -= self.cpu_hz;
-                }   
+    /// invoke tick on peripherals if needed. Returns early if the debugger
+    /// stops the execution.
+    pub fn tick(&mut self, n: usize) -> Result<StopReason, CpuError> {
+        self.run(n, None, false, None)
+    }
+
+    /// Same as `tick`, reporting the executed instructions to `hook`
+    pub fn tick_traced(&mut self, n: usize, hook: &mut dyn TraceHook) -> Result<StopReason, CpuError> {
+        self.run(n, None, false, Some(hook))
+    }
+
+    /// Run the instructions of one 60Hz frame, up to the vertical blank
+    /// where the timers are decremented. The fractional instructions per
+    /// frame are carried over to the next ones, so that the average rate is
+    /// exactly `cpu_hz`. Returns early if the debugger stops the execution.
+    pub fn run_frame(&mut self) -> Result<FrameResult, CpuError> {
+        self.frame(None)
+    }
+
+    /// Same as `run_frame`, reporting the executed instructions to `hook`
+    pub fn run_frame_traced(&mut self, hook: &mut dyn TraceHook) -> Result<FrameResult, CpuError> {
+        self.frame(Some(hook))
+    }
+
+    fn frame(&mut self, hook: Option<&mut dyn TraceHook>) -> Result<FrameResult, CpuError> {
+        self.frame_with(|emu| emu.run(usize::MAX, None, true, hook))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn frames_ended(&self) -> u64 {
+        self.frames_ended
+    }
+
+    /// Run a frame with `run`, and report the changes it made
+    pub(crate) fn frame_with<F>(&mut self, run: F) -> Result<FrameResult, CpuError>
+    where
+        F: FnOnce(&mut Self) -> Result<StopReason, CpuError>,
+    {
+        let changes = self.periph.screen.change_count();
+        let stop = run(self)?;
+        Ok(FrameResult {
+            stop,
+            screen_changed: self.periph.screen.change_count() != changes,
+            sound_active: self.periph.sound_timer > 0,
+            waiting_for_key: *self.cpu.status() == CpuStatus::WaitEvent,
+        })
+    }
+
+    /// Execute up to `n` instructions, or up to the end of the frame if
+    /// `frame` is set. With the VIP timing, `vip_budget` also limits the
+    /// machine cycles: the cycles spent are deducted and the execution
+    /// stops once it is exhausted, the last instruction possibly
+    /// overshooting it.
+    pub(crate) fn run(
+        &mut self,
+        n: usize,
+        mut vip_budget: Option<&mut i64>,
+        frame: bool,
+        mut hook: Option<&mut dyn TraceHook>,
+    ) -> Result<StopReason, CpuError> {
+        let deduct = |budget: &mut Option<&mut i64>, spent: u32| {
+            if let Some(budget) = budget.as_deref_mut() {
+                *budget -= spent as i64;
+            }
+        };
+        let exhausted = |budget: &Option<&mut i64>| budget.as_deref().is_some_and(|b| *b <= 0);
+        for _ in 0..n {
+            if exhausted(&vip_budget) {
+                break;
+            }
+            if self.timing == TimingModel::CosmacVip {
+                let waited = self.vip_display_wait();
+                deduct(&mut vip_budget, waited);
+                if waited > 0 && (frame || exhausted(&vip_budget)) {
+                    break;
+                }
+            }
+            let pre = if self.debugger.is_active() {
+                match self.debugger.before_insn(&self.cpu, &self.periph) {
+                    Ok(pre) => Some(pre),
+                    Err(reason) => return Ok(reason),
+                }
+            } else {
+                None
+            };
+            let frame_ended = match self.timing {
+                TimingModel::Flat => {
+                    self.exec(&mut hook)?;
+                    self.sim_ms += self.periph_hz;
+                    let ended = self.sim_ms >= self.cpu_hz;
+                    if ended {
+                        self.periph.tick();
+                        self.sim_ms -= self.cpu_hz;
+                        self.end_frame();
+                    }
+                    ended
+                }
+                TimingModel::CosmacVip => {
+                    let (spent, ended) = self.step_vip(&mut hook)?;
+                    deduct(&mut vip_budget, spent);
+                    ended
+                }
+            };
+            if let Some(reason) = pre.and_then(|pre| self.debugger.after_insn(&pre, &self.cpu)) {
+                return Ok(reason);
+            }
+            if frame && frame_ended {
+                break;
             }
         }
-        self.sim_ms = t;
-        Ok(())
+        Ok(StopReason::Completed)
+    }
+
+    fn exec(&mut self, hook: &mut Option<&mut dyn TraceHook>) -> Result<(), CpuError> {
+        match hook {
+            Some(hook) => self.cpu.tick_traced(&mut self.periph, &mut **hook),
+            None => self.cpu.tick(&mut self.periph),
+        }
     }
-}
\ No newline at end of file
+
+    /// With the VIP timing, `DrawSprite` waits for the vertical blank: if it
+    /// is the next instruction, the current frame ends. Returns the machine
+    /// cycles spent waiting.
+    fn vip_display_wait(&mut self) -> u32 {
+        if self.sim_ms == 0 || *self.cpu.status() == CpuStatus::Halted {
+            return 0;
+        }
+        match self.cpu.fetch(&self.periph) {
+            Ok((Insn::DrawSprite(..), _)) => {
+                let waited = VIP_FRAME_BUDGET - self.sim_ms;
+                self.sim_ms = 0;
+                self.periph.tick();
+                self.end_frame();
+                waited
+            }
+            _ => 0,
+        }
+    }
+
+    /// Execute an instruction with the VIP timing, returns the machine
+    /// cycles spent and whether a frame ended
+    fn step_vip(&mut self, hook: &mut Option<&mut dyn TraceHook>) -> Result<(u32, bool), CpuError> {
+        let fetched = match self.cpu.status() {
+            CpuStatus::Halted => None,
+            // A fetch error is reported by the execution
+            _ => self.cpu.fetch(&self.periph).ok().map(|(insn, _)| insn),
+        };
+        let pc = self.cpu.pc();
+        let v = *self.cpu.gprs();
+        self.exec(hook)?;
+        let cost = match fetched {
+            Some(insn) => {
+                let skipped = self.cpu.pc() != pc.wrapping_add(insn.size());
+                timing::vip_cycles(&insn, &v, skipped)
+            }
+            None => timing::VIP_FETCH,
+        };
+        self.sim_ms += cost;
+        let ended = self.sim_ms >= VIP_FRAME_BUDGET;
+        while self.sim_ms >= VIP_FRAME_BUDGET {
+            self.periph.tick();
+            self.sim_ms -= VIP_FRAME_BUDGET;
+            self.end_frame();
+        }
+        Ok((cost, ended))
+    }
+}
+
+/// Outcome of `Chip8Emulator::run_frame`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FrameResult {
+    /// `Completed` if the frame ran up to its end, otherwise the reason the
+    /// debugger stopped it
+    pub stop: StopReason,
+    /// The screen was modified during the frame
+    pub screen_changed: bool,
+    /// The sound timer is running, the buzzer should be on
+    pub sound_active: bool,
+    /// The CPU is blocked in `WaitForKey`
+    pub waiting_for_key: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vip_emulator(rom: &[u8]) -> Chip8Emulator {
+        let mut emu = Chip8Emulator::new(500);
+        emu.set_timing(TimingModel::CosmacVip);
+        emu.load_rom(rom);
+        emu
+    }
+
+    #[test]
+    fn vip_cost_before_exec() {
+        // LD VF, 8; DRW VF, V1, 5 twice, the second one colliding and
+        // setting VF to 1: both rows are aligned
+        let mut emu = vip_emulator(&[0x6F, 0x08, 0xDF, 0x15, 0x6F, 0x08, 0xDF, 0x15]);
+        let costs: [u32; 4] = core::array::from_fn(|_| emu.step_vip(&mut None).unwrap().0);
+        assert_eq!(emu.cpu.read_gpr(0xF), 1);
+        assert_eq!(costs[1], costs[3]);
+        assert_eq!(costs[3], timing::VIP_FETCH + 26 + 34 * 5);
+
+        // LD V3, 255; LD B, V3
+        let mut emu = vip_emulator(&[0x63, 0xFF, 0xF3, 0x33]);
+        emu.step_vip(&mut None).unwrap();
+        assert_eq!(emu.step_vip(&mut None).unwrap().0, timing::VIP_FETCH + 80 + 16 * (2 + 5 + 5));
+    }
+}
@@ -0,0 +1,174 @@
+//
+// Export of the screen content
+//
+// The screen is written as seen by the player: top row first whatever the
+// storage order of `Screen::data`, in the colors of the palette.
+//
+// * PBM (P4) and PPM (P6) images, without dependencies
+// * PNG images, with the `png` feature
+// * Text, two pixel rows per line with the Unicode half blocks, in the
+//   palette colors with ANSI escape codes or in the terminal colors
+//
+// The images can be scaled up, a CHIP-8 screen being only 64x32 pixels.
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::screen::Screen;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ImageFormat {
+    Pbm,
+    Ppm,
+    #[cfg(feature = "png")]
+    Png,
+    Text,
+}
+
+impl ImageFormat {
+    /// Usual file extension
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Pbm => "pbm",
+            ImageFormat::Ppm => "ppm",
+            #[cfg(feature = "png")]
+            ImageFormat::Png => "png",
+            ImageFormat::Text => "txt",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pbm" => Ok(ImageFormat::Pbm),
+            "ppm" => Ok(ImageFormat::Ppm),
+            #[cfg(feature = "png")]
+            "png" => Ok(ImageFormat::Png),
+            "text" | "txt" => Ok(ImageFormat::Text),
+            _ => Err("unknown image format (expected: pbm, ppm, png, text)"),
+        }
+    }
+}
+
+/// RGB components of a palette color (RGBA byte order)
+fn rgb(color: u32) -> [u8; 3] {
+    let [r, g, b, _] = color.to_le_bytes();
+    [r, g, b]
+}
+
+fn is_dark(color: u32) -> bool {
+    let [r, g, b] = rgb(color);
+    // Rec. 601 luma
+    let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    luma < 128 * 1000
+}
+
+impl Screen {
+    /// Color of the pixel at (x, y), y = 0 being the top row
+    pub fn color(&self, x: u32, y: u32) -> u32 {
+        self.palette()[self.pixel(x, y) as usize]
+    }
+
+    /// Write the given format, `scale` being ignored by the text
+    pub fn write_image<W: Write>(&self, out: &mut W, format: ImageFormat, scale: u32) -> io::Result<()> {
+        match format {
+            ImageFormat::Pbm => self.write_pbm(out, scale),
+            ImageFormat::Ppm => self.write_ppm(out, scale),
+            #[cfg(feature = "png")]
+            ImageFormat::Png => self.write_png(out, scale),
+            ImageFormat::Text => self.write_text(out, false),
+        }
+    }
+
+    /// Rows of RGB pixels, scaled
+    fn rgb_rows(&self, scale: u32) -> impl Iterator<Item = Vec<u8>> + '_ {
+        let scale = scale.max(1);
+        (0..self.height() * scale).map(move |y| {
+            let mut row = Vec::with_capacity((self.width() * scale * 3) as usize);
+            for x in 0..self.width() * scale {
+                row.extend_from_slice(&rgb(self.color(x / scale, y / scale)));
+            }
+            row
+        })
+    }
+
+    /// Binary PBM, a bitmap: the pixels of the background color are black
+    /// if it is dark, the others are of the opposite color
+    pub fn write_pbm<W: Write>(&self, out: &mut W, scale: u32) -> io::Result<()> {
+        let scale = scale.max(1);
+        let (w, h) = (self.width() * scale, self.height() * scale);
+        let background = self.palette()[0];
+        // In PBM, 1 is black
+        let bg_bit = is_dark(background);
+        write!(out, "P4\n{} {}\n", w, h)?;
+        let mut row = vec![0u8; w.div_ceil(8) as usize];
+        for y in 0..h {
+            row.iter_mut().for_each(|b| *b = 0);
+            for x in 0..w {
+                let is_bg = self.color(x / scale, y / scale) == background;
+                if is_bg == bg_bit {
+                    row[(x / 8) as usize] |= 0x80 >> (x % 8);
+                }
+            }
+            out.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Binary PPM, in the palette colors (the alpha channel is dropped)
+    pub fn write_ppm<W: Write>(&self, out: &mut W, scale: u32) -> io::Result<()> {
+        let scale = scale.max(1);
+        write!(out, "P6\n{} {}\n255\n", self.width() * scale, self.height() * scale)?;
+        for row in self.rgb_rows(scale) {
+            out.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    /// 8-bit RGB PNG, in the palette colors (the alpha channel is dropped)
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, out: &mut W, scale: u32) -> io::Result<()> {
+        let scale = scale.max(1);
+        let mut encoder = png::Encoder::new(out, self.width() * scale, self.height() * scale);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let data: Vec<u8> = self.rgb_rows(scale).flatten().collect();
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Two pixel rows per line with the half block characters. With
+    /// `ansi_colors`, the pixels are drawn in the palette colors with 24-bit
+    /// ANSI escape codes, otherwise the pixels differing from the background
+    /// are drawn in the foreground color of the terminal.
+    pub fn write_text<W: Write>(&self, out: &mut W, ansi_colors: bool) -> io::Result<()> {
+        let background = self.palette()[0];
+        for y in (0..self.height()).step_by(2) {
+            for x in 0..self.width() {
+                let top = self.color(x, y);
+                let bottom = if y + 1 < self.height() { self.color(x, y + 1) } else { background };
+                if ansi_colors {
+                    let ([r, g, b], [br, bg, bb]) = (rgb(top), rgb(bottom));
+                    write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m\u{2580}", r, g, b, br, bg, bb)?;
+                    continue;
+                }
+                let c = match (top != background, bottom != background) {
+                    (false, false) => ' ',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (true, true) => '\u{2588}',
+                };
+                write!(out, "{}", c)?;
+            }
+            if ansi_colors {
+                write!(out, "\x1b[0m")?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
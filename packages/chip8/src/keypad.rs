@@ -1,8 +1,16 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
 #[derive(Debug, Copy, Clone)]
 pub struct Keypad {
     keystate: u32
 }
 
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keypad {
     pub fn new() -> Self {
         Keypad {
@@ -29,11 +37,18 @@ impl Keypad {
         if self.keystate == 0 {
             return None;
         }
-        for i in 0..16 {
-            if (self.keystate >> i as u32) & 1 != 0 {
-                return Some(i);
-            }
+        (0..16).find(|&i| (self.keystate >> i as u32) & 1 != 0)
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.u32(self.keystate);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.keystate = r.u32();
+        if self.keystate > 0xFFFF {
+            return Err(StateError::InvalidValue("keypad"));
         }
-        None
+        Ok(())
     }
-}
\ No newline at end of file
+}
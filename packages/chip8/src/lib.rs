@@ -1,12 +1,30 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 pub mod cpu;
+pub mod debug;
+pub mod disasm;
 pub mod screen;
 pub mod keypad;
 pub mod emu;
+#[cfg(feature = "std")]
+pub mod export;
+pub mod quirks;
+#[cfg(feature = "romdb")]
+pub mod romdb;
+#[cfg(feature = "std")]
+pub mod movie;
+#[cfg(feature = "std")]
+pub mod rewind;
+pub mod state;
+pub mod timing;
+pub mod trace;
 pub mod utils;
 
-pub use cpu::{Insn, Chip8Cpu};
-pub use emu::{Chip8Emulator, Chip8Peripherals, CHIP8_PERIPH_HZ};
-pub use screen::{Screen, Chip8Fb, CHIP8_FB_W, CHIP8_FB_H};
-pub use utils::Pcg32;
+pub use cpu::{Insn, OctoInsn, Chip8Cpu, CpuSnapshot, Platform};
+pub use debug::StopReason;
+pub use emu::{Chip8Emulator, Chip8Peripherals, FrameResult, CHIP8_PERIPH_HZ, MEMORY_SIZE};
+pub use screen::{Screen, Damage, Chip8Fb, CHIP8_FB_W, CHIP8_FB_H, SCHIP_FB_W, SCHIP_FB_H};
+pub use quirks::{IndexQuirk, Quirks};
+pub use state::{StateError, STATE_SIZE};
+pub use timing::TimingModel;
+pub use utils::{crc32, Pcg32};
@@ -0,0 +1,435 @@
+//
+// Movies: deterministic recording and replay of the keypad input
+//
+// With a fixed RNG seed, a run only depends on the configuration of the
+// emulator, the ROM, and the keypad. A movie records the former and every
+// keypad transition, stamped with the CPU cycle at which it happened, so that
+// replaying it reproduces the run exactly.
+//
+// File format (little-endian):
+//
+//   offset  size   content
+//   0       4      magic "C8MV"
+//   4       2      format version
+//   6       1      platform
+//   7       6      quirks (same encoding as the save states)
+//   13      4      CPU frequency (Hz)
+//   17      1      timing model
+//   18      8      RNG seed
+//   26      4      CRC32 of the ROM
+//   30      8      length of the movie, in CPU cycles
+//   38      4      number of events N
+//   42      9*N    events: cycle (8 bytes), then the key in the low nibble
+//                  and 0x80 if it is pressed
+//   42+9*N  4      CRC32 of all the preceding bytes
+use core::fmt;
+
+use crate::cpu::{CpuError, Platform};
+use crate::debug::StopReason;
+use crate::quirks::{IndexQuirk, Quirks};
+use crate::timing::TimingModel;
+use crate::trace::TraceHook;
+use crate::utils::crc32;
+use crate::{Chip8Emulator, FrameResult};
+
+pub const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+
+/// Version of the format, incremented on any layout change once released
+pub const MOVIE_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 42;
+const EVENT_SIZE: usize = 9;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MovieError {
+    /// The input is not a movie
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The input is truncated, or has trailing data
+    InvalidLength,
+    /// The input is corrupted
+    ChecksumMismatch,
+    /// A field holds an out of range value
+    InvalidValue(&'static str),
+    /// The ROM is not the one the movie was recorded with
+    RomMismatch,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::InvalidLength => write!(f, "invalid movie length"),
+            MovieError::ChecksumMismatch => write!(f, "corrupted movie (bad checksum)"),
+            MovieError::InvalidValue(field) => write!(f, "invalid value of '{}' in movie", field),
+            MovieError::RomMismatch => write!(f, "the movie was recorded with another ROM"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// Keypad transition
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyEvent {
+    /// Value of `Chip8Emulator::cycles` when the transition happened
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Movie {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub cpu_hz: u32,
+    pub timing: TimingModel,
+    pub rng_seed: u64,
+    pub rom_crc: u32,
+    /// Number of CPU cycles covered by the movie
+    pub length: u64,
+    /// Keypad transitions, sorted by cycle
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    /// Create an emulator configured as when the movie was recorded, with
+    /// the ROM loaded. Fails if `rom` is not the recorded ROM.
+    pub fn make_emulator(&self, rom: &[u8]) -> Result<Chip8Emulator, MovieError> {
+        if crc32(rom) != self.rom_crc {
+            return Err(MovieError::RomMismatch);
+        }
+        let mut emu = Chip8Emulator::new(self.cpu_hz);
+        emu.set_platform(self.platform);
+        emu.set_quirks(self.quirks);
+        emu.set_timing(self.timing);
+        emu.load_rom(rom);
+        emu.set_cpu_rng_seed(self.rng_seed);
+        Ok(emu)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + EVENT_SIZE * self.events.len() + 4);
+        out.extend_from_slice(&MOVIE_MAGIC);
+        out.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        out.push(self.platform as u8);
+        out.push(self.quirks.shift_uses_vy as u8);
+        out.push(self.quirks.load_store_index as u8);
+        out.push(self.quirks.jump_uses_vx as u8);
+        out.push(self.quirks.clip_sprites as u8);
+        out.push(self.quirks.logic_resets_vf as u8);
+        out.push(self.quirks.wrap_memory as u8);
+        out.extend_from_slice(&self.cpu_hz.to_le_bytes());
+        out.push(self.timing as u8);
+        out.extend_from_slice(&self.rng_seed.to_le_bytes());
+        out.extend_from_slice(&self.rom_crc.to_le_bytes());
+        out.extend_from_slice(&self.length.to_le_bytes());
+        out.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+        for ev in self.events.iter() {
+            out.extend_from_slice(&ev.cycle.to_le_bytes());
+            out.push(ev.key | (ev.pressed as u8) << 7);
+        }
+        let crc = crc32(&out);
+        out.extend_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < 6 || data[..4] != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        if data.len() < HEADER_SIZE + 4 {
+            return Err(MovieError::InvalidLength);
+        }
+        let count = u32_at(data, 38) as usize;
+        if count.checked_mul(EVENT_SIZE) != Some(data.len() - HEADER_SIZE - 4) {
+            return Err(MovieError::InvalidLength);
+        }
+        let (data, crc) = data.split_at(data.len() - 4);
+        if crc32(data).to_le_bytes() != crc {
+            return Err(MovieError::ChecksumMismatch);
+        }
+
+        let platform = match data[6] {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(MovieError::InvalidValue("platform")),
+        };
+        let flag = |offset: usize, field| match data[offset] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(MovieError::InvalidValue(field)),
+        };
+        let quirks = Quirks {
+            shift_uses_vy: flag(7, "quirks.shift_uses_vy")?,
+            load_store_index: match data[8] {
+                0 => IndexQuirk::Unchanged,
+                1 => IndexQuirk::IncrementX,
+                2 => IndexQuirk::IncrementXPlusOne,
+                _ => return Err(MovieError::InvalidValue("quirks.load_store_index")),
+            },
+            jump_uses_vx: flag(9, "quirks.jump_uses_vx")?,
+            clip_sprites: flag(10, "quirks.clip_sprites")?,
+            logic_resets_vf: flag(11, "quirks.logic_resets_vf")?,
+            wrap_memory: flag(12, "quirks.wrap_memory")?,
+        };
+        let cpu_hz = u32_at(data, 13);
+        if cpu_hz == 0 {
+            return Err(MovieError::InvalidValue("frequency"));
+        }
+        let timing = TimingModel::from_u8(data[17]).ok_or(MovieError::InvalidValue("timing"))?;
+        let length = u64_at(data, 30);
+        let mut events = Vec::with_capacity(count);
+        for chunk in data[HEADER_SIZE..].chunks(EVENT_SIZE) {
+            let ev = KeyEvent {
+                cycle: u64_at(chunk, 0),
+                key: chunk[8] & 0x0F,
+                pressed: chunk[8] & 0x80 != 0,
+            };
+            if chunk[8] & 0x70 != 0 {
+                return Err(MovieError::InvalidValue("event"));
+            }
+            if ev.cycle > length || events.last().is_some_and(|prev: &KeyEvent| prev.cycle > ev.cycle) {
+                return Err(MovieError::InvalidValue("event cycle"));
+            }
+            events.push(ev);
+        }
+        Ok(Movie {
+            platform,
+            quirks,
+            cpu_hz,
+            timing,
+            rng_seed: u64_at(data, 18),
+            rom_crc: u32_at(data, 26),
+            length,
+            events,
+        })
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// Record the keypad of an emulator. The keys must go through the recorder
+/// instead of `Keypad`, and the emulator state must not be changed by other
+/// means (loading a state, rewinding) during the recording.
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Start recording a freshly reset emulator running `rom`. The RNG of
+    /// the emulator is reset with `rng_seed`.
+    pub fn new(emu: &mut Chip8Emulator, rom: &[u8], rng_seed: u64) -> Self {
+        emu.set_cpu_rng_seed(rng_seed);
+        MovieRecorder {
+            movie: Movie {
+                platform: emu.platform(),
+                quirks: *emu.quirks(),
+                cpu_hz: emu.cpu_hz(),
+                timing: emu.timing(),
+                rng_seed,
+                rom_crc: crc32(rom),
+                length: 0,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    pub fn key_pressed(&mut self, emu: &mut Chip8Emulator, key: u8) {
+        self.set_key(emu, key, true);
+    }
+
+    pub fn key_released(&mut self, emu: &mut Chip8Emulator, key: u8) {
+        self.set_key(emu, key, false);
+    }
+
+    fn set_key(&mut self, emu: &mut Chip8Emulator, key: u8, pressed: bool) {
+        let keypad = &mut emu.peripherals_mut().keypad;
+        if (keypad.key_state(key) != 0) == pressed {
+            return;
+        }
+        if pressed {
+            keypad.key_pressed(key);
+        } else {
+            keypad.key_released(key);
+        }
+        self.movie.events.push(KeyEvent { cycle: emu.cycles(), key, pressed });
+    }
+
+    /// Events recorded so far
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.movie.events
+    }
+
+    /// Stop the recording, the movie ends at the current cycle
+    pub fn finish(mut self, emu: &Chip8Emulator) -> Movie {
+        self.movie.length = emu.cycles();
+        self.movie
+    }
+}
+
+/// Drive an emulator created by `Movie::make_emulator` with the recorded
+/// keypad events
+pub struct MoviePlayer {
+    movie: Movie,
+    next: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, next: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// True once the emulator reached the end of the movie
+    pub fn finished(&self, emu: &Chip8Emulator) -> bool {
+        emu.cycles() >= self.movie.length
+    }
+
+    /// Apply the events due at the current cycle
+    fn apply_events(&mut self, emu: &mut Chip8Emulator) {
+        let cycle = emu.cycles();
+        while let Some(ev) = self.movie.events.get(self.next) {
+            if ev.cycle > cycle {
+                break;
+            }
+            let keypad = &mut emu.peripherals_mut().keypad;
+            if ev.pressed {
+                keypad.key_pressed(ev.key);
+            } else {
+                keypad.key_released(ev.key);
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Same as `Chip8Emulator::tick`, feeding the keypad from the movie.
+    /// The emulation goes on after the end of the movie.
+    pub fn tick(&mut self, emu: &mut Chip8Emulator, n: usize) -> Result<StopReason, CpuError> {
+        self.run(emu, n, None, None)
+    }
+
+    /// Same as `tick`, reporting the executed instructions to `hook`
+    pub fn tick_traced(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        n: usize,
+        hook: &mut dyn TraceHook,
+    ) -> Result<StopReason, CpuError> {
+        self.run(emu, n, None, Some(hook))
+    }
+
+    fn run(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        n: usize,
+        mut vip_budget: Option<&mut i64>,
+        mut hook: Option<&mut dyn TraceHook>,
+    ) -> Result<StopReason, CpuError> {
+        let end = emu.cycles().saturating_add(n as u64);
+        loop {
+            self.apply_events(emu);
+            let cycle = emu.cycles();
+            if cycle >= end || vip_budget.as_deref().is_some_and(|b| *b <= 0) {
+                return Ok(StopReason::Completed);
+            }
+            // Run up to the next event in one go
+            let until = match self.movie.events.get(self.next) {
+                Some(ev) => ev.cycle.min(end),
+                None => end,
+            };
+            let steps = (until - cycle) as usize;
+            let hook = hook.as_mut().map(|hook| &mut **hook as &mut dyn TraceHook);
+            let reason = emu.run(steps, vip_budget.as_deref_mut(), false, hook)?;
+            if reason != StopReason::Completed {
+                return Ok(reason);
+            }
+            if emu.cycles() == cycle {
+                // Halted CPU: the cycle counter does not move anymore
+                return Ok(StopReason::Completed);
+            }
+        }
+    }
+
+    /// Same as `Chip8Emulator::run_frame`, feeding the keypad from the
+    /// movie
+    pub fn run_frame(&mut self, emu: &mut Chip8Emulator) -> Result<FrameResult, CpuError> {
+        self.frame(emu, None)
+    }
+
+    /// Same as `run_frame`, reporting the executed instructions to `hook`
+    pub fn run_frame_traced(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        hook: &mut dyn TraceHook,
+    ) -> Result<FrameResult, CpuError> {
+        self.frame(emu, Some(hook))
+    }
+
+    fn frame(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        mut hook: Option<&mut dyn TraceHook>,
+    ) -> Result<FrameResult, CpuError> {
+        emu.frame_with(|emu| {
+            let frame = emu.frames_ended();
+            loop {
+                self.apply_events(emu);
+                // Run up to the next event, or the end of the frame
+                let steps = match self.movie.events.get(self.next) {
+                    Some(ev) => (ev.cycle - emu.cycles()) as usize,
+                    None => usize::MAX,
+                };
+                let hook = hook.as_mut().map(|hook| &mut **hook as &mut dyn TraceHook);
+                let reason = emu.run(steps, None, true, hook)?;
+                if reason != StopReason::Completed || emu.frames_ended() != frame {
+                    return Ok(reason);
+                }
+            }
+        })
+    }
+
+    /// Same as `Chip8Emulator::advance_ms`, feeding the keypad from the
+    /// movie
+    pub fn advance_ms(&mut self, emu: &mut Chip8Emulator, ms: u32) -> Result<StopReason, CpuError> {
+        self.advance(emu, ms, None)
+    }
+
+    /// Same as `advance_ms`, reporting the executed instructions to `hook`
+    pub fn advance_ms_traced(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        ms: u32,
+        hook: &mut dyn TraceHook,
+    ) -> Result<StopReason, CpuError> {
+        self.advance(emu, ms, Some(hook))
+    }
+
+    fn advance(
+        &mut self,
+        emu: &mut Chip8Emulator,
+        ms: u32,
+        hook: Option<&mut dyn TraceHook>,
+    ) -> Result<StopReason, CpuError> {
+        emu.advance_with(ms, |emu, n, vip_budget| self.run(emu, n, vip_budget, hook))
+    }
+}
@@ -0,0 +1,131 @@
+//
+// Behaviors of ambiguous instructions, which differ between interpreters.
+//
+// * https://github.com/Timendus/chip8-test-suite#quirks-test
+// * https://chip-8.github.io/extensions/
+use core::str::FromStr;
+
+use crate::cpu::Platform;
+
+/// Effect of `StoreRegs` (FX55) and `LoadRegs` (FX65) on the I register
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IndexQuirk {
+    /// I is left untouched (SUPER-CHIP)
+    Unchanged,
+    /// I is incremented by X (CHIP-48)
+    IncrementX,
+    /// I is incremented by X + 1 (COSMAC VIP, XO-CHIP)
+    IncrementXPlusOne,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Quirks {
+    /// `Shr`/`Shl` shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    pub load_store_index: IndexQuirk,
+    /// `JumpV0` (BNNN) jumps to XNN + VX, instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    /// The initial coordinates always wrap.
+    pub clip_sprites: bool,
+    /// `Or`, `And` and `Xor` (8XY1/2/3) reset VF to 0
+    pub logic_resets_vf: bool,
+    /// Memory accesses past the end of the address space wrap around to 0,
+    /// instead of failing with `CpuError::MemoryError`
+    pub wrap_memory: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::legacy()
+    }
+}
+
+impl Quirks {
+    /// Historical behavior of this emulator, used by default
+    pub const fn legacy() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index: IndexQuirk::Unchanged,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            wrap_memory: false,
+        }
+    }
+
+    /// Original interpreter of the COSMAC VIP (1977)
+    pub const fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_index: IndexQuirk::IncrementXPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            logic_resets_vf: true,
+            wrap_memory: true,
+        }
+    }
+
+    /// CHIP-48 for the HP48 calculators (1990)
+    pub const fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index: IndexQuirk::IncrementX,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            wrap_memory: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 (1991)
+    pub const fn super_chip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index: IndexQuirk::Unchanged,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            wrap_memory: false,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo
+    pub const fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_index: IndexQuirk::IncrementXPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            wrap_memory: true,
+        }
+    }
+}
+
+impl Platform {
+    /// Quirks expected by most programs written for the platform
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = &'static str;
+
+    /// Parse a preset name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Quirks::default()),
+            "vip" => Ok(Quirks::cosmac_vip()),
+            "chip48" => Ok(Quirks::chip48()),
+            "schip" => Ok(Quirks::super_chip()),
+            "xochip" => Ok(Quirks::xo_chip()),
+            _ => Err("unknown quirks preset (expected: default, vip, chip48, schip, xochip)"),
+        }
+    }
+}
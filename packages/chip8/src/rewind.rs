@@ -0,0 +1,307 @@
+//
+// Rewind: bounded history of past emulator states
+//
+// A save state is captured every `interval` frames. Snapshots are grouped,
+// each group starting with a full keyframe, the other snapshots being stored
+// as the XOR with their keyframe, run-length encoded. Little changes from a
+// frame to the next, so the XOR is mostly made of zeros and compresses very
+// well.
+use std::collections::VecDeque;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RewindConfig {
+    /// Frames between two snapshots
+    pub interval: u32,
+    /// Maximum number of snapshots kept, the oldest are dropped first
+    pub capacity: usize,
+    /// Snapshots per group (one keyframe, followed by deltas)
+    pub keyframe_interval: usize,
+}
+
+impl Default for RewindConfig {
+    /// About 20 seconds of history at 60 frames per second
+    fn default() -> Self {
+        RewindConfig {
+            interval: 2,
+            capacity: 600,
+            keyframe_interval: 30,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Group {
+    keyframe: Vec<u8>,
+    /// Frame numbers of the snapshots, the first one being the keyframe
+    frames: Vec<u64>,
+    /// Encoded deltas of the snapshots following the keyframe
+    deltas: Vec<Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct Rewind {
+    config: RewindConfig,
+    frame: u64,
+    groups: VecDeque<Group>,
+    len: usize,
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Self {
+        Rewind {
+            config: RewindConfig {
+                interval: config.interval.max(1),
+                capacity: config.capacity.max(1),
+                // A group larger than the capacity could not be dropped
+                keyframe_interval: config.keyframe_interval.clamp(1, config.capacity.max(1)),
+            },
+            frame: 0,
+            groups: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub fn config(&self) -> &RewindConfig {
+        &self.config
+    }
+
+    /// Number of snapshots kept
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Memory used by the snapshots, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.groups
+            .iter()
+            .map(|g| g.keyframe.len() + g.deltas.iter().map(|d| d.len()).sum::<usize>())
+            .sum()
+    }
+
+    /// Count a new frame, returns true if a snapshot must be captured
+    pub(crate) fn next_frame(&mut self) -> bool {
+        self.frame += 1;
+        self.frame.is_multiple_of(self.config.interval as u64)
+    }
+
+    /// Record the state of the current frame
+    pub(crate) fn push(&mut self, state: &[u8]) {
+        let frame = self.frame;
+        match self.groups.back_mut() {
+            Some(g) if g.frames.len() < self.config.keyframe_interval && g.keyframe.len() == state.len() => {
+                g.deltas.push(encode_delta(&g.keyframe, state));
+                g.frames.push(frame);
+            }
+            _ => self.groups.push_back(Group {
+                keyframe: state.to_vec(),
+                frames: vec![frame],
+                deltas: Vec::new(),
+            }),
+        }
+        self.len += 1;
+        // Drop whole groups, their deltas are useless without the keyframe
+        while self.len > self.config.capacity && self.groups.len() > 1 {
+            let g = self.groups.pop_front().unwrap();
+            self.len -= g.frames.len();
+        }
+    }
+
+    /// Go back to the newest snapshot taken at least `frames` frames ago
+    /// (or the oldest one), dropping the snapshots taken after it. Returns
+    /// the snapshot and the number of frames rewound.
+    pub(crate) fn seek_back(&mut self, frames: u64) -> Option<(Vec<u8>, u64)> {
+        let target = self.frame.saturating_sub(frames);
+        // Drop the groups starting after the target, keeping at least one
+        while self.groups.len() > 1 && self.groups.back().unwrap().frames[0] > target {
+            let g = self.groups.pop_back().unwrap();
+            self.len -= g.frames.len();
+        }
+        let g = self.groups.back_mut()?;
+        let k = g.frames.iter().rposition(|f| *f <= target).unwrap_or(0);
+        self.len -= g.frames.len() - (k + 1);
+        g.frames.truncate(k + 1);
+        g.deltas.truncate(k);
+        let state = match k {
+            0 => g.keyframe.clone(),
+            _ => decode_delta(&g.keyframe, &g.deltas[k - 1]),
+        };
+        let rewound = self.frame - g.frames[k];
+        self.frame = g.frames[k];
+        Some((state, rewound))
+    }
+}
+
+//
+// Delta encoding: sequence of (number of unchanged bytes, number of changed
+// bytes, XOR of the changed bytes), counts being LEB128 varints.
+//
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*pos];
+        *pos += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+fn encode_delta(base: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut k = 0;
+    while k < state.len() {
+        let start = k;
+        while k < state.len() && base[k] == state[k] {
+            k += 1;
+        }
+        if k == state.len() {
+            break;
+        }
+        let zeros = k - start;
+        let changed = k;
+        while k < state.len() && base[k] != state[k] {
+            k += 1;
+        }
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, k - changed);
+        out.extend(base[changed..k].iter().zip(&state[changed..k]).map(|(a, b)| a ^ b));
+    }
+    out
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = base.to_vec();
+    let (mut pos, mut k) = (0, 0);
+    while pos < delta.len() {
+        k += read_varint(delta, &mut pos);
+        let n = read_varint(delta, &mut pos);
+        for (s, d) in state[k..k + n].iter_mut().zip(&delta[pos..pos + n]) {
+            *s ^= d;
+        }
+        k += n;
+        pos += n;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pcg32;
+
+    fn rng(seed: u64) -> Pcg32 {
+        let mut rng = Pcg32::default();
+        rng.reset(seed, 1);
+        rng
+    }
+
+    #[test]
+    fn varint() {
+        for v in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, 73_879, usize::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, v);
+            let mut pos = 0;
+            assert_eq!(read_varint(&out, &mut pos), v);
+            assert_eq!(pos, out.len());
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let mut rng = rng(0xDE17A);
+        for _ in 0..500 {
+            let len = rng.generate() as usize % 4096;
+            let base: Vec<u8> = (0..len).map(|_| rng.generate() as u8).collect();
+            // Runs of changes, short and long, up to a fully different state
+            let mut state = base.clone();
+            let runs = match rng.generate() % 4 {
+                0 => 0,
+                1 => 1,
+                _ => rng.generate() as usize % 64,
+            };
+            for _ in 0..runs.min(len) {
+                let start = rng.generate() as usize % len;
+                let n = (1 + rng.generate() as usize % 300).min(len - start);
+                for b in state[start..start + n].iter_mut() {
+                    *b = rng.generate() as u8;
+                }
+            }
+            if rng.generate() & 15 == 0 {
+                state.iter_mut().for_each(|b| *b = !*b);
+            }
+            let delta = encode_delta(&base, &state);
+            assert_eq!(decode_delta(&base, &delta), state, "{} bytes, {} runs", len, runs);
+            if state == base {
+                assert!(delta.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn eviction() {
+        let config = RewindConfig { interval: 1, capacity: 10, keyframe_interval: 4 };
+        let mut rewind = Rewind::new(config);
+        let state = |frame: u64| vec![frame as u8; 64];
+        for _ in 0..25 {
+            assert!(rewind.next_frame());
+            rewind.push(&state(rewind.frame));
+            assert!(rewind.len() <= 10);
+        }
+        // Whole groups are dropped
+        assert_eq!(rewind.len(), 9);
+        assert_eq!(rewind.groups.len(), 3);
+        assert_eq!(rewind.groups.iter().map(|g| g.frames.len()).sum::<usize>(), rewind.len());
+
+        // Back to the oldest snapshot kept
+        let oldest = 25 - rewind.len() as u64 + 1;
+        assert_eq!(rewind.seek_back(1000), Some((state(oldest), 25 - oldest)));
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.seek_back(1), Some((state(oldest), 0)));
+    }
+
+    #[test]
+    fn interval() {
+        let config = RewindConfig { interval: 3, capacity: 100, keyframe_interval: 30 };
+        let mut rewind = Rewind::new(config);
+        rewind.push(&[0]);
+        for frame in 1..=10u8 {
+            if rewind.next_frame() {
+                rewind.push(&[frame]);
+            }
+        }
+        assert_eq!(rewind.len(), 4);
+        // Snapshots at 0, 3, 6 and 9: 10 - 2 is after the newest one
+        assert_eq!(rewind.seek_back(2), Some((vec![6], 4)));
+        assert_eq!(rewind.seek_back(0), Some((vec![6], 0)));
+        assert_eq!(rewind.seek_back(6), Some((vec![0], 6)));
+    }
+
+    #[test]
+    fn keyframe_interval_clamped() {
+        let config = RewindConfig { interval: 1, capacity: 5, keyframe_interval: 20 };
+        let mut rewind = Rewind::new(config);
+        assert_eq!(rewind.config().keyframe_interval, 5);
+        for frame in 1..=30u8 {
+            assert!(rewind.next_frame());
+            rewind.push(&[frame]);
+            assert!(rewind.len() <= 5, "{} snapshots", rewind.len());
+        }
+        assert_eq!(rewind.seek_back(1000), Some((vec![26], 4)));
+    }
+}
@@ -0,0 +1,42 @@
+//
+// Built-in database of known ROMs, generated from the revival pack by
+// `scripts/make-romdb.py`.
+use crate::cpu::Platform;
+use crate::quirks::Quirks;
+use crate::utils::crc32;
+
+mod table;
+
+/// Role of a keypad key in a program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeyHint {
+    pub key: u8,
+    pub action: &'static str,
+}
+
+/// Recommended settings for a known ROM
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RomInfo {
+    /// CRC32 (IEEE) of the ROM file
+    pub crc32: u32,
+    pub title: &'static str,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub cpu_hz: u32,
+    pub keys: &'static [KeyHint],
+}
+
+/// All known ROMs, sorted by CRC32
+pub fn entries() -> &'static [RomInfo] {
+    &table::ROM_TABLE
+}
+
+/// Find the settings of a ROM given its content
+pub fn lookup(rom: &[u8]) -> Option<&'static RomInfo> {
+    lookup_crc32(crc32(rom))
+}
+
+pub fn lookup_crc32(crc: u32) -> Option<&'static RomInfo> {
+    let table = entries();
+    table.binary_search_by_key(&crc, |e| e.crc32).ok().map(|k| &table[k])
+}
@@ -0,0 +1,792 @@
+// Generated by scripts/make-romdb.py, do not edit.
+use crate::cpu::Platform;
+use crate::quirks::Quirks;
+use super::{KeyHint, RomInfo};
+
+/// Sorted by CRC32
+pub const ROM_TABLE: [RomInfo; 98] = [
+    RomInfo {
+        crc32: 0x017884e3,
+        title: "Soccer",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x02393966,
+        title: "Reversi [Philip Baltzer]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x5, action: "place marker" }, KeyHint { key: 0xF, action: "pass" }],
+    },
+    RomInfo {
+        crc32: 0x027e5abb,
+        title: "Rocket Launcher",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x040ca946,
+        title: "Puzzle",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x04291dd8,
+        title: "Clock Program [Bill Fisher, 1981]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x0501cecb,
+        title: "Guess [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x5, action: "yes" }],
+    },
+    RomInfo {
+        crc32: 0x050a0a72,
+        title: "Rush Hour [Hap, 2006]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x7, action: "left" }, KeyHint { key: 0x9, action: "right" }],
+    },
+    RomInfo {
+        crc32: 0x0614ba7f,
+        title: "Astro Dodge [Revival Studios, 2008]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }, KeyHint { key: 0x5, action: "start" }],
+    },
+    RomInfo {
+        crc32: 0x06fe7c7d,
+        title: "Fishie [Hap, 2005]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x0aeff5a0,
+        title: "Bowling [Gooitzen van der Wal]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x0ce70772,
+        title: "Tetris [Fran Dachille, 1991]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "rotate" }, KeyHint { key: 0x5, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x1, action: "drop" }],
+    },
+    RomInfo {
+        crc32: 0x0d145bce,
+        title: "Framed MK2 [GV Samways, 1980]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x0dbf7208,
+        title: "Vers [JMN, 1991]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x1096c3d5,
+        title: "Merlin [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "top left" }, KeyHint { key: 0x5, action: "top right" }, KeyHint { key: 0x1, action: "bottom left" }, KeyHint { key: 0x2, action: "bottom right" }],
+    },
+    RomInfo {
+        crc32: 0x15965766,
+        title: "X-Mirror",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x1b459fa0,
+        title: "Nim [Carmelo Cortez, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0xF, action: "play first" }],
+    },
+    RomInfo {
+        crc32: 0x1c5735aa,
+        title: "Chip8 emulator Logo [Garstyciuks]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x1da653f8,
+        title: "SQRT Test [Sergey Naydenov, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x1dd59be5,
+        title: "Trip8 Demo (2008) [Revival Studios]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x200382c1,
+        title: "Shooting Stars [Philip Baltzer, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x21a982fc,
+        title: "Craps [Camerlo Cortez, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x2b450d6a,
+        title: "Mastermind FourRow (Robert Lindley, 1978)",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x30ce37b1,
+        title: "15 Puzzle [Roger Ivie] (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x331413e7,
+        title: "UFO [Lutz V, 1992]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "shoot left" }, KeyHint { key: 0x5, action: "shoot up" }, KeyHint { key: 0x6, action: "shoot right" }],
+    },
+    RomInfo {
+        crc32: 0x37a658a2,
+        title: "Maze [David Winter, 199x]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x39199fe2,
+        title: "Maze (alt) [David Winter, 199x]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x3a297a10,
+        title: "Tic-Tac-Toe [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x3b2aea72,
+        title: "Deflection [John Fort]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x3bc80ce8,
+        title: "Cave",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x3bfced42,
+        title: "Brick (Brix hack, 1990)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }],
+    },
+    RomInfo {
+        crc32: 0x3f15d84e,
+        title: "Spooky Spot [Joseph Weisbecker, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x0, action: "ask" }],
+    },
+    RomInfo {
+        crc32: 0x42092885,
+        title: "Minimal game [Revival Studios, 2007]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x428c1e4d,
+        title: "Rocket [Joseph Weisbecker, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x432e2fe1,
+        title: "Guess [David Winter] (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x5, action: "yes" }],
+    },
+    RomInfo {
+        crc32: 0x4b7cf2cd,
+        title: "Jumping X and O [Harry Kleinberg, 1977]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x4db1c26c,
+        title: "Timebomb",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x4e8693f1,
+        title: "15 Puzzle [Roger Ivie]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }],
+    },
+    RomInfo {
+        crc32: 0x511cdd7a,
+        title: "Stars [Sergey Naydenov, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x51c9528b,
+        title: "Sum Fun [Joyce Weisbecker]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x53b431fc,
+        title: "Particle Demo [zeroZshadow, 2008]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x5a83ff48,
+        title: "Sequence Shoot [Joyce Weisbecker]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0xC, action: "shoot 1" }, KeyHint { key: 0xD, action: "shoot 2" }, KeyHint { key: 0xE, action: "shoot 3" }, KeyHint { key: 0xF, action: "shoot 4" }],
+    },
+    RomInfo {
+        crc32: 0x5c786254,
+        title: "Paddles",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x608c6ab0,
+        title: "Vertical Brix [Paul Robson, 1996]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x61861ae5,
+        title: "Hidden [David Winter, 1996]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }, KeyHint { key: 0x5, action: "select" }],
+    },
+    RomInfo {
+        crc32: 0x6465acef,
+        title: "Animal Race [Brian Astle]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x64a9054b,
+        title: "Wall [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x65c3421b,
+        title: "ZeroPong [zeroZshadow, 2007]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x67a9c567,
+        title: "Figures",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x67e4bf9c,
+        title: "Syzygy [Roy Trevino, 1990]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x1, action: "left" }, KeyHint { key: 0x2, action: "right" }, KeyHint { key: 0x6, action: "up" }, KeyHint { key: 0x9, action: "down" }],
+    },
+    RomInfo {
+        crc32: 0x69970ad2,
+        title: "Pong (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6e1d4e9b,
+        title: "Keypad Test [Hap, 2006]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6e485c29,
+        title: "Missile [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6e9ccb66,
+        title: "Hi-Lo [Jef Winsor, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6ea76947,
+        title: "Blinky [Hans Christian Egeberg] (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 1000,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6efd1f32,
+        title: "Random Number Test [Matthew Mikolay, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6fd89b3d,
+        title: "Airplane",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x6ff0a017,
+        title: "Space Invaders [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x5, action: "shoot" }],
+    },
+    RomInfo {
+        crc32: 0x746a9de0,
+        title: "Slide [Joyce Weisbecker]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x0, action: "stop puck" }],
+    },
+    RomInfo {
+        crc32: 0x7d6a9ed9,
+        title: "Zero Demo [zeroZshadow, 2007]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x7d75a857,
+        title: "Pong [Paul Vervalin, 1990]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x1, action: "left up" }, KeyHint { key: 0x4, action: "left down" }, KeyHint { key: 0xC, action: "right up" }, KeyHint { key: 0xD, action: "right down" }],
+    },
+    RomInfo {
+        crc32: 0x801843e0,
+        title: "Squash [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x804d282c,
+        title: "Landing",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x80cb3466,
+        title: "BMP Viewer - Hello (C8 example) [Hap, 2005]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x841fde23,
+        title: "Pong (1 player)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x8c99c724,
+        title: "Space Intercept [Joseph Weisbecker, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x1, action: "large UFO" }, KeyHint { key: 0x2, action: "small UFO" }, KeyHint { key: 0x4, action: "launch left" }, KeyHint { key: 0x5, action: "launch" }, KeyHint { key: 0x6, action: "launch right" }],
+    },
+    RomInfo {
+        crc32: 0x8d274549,
+        title: "Coin Flipping [Carmelo Cortez, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x9858889b,
+        title: "Connect 4 [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x5, action: "drop coin" }],
+    },
+    RomInfo {
+        crc32: 0x9d307e90,
+        title: "Blinky [Hans Christian Egeberg, 1991]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 1000,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x9e738d35,
+        title: "Chip8 Picture",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0x9fdb8801,
+        title: "Delay Timer Test [Matthew Mikolay, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x2, action: "increase" }, KeyHint { key: 0x8, action: "decrease" }, KeyHint { key: 0x5, action: "start timer" }],
+    },
+    RomInfo {
+        crc32: 0xa423f9e7,
+        title: "Rush Hour [Hap, 2006] (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xa6bca0f7,
+        title: "Breakout (Brix hack) [David Winter, 1997]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }],
+    },
+    RomInfo {
+        crc32: 0xa929cb73,
+        title: "Tank",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }, KeyHint { key: 0x5, action: "fire" }],
+    },
+    RomInfo {
+        crc32: 0xaaa44d0b,
+        title: "Brix [Andreas Gustafsson, 1990]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }],
+    },
+    RomInfo {
+        crc32: 0xac46b66d,
+        title: "Pong 2 (Pong hack) [David Winter, 1997]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xb197ce7a,
+        title: "Biorhythm [Jef Winsor]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xb2696048,
+        title: "Wipe Off [Joseph Weisbecker]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xbb286fea,
+        title: "Rocket Launch [Jonas Lindstedt]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xc20dc1ab,
+        title: "Addition Problems [Paul C. Moews]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xc46ca868,
+        title: "IBM Logo",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xc73ba60c,
+        title: "Kaleidoscope [Joseph Weisbecker, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }, KeyHint { key: 0x0, action: "repeat pattern" }],
+    },
+    RomInfo {
+        crc32: 0xc77a1852,
+        title: "Tapeworm [JDR, 1999]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xcb331b6a,
+        title: "Submarine [Carmelo Cortez, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x5, action: "fire" }],
+    },
+    RomInfo {
+        crc32: 0xcc8eec70,
+        title: "Programmable Spacefighters [Jef Winsor]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xd106c808,
+        title: "Blitz [David Winter]",
+        platform: Platform::Chip8,
+        quirks: Quirks { clip_sprites: true, ..Quirks::legacy() },
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x5, action: "drop bomb" }],
+    },
+    RomInfo {
+        crc32: 0xdbc74090,
+        title: "Space Flight",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xdd68f8d1,
+        title: "Russian Roulette [Carmelo Cortez, 1978]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xe5b40a11,
+        title: "Tron",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xe941c6d7,
+        title: "Most Dangerous Game [Peter Maruhnic]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x2, action: "up" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x8, action: "down" }, KeyHint { key: 0x0, action: "end turn" }],
+    },
+    RomInfo {
+        crc32: 0xead625b8,
+        title: "Space Invaders [David Winter] (alt)",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }, KeyHint { key: 0x5, action: "shoot" }],
+    },
+    RomInfo {
+        crc32: 0xec14266c,
+        title: "Sierpinski [Sergey Naydenov, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xf0ec9a3d,
+        title: "Lunar Lander (Udo Pernisz, 1979)",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[KeyHint { key: 0x2, action: "thrust" }, KeyHint { key: 0x4, action: "left" }, KeyHint { key: 0x6, action: "right" }],
+    },
+    RomInfo {
+        crc32: 0xf6faf242,
+        title: "Division Test [Sergey Naydenov, 2010]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xfafdb137,
+        title: "Framed MK1 [GV Samways, 1980]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xfb592cc5,
+        title: "Filter",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xfcfbe07d,
+        title: "Worm V4 [RB-Revival Studios, 2007]",
+        platform: Platform::Chip8,
+        quirks: Quirks::legacy(),
+        cpu_hz: 700,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xfd978291,
+        title: "Life [GV Samways, 1980]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+    RomInfo {
+        crc32: 0xfe8c859b,
+        title: "Breakout [Carmelo Cortez, 1979]",
+        platform: Platform::Chip8,
+        quirks: Quirks::cosmac_vip(),
+        cpu_hz: 500,
+        keys: &[],
+    },
+];
@@ -1,30 +1,138 @@
+use core::ops::Range;
+
+use crate::state::{StateError, StateReader, StateWriter};
+
 type Point2i = (i32, i32);
 
 pub const CHIP8_FB_W: usize = 64;
 pub const CHIP8_FB_H: usize = 32;
 
-pub type Chip8Fb = [u32; CHIP8_FB_W * CHIP8_FB_H];
+pub const SCHIP_FB_W: usize = 128;
+pub const SCHIP_FB_H: usize = 64;
+
+/// Backing storage of the screen, large enough for any resolution
+pub type Chip8Fb = [u32; SCHIP_FB_W * SCHIP_FB_H];
+
+/// Number of bitplanes (XO-CHIP)
+pub const SCREEN_PLANES: usize = 2;
+
+/// Colors of the four plane combinations (none, 1, 2, both), in RGBA byte
+/// order.
+pub const DEFAULT_PALETTE: [u32; 1 << SCREEN_PLANES] = [
+    0x0000_0000,
+    0xFFFF_FFFF,
+    0xFFAA_AAAA,
+    0xFF55_5555,
+];
+
+/// Rectangle of the screen which changed, in the layout of `Screen::data`
+/// (rows stored bottom-up if the Y axis is inverted)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Damage {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Damage {
+    /// Rows of `Screen::data` covered by the rectangle
+    pub fn rows(&self) -> Range<u32> {
+        self.y..self.y + self.height
+    }
+
+    /// Columns covered by the rectangle
+    pub fn columns(&self) -> Range<u32> {
+        self.x..self.x + self.width
+    }
+}
 
 #[derive(Clone)]
 pub struct Screen {
     inverted_y: bool,
+    /// Rendered colors, derived from `planes` through the palette
     fb: Chip8Fb,
+    /// Bitmask of the planes set for each pixel
+    planes: [u8; SCHIP_FB_W * SCHIP_FB_H],
+    palette: [u32; 1 << SCREEN_PLANES],
+    selected_planes: u8,
     width: u32,
     height: u32,
+    /// Bounding box of the pixels changed since the last `take_damage`, as
+    /// (min x, min y, max x, max y) in storage coordinates
+    dirty: Option<(u32, u32, u32, u32)>,
+    /// Incremented on every change, see `change_count`
+    changes: u32,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Screen {
     pub fn new() -> Self {
         Screen {
             inverted_y: true,
-            fb: [0; CHIP8_FB_W * CHIP8_FB_H],
+            fb: [0; SCHIP_FB_W * SCHIP_FB_H],
+            planes: [0; SCHIP_FB_W * SCHIP_FB_H],
+            palette: DEFAULT_PALETTE,
+            selected_planes: 1,
             width: CHIP8_FB_W as u32,
             height: CHIP8_FB_H as u32,
+            dirty: Some((0, 0, CHIP8_FB_W as u32 - 1, CHIP8_FB_H as u32 - 1)),
+            changes: 0,
         }
     }
 
     pub fn set_inverted_y(&mut self, b: bool) {
         self.inverted_y = b;
+        self.damage_all();
+    }
+
+    /// True if the rows of `data` and `planes` are stored bottom-up
+    pub fn is_inverted_y(&self) -> bool {
+        self.inverted_y
+    }
+
+    /// Switch between the 64x32 and 128x64 (SUPER-CHIP) modes. The screen
+    /// content is cleared.
+    pub fn set_hires(&mut self, hires: bool) {
+        let (w, h) = if hires {
+            (SCHIP_FB_W, SCHIP_FB_H)
+        } else {
+            (CHIP8_FB_W, CHIP8_FB_H)
+        };
+        self.width = w as u32;
+        self.height = h as u32;
+        self.clear();
+        self.damage_all();
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width as usize == SCHIP_FB_W
+    }
+
+    pub fn palette(&self) -> &[u32; 1 << SCREEN_PLANES] {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: [u32; 1 << SCREEN_PLANES]) {
+        self.palette = palette;
+        for (px, p) in self.fb.iter_mut().zip(self.planes.iter()) {
+            *px = palette[*p as usize];
+        }
+        self.damage_all();
+    }
+
+    /// Planes affected by drawing, clearing and scrolling instructions
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << SCREEN_PLANES) - 1) as u8;
     }
 
     fn px_index(&self, coords: Point2i) -> usize {
@@ -34,8 +142,69 @@ impl Screen {
         k as usize
     }
 
+    fn write_px(&mut self, k: usize, planes: u8) {
+        if self.planes[k] == planes {
+            return;
+        }
+        self.planes[k] = planes;
+        self.fb[k] = self.palette[planes as usize];
+        // Pixels outside of the current resolution are not displayed
+        let w = self.width as usize;
+        if k < w * self.height as usize {
+            let (x, y) = ((k % w) as u32, (k / w) as u32);
+            self.damage(x, y, x, y);
+        }
+    }
+
+    fn damage(&mut self, x0: u32, y0: u32, x1: u32, y1: u32) {
+        self.changes = self.changes.wrapping_add(1);
+        self.dirty = Some(match self.dirty {
+            Some((dx0, dy0, dx1, dy1)) => (dx0.min(x0), dy0.min(y0), dx1.max(x1), dy1.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+
+    fn damage_all(&mut self) {
+        self.damage(0, 0, self.width - 1, self.height - 1);
+    }
+
+    /// True if the screen changed since the last `take_damage`
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Region changed since the last call, to update only that part of a
+    /// display. A change of resolution, palette or orientation damages the
+    /// whole screen.
+    pub fn take_damage(&mut self) -> Option<Damage> {
+        self.dirty.take().map(|(x0, y0, x1, y1)| Damage {
+            x: x0,
+            y: y0,
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+        })
+    }
+
+    /// Counter of the changes, wrapping around. The screen changed between
+    /// two calls if the values differ, `take_damage` being left to the
+    /// display.
+    pub fn change_count(&self) -> u32 {
+        self.changes
+    }
+
     pub fn data(&self) -> &[u32] {
-        &self.fb
+        &self.fb[..(self.width * self.height) as usize]
+    }
+
+    /// Raw plane bitmasks, same layout as `data`
+    pub fn planes(&self) -> &[u8] {
+        &self.planes[..(self.width * self.height) as usize]
+    }
+
+    /// Planes set at (x, y), y = 0 being the top row whatever the storage
+    /// order
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.planes[self.px_index((x as i32, y as i32))]
     }
 
     /// Get (width, height)
@@ -50,19 +219,127 @@ impl Screen {
         self.height
     }
 
-    pub fn set_pixel(&mut self, coords: Point2i, value: u32) {
-        let k = (coords.1 as u32) * self.width + coords.0 as u32;
-        self.fb[k as usize] = value;
+    pub fn set_pixel(&mut self, coords: Point2i, planes: u8) {
+        let k = self.px_index(coords);
+        self.write_px(k, planes);
     }
 
-    pub fn xor_pixel(&mut self, coords: Point2i, value: u32) -> bool {
+    /// Flip the given planes of a pixel, returns true if any of them was set
+    pub fn xor_pixel(&mut self, coords: Point2i, planes: u8) -> bool {
         let k = self.px_index(coords);
-        let old_px = self.fb[k as usize];
-        self.fb[k] ^= value;
-        old_px != 0 && value != 0
+        let old_px = self.planes[k];
+        self.write_px(k, old_px ^ planes);
+        old_px & planes != 0
     }
 
-    pub fn clear(&mut self, color: u32) {
-        self.fb.iter_mut().for_each(|x| *x = color);
+    /// Reset all planes
+    pub fn clear(&mut self) {
+        self.clear_planes(0xFF);
+    }
+
+    pub fn clear_planes(&mut self, mask: u8) {
+        for k in 0..self.planes.len() {
+            self.write_px(k, self.planes[k] & !mask);
+        }
+    }
+
+    /// Move the content of the selected planes n pixels down, the top rows
+    /// are cleared
+    pub fn scroll_down(&mut self, n: u32) {
+        for y in (0..self.height as i32).rev() {
+            self.copy_row(y, y - n as i32);
+        }
+    }
+
+    /// Move the content of the selected planes n pixels up, the bottom rows
+    /// are cleared
+    pub fn scroll_up(&mut self, n: u32) {
+        for y in 0..self.height as i32 {
+            self.copy_row(y, y + n as i32);
+        }
+    }
+
+    /// Move the content of the selected planes n pixels right, the left
+    /// columns are cleared
+    pub fn scroll_right(&mut self, n: u32) {
+        for y in 0..self.height as i32 {
+            for x in (0..self.width as i32).rev() {
+                self.copy_px((x, y), (x - n as i32, y));
+            }
+        }
+    }
+
+    /// Move the content of the selected planes n pixels left, the right
+    /// columns are cleared
+    pub fn scroll_left(&mut self, n: u32) {
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                self.copy_px((x, y), (x + n as i32, y));
+            }
+        }
+    }
+
+    /// Copy row `src` into row `dst` (logical coordinates), clear `dst` if
+    /// `src` is off-screen.
+    fn copy_row(&mut self, dst: i32, src: i32) {
+        for x in 0..self.width as i32 {
+            self.copy_px((x, dst), (x, src));
+        }
+    }
+
+    /// Copy the selected planes of pixel `src` into `dst`, clear them if
+    /// `src` is off-screen.
+    fn copy_px(&mut self, dst: Point2i, src: Point2i) {
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mask = self.selected_planes;
+        let src_px = if src.0 < 0 || src.0 >= w || src.1 < 0 || src.1 >= h {
+            0
+        } else {
+            self.planes[self.px_index(src)]
+        };
+        let k = self.px_index(dst);
+        self.write_px(k, (self.planes[k] & !mask) | (src_px & mask));
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.width as u16);
+        w.u16(self.height as u16);
+        w.u8(self.selected_planes);
+        // Rows top-down whatever the storage order, which differs between
+        // the frontends
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                w.u8(self.planes[self.px_index((x, y))]);
+            }
+        }
+        w.bytes(&self.planes[(self.width * self.height) as usize..]);
+    }
+
+    /// Restore the content of the screen, the display settings (palette and
+    /// Y orientation) are kept.
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let dims = (r.u16() as usize, r.u16() as usize);
+        if dims != (CHIP8_FB_W, CHIP8_FB_H) && dims != (SCHIP_FB_W, SCHIP_FB_H) {
+            return Err(StateError::InvalidValue("screen dimensions"));
+        }
+        self.width = dims.0 as u32;
+        self.height = dims.1 as u32;
+        self.selected_planes = r.u8();
+        if self.selected_planes >= 1 << SCREEN_PLANES {
+            return Err(StateError::InvalidValue("screen selected planes"));
+        }
+        let mut planes = [0; SCHIP_FB_W * SCHIP_FB_H];
+        r.bytes(&mut planes);
+        if planes.iter().any(|p| *p >= 1 << SCREEN_PLANES) {
+            return Err(StateError::InvalidValue("screen planes"));
+        }
+        let w = self.width as usize;
+        for (k, p) in planes.iter().enumerate() {
+            let i = if k < w * self.height as usize { self.px_index(((k % w) as i32, (k / w) as i32)) } else { k };
+            self.planes[i] = *p;
+        }
+        // Damages the whole screen
+        self.set_palette(self.palette);
+        Ok(())
     }
 }
@@ -0,0 +1,248 @@
+//
+// Save states
+//
+// Portable binary snapshot of the complete emulator state, little-endian,
+// with a fixed size of STATE_SIZE bytes:
+//
+//   offset  size   content
+//   0       4      magic "C8ST"
+//   4       2      format version
+//   6       19     emulator: cpu_hz, periph_hz, sim_ms, advance carry, VIP
+//                  debt, timing model
+//   25      70     cpu: platform, quirks, status, V0-VF, I, stack, pc, sp,
+//                  cycles
+//   95      73790  peripherals: memory, screen, keypad, timers, RNG, RPL
+//                  flags, audio pattern, pitch
+//   73885   4      CRC32 of all the preceding bytes
+//
+// Display settings (palette, orientation of the Y axis) belong to the
+// frontend and are not part of the state.
+use core::fmt;
+
+use crate::utils::crc32;
+
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+
+/// Version of the format, incremented on any layout change once released
+pub const STATE_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 6;
+const EMULATOR_SIZE: usize = 19;
+const CPU_SIZE: usize = 70;
+const SCREEN_SIZE: usize = 5 + crate::screen::SCHIP_FB_W * crate::screen::SCHIP_FB_H;
+const PERIPH_SIZE: usize = crate::emu::MEMORY_SIZE + SCREEN_SIZE + 57;
+
+/// Size of a save state, in bytes
+pub const STATE_SIZE: usize = HEADER_SIZE + EMULATOR_SIZE + CPU_SIZE + PERIPH_SIZE + 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StateError {
+    /// The output buffer is smaller than `STATE_SIZE`
+    BufferTooSmall,
+    /// The input does not have the size of a save state
+    InvalidLength,
+    /// The input is not a save state
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The input is corrupted
+    ChecksumMismatch,
+    /// A field holds an out of range value
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BufferTooSmall => write!(f, "buffer too small for a save state"),
+            StateError::InvalidLength => write!(f, "invalid save state length"),
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::ChecksumMismatch => write!(f, "corrupted save state (bad checksum)"),
+            StateError::InvalidValue(field) => write!(f, "invalid value of '{}' in save state", field),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+/// Sequential writer, into a buffer of at least STATE_SIZE bytes
+pub(crate) struct StateWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> StateWriter<'a> {
+    /// Start a state, writing its header
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, StateError> {
+        if buf.len() < STATE_SIZE {
+            return Err(StateError::BufferTooSmall);
+        }
+        let mut w = StateWriter { buf, pos: 0 };
+        w.bytes(&STATE_MAGIC);
+        w.u16(STATE_VERSION);
+        Ok(w)
+    }
+
+    /// Append the checksum, returns the size of the state
+    pub fn finish(mut self) -> usize {
+        debug_assert_eq!(self.pos, STATE_SIZE - 4);
+        let crc = crc32(&self.buf[..self.pos]);
+        self.u32(crc);
+        self.pos
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.bytes(&[v]);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+}
+
+/// Sequential reader of a validated state
+pub(crate) struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Check the header, size and checksum of a state
+    pub fn new(buf: &'a [u8]) -> Result<Self, StateError> {
+        if buf.len() < HEADER_SIZE || buf[..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([buf[4], buf[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if buf.len() != STATE_SIZE {
+            return Err(StateError::InvalidLength);
+        }
+        let (data, crc) = buf.split_at(STATE_SIZE - 4);
+        if crc32(data).to_le_bytes() != crc {
+            return Err(StateError::ChecksumMismatch);
+        }
+        Ok(StateReader { buf: data, pos: HEADER_SIZE })
+    }
+
+    pub fn bytes(&mut self, out: &mut [u8]) {
+        out.copy_from_slice(&self.buf[self.pos..self.pos + out.len()]);
+        self.pos += out.len();
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        let mut b = [0; 1];
+        self.bytes(&mut b);
+        b[0]
+    }
+
+    pub fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8() {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue(field)),
+        }
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        let mut b = [0; 2];
+        self.bytes(&mut b);
+        u16::from_le_bytes(b)
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        let mut b = [0; 4];
+        self.bytes(&mut b);
+        u32::from_le_bytes(b)
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        let mut b = [0; 8];
+        self.bytes(&mut b);
+        u64::from_le_bytes(b)
+    }
+}
+
+//
+// Serde support: the emulator is serialized as the bytes of its save state
+//
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use core::fmt;
+
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::ser;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::STATE_SIZE;
+    use crate::Chip8Emulator;
+
+    impl Serialize for Chip8Emulator {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut buf = [0u8; STATE_SIZE];
+            self.save_state(&mut buf).map_err(ser::Error::custom)?;
+            serializer.serialize_bytes(&buf)
+        }
+    }
+
+    struct StateVisitor;
+
+    impl StateVisitor {
+        fn load<E: de::Error>(data: &[u8]) -> Result<Chip8Emulator, E> {
+            let mut emu = Chip8Emulator::new(0);
+            emu.load_state(data).map_err(E::custom)?;
+            Ok(emu)
+        }
+    }
+
+    impl<'de> Visitor<'de> for StateVisitor {
+        type Value = Chip8Emulator;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a CHIP-8 save state")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Self::load(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut buf = [0u8; STATE_SIZE];
+            let mut n = 0;
+            while let Some(b) = seq.next_element::<u8>()? {
+                if n == STATE_SIZE {
+                    return Err(de::Error::invalid_length(n + 1, &self));
+                }
+                buf[n] = b;
+                n += 1;
+            }
+            Self::load(&buf[..n])
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Chip8Emulator {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_bytes(StateVisitor)
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//
+// Timing models, deciding how long instructions take relative to the 60Hz
+// frames.
+//
+// The flat model runs `cpu_hz` instructions per second, whatever they are.
+// The COSMAC VIP model follows the original interpreter, running on an RCA
+// 1802 at 1.76064 MHz (8 clocks per machine cycle):
+//
+// * A frame lasts 3668 machine cycles. The video interrupt and the display
+//   DMA take about half of them, leaving 1836 for the interpreter.
+// * Each instruction has a cost in machine cycles, including the fetch and
+//   decode loop. The costs follow the instruction timings of Laurence
+//   Scotford's analysis of the interpreter listing ("Chip-8 on the COSMAC
+//   VIP"), simplified where they depend on the data (a sprite only by its
+//   height and alignment).
+// * `DrawSprite` waits for the vertical blank, so at most one sprite is drawn
+//   per frame.
+// * The timers are decremented by the video interrupt, at the end of frames.
+use core::str::FromStr;
+
+use crate::cpu::Insn;
+
+/// Clock of the COSMAC VIP CPU
+const VIP_CLOCK_HZ: u32 = 1_760_640;
+
+/// Machine cycles of a COSMAC VIP frame (3668), 8 clocks each
+pub const VIP_CYCLES_PER_FRAME: u32 = VIP_CLOCK_HZ / 8 / 60;
+
+/// Machine cycles of a frame spent in the video interrupt and the display
+/// DMA
+const VIP_DISPLAY_CYCLES: u32 = 1832;
+
+/// Machine cycles of a frame left to the interpreter (1836)
+pub const VIP_FRAME_BUDGET: u32 = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+
+/// Fetch and decode of an instruction by the interpreter loop, also spent
+/// when halted
+pub(crate) const VIP_FETCH: u32 = 40;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum TimingModel {
+    /// Every instruction takes one cycle at `cpu_hz`
+    #[default]
+    Flat,
+    /// Cycle costs and display wait of the COSMAC VIP interpreter, `cpu_hz`
+    /// is ignored
+    CosmacVip,
+}
+
+impl TimingModel {
+    pub(crate) fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(TimingModel::Flat),
+            1 => Some(TimingModel::CosmacVip),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TimingModel {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(TimingModel::Flat),
+            "vip" => Ok(TimingModel::CosmacVip),
+            _ => Err("unknown timing model (expected: flat, vip)"),
+        }
+    }
+}
+
+/// Machine cycles taken by the VIP interpreter to execute `insn`. `v` are
+/// the registers before the execution (a sprite drawn at VF changes it),
+/// and `skipped` tells if a conditional skip was taken.
+pub(crate) fn vip_cycles(insn: &Insn, v: &[u8; 16], skipped: bool) -> u32 {
+    let vx = |r: u8| v[r as usize] as u32;
+    let skip = |base| if skipped { base + 4 } else { base };
+    let cost = match *insn {
+        // Clears the 256 bytes of the display buffer, one at a time
+        Insn::Cls => 3078,
+        Insn::Ret => 10,
+        Insn::Jump(_) => 12,
+        Insn::JumpV0(_) => 22,
+        Insn::Call(_) => 26,
+        Insn::SkipEqI(..) | Insn::SkipNeqI(..) => skip(10),
+        Insn::SkipEq(..) | Insn::SkipNeq(..) => skip(14),
+        Insn::LoadI(..) => 6,
+        Insn::AddI(..) => 10,
+        Insn::Move(..)
+        | Insn::Or(..)
+        | Insn::And(..)
+        | Insn::Xor(..)
+        | Insn::Add(..)
+        | Insn::Sub(..)
+        | Insn::Shr(..)
+        | Insn::SubN(..)
+        | Insn::Shl(..) => 44,
+        Insn::LoadA(_) => 12,
+        Insn::AddA(_) => 16,
+        Insn::RndAnd(..) => 36,
+        // Unaligned rows span two bytes of the display buffer
+        Insn::DrawSprite(x, _, n) => {
+            let row = if vx(x) % 8 == 0 { 34 } else { 46 };
+            26 + row * n as u32
+        }
+        Insn::SkipKeyPressed(_) | Insn::SkipKeyNPressed(_) => skip(14),
+        Insn::LoadTimer(_) | Insn::WaitForKey(_) | Insn::SetDelayTimer(_) | Insn::SetSoundTimer(_) => 10,
+        Insn::SpriteLoc(_) => 16,
+        // Repeated subtractions of 100, 10 and 1
+        Insn::StoreBCD(x) => {
+            let v = vx(x);
+            80 + 16 * (v / 100 + v / 10 % 10 + v % 10)
+        }
+        Insn::StoreRegs(x) | Insn::LoadRegs(x) => 14 + 14 * (x as u32 + 1),
+        // Not part of the VIP instruction set
+        _ => 10,
+    };
+    VIP_FETCH + cost
+}
@@ -0,0 +1,243 @@
+//
+// Execution tracing
+//
+// `Chip8Cpu::tick_traced` (and `Chip8Emulator::tick_traced`) report every
+// executed instruction to a `TraceHook`. With the `std` feature, sinks are
+// provided to log the instructions in three formats:
+//
+// * Text: one line per instruction, with the registers it modified
+//
+//       cycle       pc    opcode    instruction           changes
+//       12          0204  6105      LD V1, 0x05           V1: 00 -> 05
+//
+// * Compat: the state *before* each instruction on a single line, as logged
+//   by other CHIP-8 emulators, to diff against their traces
+//
+//       PC:0204 OP:6105 V0:00 V1:00 [...] VF:00 I:0000 SP:00
+//
+// * Binary: header "C8TR" and format version (u16), followed by 33-byte
+//   little-endian records: cycle (u64, counting this instruction), pc
+//   (u16), opcode (u32), then the state after the instruction: V0-VF, I
+//   (u16), SP (u8). `TraceReader` reads them back.
+use crate::cpu::{CpuSnapshot, Insn};
+
+/// An executed instruction
+pub struct TraceEvent<'a> {
+    pub pc: u16,
+    /// Raw opcode, 32 bits for the XO-CHIP long instructions
+    pub opcode: u32,
+    pub insn: &'a Insn,
+    pub before: &'a CpuSnapshot,
+    pub after: &'a CpuSnapshot,
+}
+
+pub trait TraceHook {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+#[cfg(feature = "std")]
+pub use sinks::*;
+
+#[cfg(feature = "std")]
+mod sinks {
+    use std::io::{self, ErrorKind, Read, Write};
+    use std::str::FromStr;
+
+    use super::{TraceEvent, TraceHook};
+
+    pub const TRACE_MAGIC: [u8; 4] = *b"C8TR";
+
+    /// Version of the binary format, incremented on any layout change
+    pub const TRACE_VERSION: u16 = 1;
+
+    /// Size of a record of the binary format
+    pub const TRACE_RECORD_SIZE: usize = 33;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum TraceFormat {
+        Text,
+        Compat,
+        Binary,
+    }
+
+    impl FromStr for TraceFormat {
+        type Err = &'static str;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "text" => Ok(TraceFormat::Text),
+                "compat" => Ok(TraceFormat::Compat),
+                "binary" => Ok(TraceFormat::Binary),
+                _ => Err("unknown trace format (expected: text, compat, binary)"),
+            }
+        }
+    }
+
+    /// Writes the events to `out` in one of the formats. The first write
+    /// error stops the logging, it is reported by `finish`.
+    pub struct TraceWriter<W: Write> {
+        out: W,
+        format: TraceFormat,
+        result: io::Result<()>,
+    }
+
+    impl<W: Write> TraceWriter<W> {
+        pub fn new(out: W, format: TraceFormat) -> Self {
+            let mut w = TraceWriter { out, format, result: Ok(()) };
+            if format == TraceFormat::Binary {
+                w.result = w
+                    .out
+                    .write_all(&TRACE_MAGIC)
+                    .and_then(|_| w.out.write_all(&TRACE_VERSION.to_le_bytes()));
+            }
+            w
+        }
+
+        /// Flush the output, returns it or the first error
+        pub fn finish(mut self) -> io::Result<W> {
+            self.result?;
+            self.out.flush()?;
+            Ok(self.out)
+        }
+
+        fn write_event(&mut self, e: &TraceEvent) -> io::Result<()> {
+            match self.format {
+                TraceFormat::Text => write_text(&mut self.out, e),
+                TraceFormat::Compat => write_compat(&mut self.out, e),
+                TraceFormat::Binary => write_binary(&mut self.out, e),
+            }
+        }
+    }
+
+    impl<W: Write> TraceHook for TraceWriter<W> {
+        fn trace(&mut self, event: &TraceEvent) {
+            if self.result.is_ok() {
+                self.result = self.write_event(event);
+            }
+        }
+    }
+
+    fn write_opcode<W: Write>(out: &mut W, opcode: u32) -> io::Result<()> {
+        if opcode > 0xFFFF {
+            write!(out, "{:08X}", opcode)
+        } else {
+            write!(out, "{:04X}    ", opcode)
+        }
+    }
+
+    fn write_text<W: Write>(out: &mut W, e: &TraceEvent) -> io::Result<()> {
+        let (before, after) = (e.before, e.after);
+        write!(out, "{:<10}  {:04X}  ", after.cycles, e.pc)?;
+        write_opcode(out, e.opcode)?;
+        let mut changes = Vec::new();
+        for (r, (x, y)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if x != y {
+                changes.push(format!("V{:X}: {:02X} -> {:02X}", r, x, y));
+            }
+        }
+        if before.i != after.i {
+            changes.push(format!("I: {:04X} -> {:04X}", before.i, after.i));
+        }
+        if before.sp != after.sp {
+            changes.push(format!("SP: {} -> {}", before.sp, after.sp));
+        }
+        if changes.is_empty() {
+            writeln!(out, "  {}", e.insn)
+        } else {
+            // Display does not honor the width, go through a String
+            writeln!(out, "  {:<20}  {}", e.insn.to_string(), changes.join(", "))
+        }
+    }
+
+    fn write_compat<W: Write>(out: &mut W, e: &TraceEvent) -> io::Result<()> {
+        let s = e.before;
+        write!(out, "PC:{:04X} OP:{:04X}", e.pc, e.opcode)?;
+        for (r, v) in s.v.iter().enumerate() {
+            write!(out, " V{:X}:{:02X}", r, v)?;
+        }
+        writeln!(out, " I:{:04X} SP:{:02X}", s.i, s.sp)
+    }
+
+    /// A record of the binary format
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct TraceRecord {
+        pub cycle: u64,
+        pub pc: u16,
+        pub opcode: u32,
+        /// V0-VF after the instruction
+        pub v: [u8; 16],
+        /// I after the instruction
+        pub i: u16,
+        /// SP after the instruction
+        pub sp: u8,
+    }
+
+    impl TraceRecord {
+        fn from_bytes(b: &[u8; TRACE_RECORD_SIZE]) -> Self {
+            let mut v = [0; 16];
+            v.copy_from_slice(&b[14..30]);
+            TraceRecord {
+                cycle: u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+                pc: u16::from_le_bytes([b[8], b[9]]),
+                opcode: u32::from_le_bytes([b[10], b[11], b[12], b[13]]),
+                v,
+                i: u16::from_le_bytes([b[30], b[31]]),
+                sp: b[32],
+            }
+        }
+    }
+
+    /// Iterates over the records of a trace in the binary format
+    pub struct TraceReader<R: Read> {
+        input: R,
+    }
+
+    impl<R: Read> TraceReader<R> {
+        /// Check the header of the trace
+        pub fn new(mut input: R) -> io::Result<Self> {
+            let mut header = [0; 6];
+            input.read_exact(&mut header)?;
+            if header[0..4] != TRACE_MAGIC {
+                return Err(io::Error::new(ErrorKind::InvalidData, "not a binary trace"));
+            }
+            if u16::from_le_bytes([header[4], header[5]]) != TRACE_VERSION {
+                return Err(io::Error::new(ErrorKind::InvalidData, "unsupported trace version"));
+            }
+            Ok(TraceReader { input })
+        }
+    }
+
+    impl<R: Read> Iterator for TraceReader<R> {
+        type Item = io::Result<TraceRecord>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let mut record = [0; TRACE_RECORD_SIZE];
+            let mut len = 0;
+            while len < record.len() {
+                match self.input.read(&mut record[len..]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            match len {
+                0 => None,
+                TRACE_RECORD_SIZE => Some(Ok(TraceRecord::from_bytes(&record))),
+                _ => Some(Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated trace record"))),
+            }
+        }
+    }
+
+    fn write_binary<W: Write>(out: &mut W, e: &TraceEvent) -> io::Result<()> {
+        let s = e.after;
+        let mut record = [0u8; TRACE_RECORD_SIZE];
+        record[0..8].copy_from_slice(&s.cycles.to_le_bytes());
+        record[8..10].copy_from_slice(&e.pc.to_le_bytes());
+        record[10..14].copy_from_slice(&e.opcode.to_le_bytes());
+        record[14..30].copy_from_slice(&s.v);
+        record[30..32].copy_from_slice(&s.i.to_le_bytes());
+        record[32] = s.sp as u8;
+        out.write_all(&record)
+    }
+}
@@ -1,20 +1,16 @@
 use core::num::Wrapping;
 
+use crate::state::{StateReader, StateWriter};
+
 type W64 = Wrapping<u64>;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Pcg32 {
     // Private fields
     state: u64,
     inc: u64,
 }
 
-impl Default for Pcg32 {
-    fn default() -> Self {
-        Pcg32 { state: 0, inc: 0 }
-    }
-}
-
 impl Pcg32 {
     pub fn reset(&mut self, state: u64, inc: u64) {
         self.state = 0;
@@ -34,4 +30,26 @@ impl Pcg32 {
         let result = (xor_shifted >> (rot.0 as usize)) | (xor_shifted << (shift.0 as usize));
         result.0 as u32
     }
-}
\ No newline at end of file
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.u64(self.state);
+        w.u64(self.inc);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) {
+        self.state = r.u64();
+        self.inc = r.u64();
+    }
+}
+/// CRC32 (IEEE 802.3 polynomial, as used by zlib and PNG)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,106 @@
+//
+// Helpers shared by the instruction tests
+//
+// Test programs are written as lists of `Insn` and assembled to bytes, so
+// that they stay readable and independent of the assembler crate.
+#![allow(dead_code)]
+
+use chip8::cpu::CpuError;
+use chip8::{Chip8Cpu, Chip8Emulator, Chip8Peripherals, Insn, Platform, Quirks, STATE_SIZE};
+
+pub const ROM_ADDR: u16 = 0x200;
+
+/// Assemble a program, XO-CHIP long loads taking 4 bytes
+pub fn assemble(program: &[Insn]) -> Vec<u8> {
+    let mut rom = Vec::new();
+    let mut buf = [0u8; 4];
+    for insn in program {
+        let n = insn.write_bytes(&mut buf);
+        rom.extend_from_slice(&buf[..n]);
+    }
+    rom
+}
+
+/// Save state of the emulator, `STATE_SIZE` bytes
+pub fn save_state_vec(emu: &Chip8Emulator) -> Vec<u8> {
+    let mut state = vec![0u8; STATE_SIZE];
+    assert_eq!(emu.save_state(&mut state), Ok(STATE_SIZE));
+    state
+}
+
+/// A CPU and its peripherals, running a program assembled at 0x200
+pub struct Machine {
+    pub cpu: Chip8Cpu,
+    pub periph: Chip8Peripherals,
+}
+
+impl Machine {
+    /// Platform with its default quirks
+    pub fn new(platform: Platform) -> Self {
+        Self::with_quirks(platform, platform.default_quirks())
+    }
+
+    pub fn with_quirks(platform: Platform, quirks: Quirks) -> Self {
+        let mut cpu = Chip8Cpu::new(ROM_ADDR);
+        cpu.set_platform(platform);
+        cpu.set_quirks(quirks);
+        Machine { cpu, periph: Chip8Peripherals::new() }
+    }
+
+    pub fn load(&mut self, program: &[Insn]) {
+        let rom = assemble(program);
+        let start = ROM_ADDR as usize;
+        self.periph.memory[start..start + rom.len()].copy_from_slice(&rom);
+    }
+
+    /// Execute the next `n` instructions
+    pub fn step(&mut self, n: usize) -> Result<(), CpuError> {
+        for _ in 0..n {
+            self.cpu.tick(&mut self.periph)?;
+        }
+        Ok(())
+    }
+
+    /// Load a program and execute all its instructions once
+    pub fn run(&mut self, program: &[Insn]) -> Result<(), CpuError> {
+        self.load(program);
+        self.step(program.len())
+    }
+
+    /// Execute a single instruction out of the program flow, returns the
+    /// jump target if any
+    pub fn exec(&mut self, insn: Insn) -> Result<Option<u16>, CpuError> {
+        self.cpu.exec_insn(insn, &mut self.periph)
+    }
+
+    pub fn v(&self, r: u8) -> u8 {
+        self.cpu.gpr(r).unwrap()
+    }
+
+    pub fn set_v(&mut self, r: u8, value: u8) {
+        self.cpu.set_gpr(r, value).unwrap();
+    }
+
+    /// Registers V0 to VF
+    pub fn regs(&self) -> [u8; 16] {
+        *self.cpu.gprs()
+    }
+
+    pub fn mem(&self, addr: u16, len: usize) -> &[u8] {
+        &self.periph.memory[addr as usize..addr as usize + len]
+    }
+
+    /// Coordinates of the lit pixels, top row first
+    pub fn lit_pixels(&self) -> Vec<(u32, u32)> {
+        let screen = &self.periph.screen;
+        let mut lit = Vec::new();
+        for y in 0..screen.height() {
+            for x in 0..screen.width() {
+                if screen.pixel(x, y) != 0 {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+}
@@ -0,0 +1,147 @@
+//
+// Static disassembler: code and data separation, labels and listings
+mod common;
+
+use chip8::disasm::{parse_symbols, Disassembly, Label, LabelKind};
+use chip8::{Insn, Platform};
+use common::{assemble, ROM_ADDR};
+
+fn listing(d: &Disassembly) -> String {
+    let mut out = String::new();
+    d.write_listing(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn code_and_data() {
+    let mut rom = assemble(&[
+        Insn::Jump(ROM_ADDR + 4),
+        // Never executed, decodes as a jump
+        Insn::Jump(0x234),
+        Insn::LoadA(ROM_ADDR + 12),
+        Insn::DrawSprite(0, 0, 2),
+        Insn::Call(ROM_ADDR + 14),
+        Insn::Jump(ROM_ADDR + 10),
+    ]);
+    // Sprite, then a subroutine
+    rom.extend_from_slice(&[0x81, 0x42]);
+    rom.extend_from_slice(&assemble(&[Insn::Ret]));
+    let d = Disassembly::analyze(&rom, ROM_ADDR, Platform::Chip8);
+
+    let code: Vec<u16> = (ROM_ADDR..ROM_ADDR + rom.len() as u16).filter(|a| d.is_code(*a)).collect();
+    assert_eq!(code, [0x200, 0x204, 0x206, 0x208, 0x20A, 0x20E]);
+    assert!(d.is_reachable(0x205) && !d.is_reachable(0x202) && !d.is_reachable(0x203));
+    assert!(d.is_sprite(0x20C) && d.is_sprite(0x20D) && !d.is_sprite(0x20E));
+
+    assert_eq!(d.label(0x200), Some(Label(LabelKind::Jump, 0x200)));
+    assert_eq!(d.label(0x204), Some(Label(LabelKind::Jump, 0x204)));
+    assert_eq!(d.label(0x20C), Some(Label(LabelKind::Sprite, 0x20C)));
+    assert_eq!(d.label(0x20E), Some(Label(LabelKind::Subroutine, 0x20E)));
+    assert_eq!(d.label(0x202), None);
+    assert_eq!(Label(LabelKind::Subroutine, 0x20E).to_string(), "sub_20E");
+
+    assert_eq!(
+        listing(&d),
+        "label_200:
+  0x200: 1204  JP label_204
+  0x202:       db 0x12, 0x34
+label_204:
+  0x204: A20C  LD I, sprite_20C
+  0x206: D002  DRW V0, V0, 2
+  0x208: 220E  CALL sub_20E
+label_20A:
+  0x20A: 120A  JP label_20A
+sprite_20C:
+  0x20C: 81    db 0x81  ; #......#
+  0x20D: 42    db 0x42  ; .#....#.
+sub_20E:
+  0x20E: 00EE  RET
+"
+    );
+}
+
+#[test]
+fn data_reference() {
+    // I loaded for something else than a sprite
+    let mut rom = assemble(&[Insn::LoadA(ROM_ADDR + 6), Insn::StoreBCD(0), Insn::Exit]);
+    rom.extend_from_slice(&[0; 3]);
+    let d = Disassembly::analyze(&rom, ROM_ADDR, Platform::SuperChip);
+    assert_eq!(d.label(0x206), Some(Label(LabelKind::Data, 0x206)));
+    assert!(!d.is_sprite(0x206) && !d.is_reachable(0x206));
+    assert!(listing(&d).ends_with("data_206:\n  0x206:       db 0x00, 0x00, 0x00\n"));
+}
+
+#[test]
+fn skip_targets() {
+    let rom = assemble(&[
+        Insn::SkipEqI(0, 1),
+        Insn::Jump(ROM_ADDR + 8),
+        Insn::AddI(1, 1),
+        Insn::Exit,
+        Insn::SkipKeyPressed(2),
+        Insn::Exit,
+        Insn::Exit,
+    ]);
+    let d = Disassembly::analyze(&rom, ROM_ADDR, Platform::SuperChip);
+    // Both sides of each skip are followed
+    for addr in [0x202, 0x204, 0x208, 0x20A, 0x20C] {
+        assert!(d.is_code(addr), "0x{:03X}", addr);
+    }
+    assert_eq!(d.label(0x204), Some(Label(LabelKind::Jump, 0x204)));
+    assert_eq!(d.label(0x20C), Some(Label(LabelKind::Jump, 0x20C)));
+    assert_eq!(d.label(0x20A), None);
+}
+
+#[test]
+fn skip_long_instruction() {
+    // On XO-CHIP, skips jump over the 4 bytes of `i := long`
+    let rom = assemble(&[Insn::SkipEqI(0, 1), Insn::LoadLongA(0x1234), Insn::Exit]);
+    let d = Disassembly::analyze(&rom, ROM_ADDR, Platform::XoChip);
+    assert!(d.is_code(0x202) && d.is_reachable(0x204) && !d.is_code(0x204));
+    assert_eq!(d.label(0x206), Some(Label(LabelKind::Jump, 0x206)));
+    assert!(listing(&d).contains("  0x202: F000  LD I, LONG 0x1234\n"));
+}
+
+#[test]
+fn computed_jump() {
+    let mut rom = assemble(&[Insn::LoadI(0, 2), Insn::JumpV0(ROM_ADDR + 6), Insn::Jump(ROM_ADDR + 6)]);
+    rom.extend_from_slice(&assemble(&[Insn::Exit, Insn::Exit]));
+    let d = Disassembly::analyze(&rom, ROM_ADDR, Platform::SuperChip);
+    assert!(d.is_computed_jump(0x202));
+    // The table is not traced
+    assert!(!d.is_code(0x204) && !d.is_code(0x206) && !d.is_code(0x208));
+    assert!(listing(&d).contains("  0x202: B206  JP V0, 0x206  ; computed jump, target unknown\n"));
+}
+
+#[test]
+fn symbols() {
+    let text = "# Symbols\n0x200 main\n\n0X20a   draw\n";
+    let symbols: Vec<(u16, &str)> = parse_symbols(text).collect::<Result<_, _>>().unwrap();
+    assert_eq!(symbols, [(0x200, "main"), (0x20A, "draw")]);
+    let errors: Vec<_> = parse_symbols("0x200 a\n200 b\n0x2G0 c\n0x200\n0x200 d e\n").filter_map(Result::err).collect();
+    assert_eq!(errors, [2, 3, 4, 5]);
+
+    let rom = assemble(&[Insn::Call(ROM_ADDR + 10), Insn::Jump(0x300), Insn::Exit, Insn::Exit, Insn::Exit, Insn::Ret]);
+    let mut d = Disassembly::analyze(&rom, ROM_ADDR, Platform::SuperChip);
+    let symbols = [(0x20A, "draw"), (0x300, "outside")];
+    d.set_symbols(&symbols);
+    assert_eq!(d.symbol(0x20A), Some("draw"));
+    let out = listing(&d);
+    assert!(out.starts_with("label_200:\n  0x200: 220A  CALL draw\n  0x202: 1300  JP outside\n"), "{}", out);
+    assert!(out.ends_with("draw:\n  0x20A: 00EE  RET\n"), "{}", out);
+}
+
+#[test]
+fn end_of_memory() {
+    // Instructions up to the last byte, the flow stops at the end
+    let rom = assemble(&[Insn::SkipEqI(0, 1), Insn::AddI(1, 1)]);
+    let d = Disassembly::analyze(&rom, 0xFFFC, Platform::XoChip);
+    assert!(d.is_code(0xFFFC) && d.is_code(0xFFFE) && d.is_reachable(0xFFFF));
+    assert_eq!(listing(&d), "label_FFFC:\n  0xFFFC: 3001  SE V0, 0x01\n  0xFFFE: 7101  ADD V1, 0x01\n");
+
+    // A truncated long instruction is data, and the last byte is listed
+    let rom = [0x00, 0xE0, 0xF0, 0x00];
+    let d = Disassembly::analyze(&rom, 0xFFFC, Platform::XoChip);
+    assert!(!d.is_reachable(0xFFFE));
+    assert!(listing(&d).ends_with("  0xFFFE:       db 0xF0, 0x00\n"));
+}
@@ -0,0 +1,180 @@
+//
+// Screen export: images and text, top row first in the palette colors
+#![cfg(feature = "std")]
+
+use chip8::export::ImageFormat;
+use chip8::Screen;
+
+/// Lit pixels: (x, y, planes)
+const PIXELS: [(i32, i32, u8); 6] = [(0, 0, 1), (63, 0, 2), (1, 1, 3), (2, 0, 1), (2, 1, 1), (5, 31, 1)];
+
+fn screen(inverted_y: bool) -> Screen {
+    let mut screen = Screen::new();
+    screen.set_inverted_y(inverted_y);
+    for (x, y, planes) in PIXELS.iter() {
+        screen.set_pixel((*x, *y), *planes);
+    }
+    screen
+}
+
+fn export(screen: &Screen, format: ImageFormat, scale: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    screen.write_image(&mut out, format, scale).unwrap();
+    out
+}
+
+fn text(screen: &Screen, ansi_colors: bool) -> String {
+    let mut out = Vec::new();
+    screen.write_text(&mut out, ansi_colors).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Split the header of a binary PBM or PPM
+fn split_header(image: &[u8], lines: usize) -> (&str, &[u8]) {
+    let end = image.iter().enumerate().filter(|(_, b)| **b == b'\n').nth(lines - 1).unwrap().0 + 1;
+    (std::str::from_utf8(&image[..end]).unwrap(), &image[end..])
+}
+
+#[test]
+fn ppm() {
+    let screen = screen(true);
+    let image = export(&screen, ImageFormat::Ppm, 1);
+    let (header, data) = split_header(&image, 3);
+    assert_eq!(header, "P6\n64 32\n255\n");
+    assert_eq!(data.len(), 64 * 32 * 3);
+    let rgb = |x: usize, y: usize| &data[(y * 64 + x) * 3..][..3];
+    // The palette colors, in RGBA byte order
+    assert_eq!(rgb(0, 0), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(rgb(63, 0), [0xAA, 0xAA, 0xAA]);
+    assert_eq!(rgb(1, 1), [0x55, 0x55, 0x55]);
+    assert_eq!(rgb(5, 31), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(rgb(1, 0), [0, 0, 0]);
+    assert_eq!(data.chunks(3).filter(|c| *c != [0, 0, 0]).count(), PIXELS.len());
+
+    let mut colored = screen.clone();
+    colored.set_palette([0xFF11_2233, 0xFF44_5566, 0, 0]);
+    let image = export(&colored, ImageFormat::Ppm, 1);
+    let (_, data) = split_header(&image, 3);
+    assert_eq!(data[..3], [0x66, 0x55, 0x44]);
+    assert_eq!(data[3..6], [0x33, 0x22, 0x11]);
+}
+
+#[test]
+fn ppm_scaled() {
+    let image = export(&screen(true), ImageFormat::Ppm, 3);
+    let (header, data) = split_header(&image, 3);
+    assert_eq!(header, "P6\n192 96\n255\n");
+    assert_eq!(data.len(), 192 * 96 * 3);
+    // Each pixel is a 3x3 square
+    let lit = |x: usize, y: usize| data[(y * 192 + x) * 3] != 0;
+    for y in 0..6 {
+        for x in 0..6 {
+            assert_eq!(lit(x, y), (x < 3 && y < 3) || (x >= 3 && y >= 3), "({}, {})", x, y);
+        }
+    }
+    assert!(lit(15, 93) && lit(17, 95) && !lit(14, 93) && !lit(18, 95) && !lit(15, 92));
+
+    // 0 is the same as 1
+    assert_eq!(export(&screen(true), ImageFormat::Ppm, 0), export(&screen(true), ImageFormat::Ppm, 1));
+}
+
+#[test]
+fn pbm() {
+    // Dark background: the background is black (1), the lit pixels white
+    let mut screen = screen(true);
+    let image = export(&screen, ImageFormat::Pbm, 1);
+    let (header, data) = split_header(&image, 2);
+    assert_eq!(header, "P4\n64 32\n");
+    assert_eq!(data.len(), 8 * 32);
+    assert_eq!(data[..8], [0x5F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]);
+    assert_eq!(data[8..16], [0x9F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(data[31 * 8..], [0xFB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+    // Light background: the other way around
+    screen.set_palette([0xFFFF_FFFF, 0xFF00_0000, 0xFF00_0000, 0xFF00_0000]);
+    let image = export(&screen, ImageFormat::Pbm, 2);
+    let (header, data) = split_header(&image, 2);
+    assert_eq!(header, "P4\n128 64\n");
+    assert_eq!(data.len(), 16 * 64);
+    for row in [&data[..16], &data[16..32]] {
+        assert_eq!(row[..2], [0xCC, 0x00]);
+        assert_eq!(row[15], 0x03);
+    }
+    assert_eq!(data[32..34], [0x3C, 0x00]);
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn png() {
+    let screen = screen(true);
+    let image = export(&screen, ImageFormat::Png, 2);
+    assert_eq!(image[..8], *b"\x89PNG\r\n\x1a\n");
+    let mut reader = png::Decoder::new(&image[..]).read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!((info.width, info.height), (128, 64));
+    assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+    // The same pixels as the PPM
+    let ppm = export(&screen, ImageFormat::Ppm, 2);
+    assert_eq!(data[..info.buffer_size()], *split_header(&ppm, 3).1);
+}
+
+#[test]
+fn half_blocks() {
+    let text = text(&screen(true), false);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 16);
+    assert!(lines.iter().all(|l| l.chars().count() == 64));
+    assert_eq!(lines[0], format!("\u{2580}\u{2584}\u{2588}{}\u{2580}", " ".repeat(60)));
+    assert!(lines[1..15].iter().all(|l| l.trim().is_empty()));
+    assert_eq!(lines[15], format!("     \u{2584}{}", " ".repeat(58)));
+    assert!(text.ends_with('\n'));
+
+    // In the palette colors, the upper half in the foreground
+    let text = self::text(&screen(true), true);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 16);
+    assert!(lines[0].starts_with("\x1b[38;2;255;255;255;48;2;0;0;0m\u{2580}\x1b[38;2;0;0;0;48;2;85;85;85m\u{2580}"));
+    assert!(lines.iter().all(|l| l.ends_with("\u{2580}\x1b[0m") && l.matches('\u{2580}').count() == 64));
+}
+
+#[test]
+fn inverted_y() {
+    // The same output whatever the storage order
+    let (inverted, upright) = (screen(true), screen(false));
+    assert_ne!(inverted.data(), upright.data());
+    for format in [ImageFormat::Pbm, ImageFormat::Ppm, ImageFormat::Text] {
+        assert_eq!(export(&inverted, format, 2), export(&upright, format, 2), "{:?}", format);
+    }
+    #[cfg(feature = "png")]
+    assert_eq!(export(&inverted, ImageFormat::Png, 1), export(&upright, ImageFormat::Png, 1));
+    assert_eq!((inverted.color(0, 0), inverted.color(0, 31)), (0xFFFF_FFFF, 0));
+}
+
+#[test]
+fn hires() {
+    let mut screen = Screen::new();
+    screen.set_hires(true);
+    screen.set_pixel((127, 63), 1);
+    let image = export(&screen, ImageFormat::Ppm, 1);
+    let (header, data) = split_header(&image, 3);
+    assert_eq!(header, "P6\n128 64\n255\n");
+    assert_eq!(data[data.len() - 3..], [0xFF, 0xFF, 0xFF]);
+    assert_eq!(text(&screen, false).lines().count(), 32);
+}
+
+#[test]
+fn format_names() {
+    for (name, format, extension) in [
+        ("pbm", ImageFormat::Pbm, "pbm"),
+        ("ppm", ImageFormat::Ppm, "ppm"),
+        ("text", ImageFormat::Text, "txt"),
+        ("txt", ImageFormat::Text, "txt"),
+    ] {
+        assert_eq!(name.parse(), Ok(format));
+        assert_eq!(format.extension(), extension);
+    }
+    #[cfg(feature = "png")]
+    assert_eq!("png".parse::<ImageFormat>().map(|f| f.extension()), Ok("png"));
+    assert!("gif".parse::<ImageFormat>().is_err());
+}
@@ -0,0 +1,226 @@
+//
+// Golden-image conformance tests
+//
+// Every ROM of assets/roms/revival-pack is run with the settings of the
+// ROM database, a fixed seed and a scripted input, the screen being hashed
+// at a few checkpoints. The hashes
+// are compared against tests/golden/revival-pack.txt, any change of the
+// emulation showing up as a mismatch. After an intended change, the file
+// is regenerated with:
+//
+//     CHIP8_UPDATE_GOLDEN=1 cargo test -p chip8 --features romdb --test golden
+//
+// and the differences reviewed like any other change.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::{crc32, romdb, Chip8Emulator, CHIP8_PERIPH_HZ};
+
+const SEED: u64 = 0x1234_56789;
+
+/// Frames at which the screen is hashed, the last one ending the run
+const CHECKPOINTS: [u64; 3] = [60, 300, 600];
+
+const UPDATE_VAR: &str = "CHIP8_UPDATE_GOLDEN";
+
+/// Key event: frame, key, pressed
+type Script = &'static [(u64, u8, bool)];
+
+/// Input of the ROMs without a script of their own: press 5 (often the
+/// start or fire key) then move around with 4, 6, 2 and 8
+const DEFAULT_SCRIPT: Script = &[
+    (90, 5, true),
+    (96, 5, false),
+    (150, 4, true),
+    (210, 4, false),
+    (220, 6, true),
+    (280, 6, false),
+    (320, 2, true),
+    (350, 2, false),
+    (380, 8, true),
+    (410, 8, false),
+    (450, 5, true),
+    (456, 5, false),
+];
+
+/// Input of the test programs, to go through their features
+fn script(name: &str) -> Script {
+    match name {
+        // Raise V3 with 2, then start the countdown with 5
+        "programs/Delay Timer Test [Matthew Mikolay, 2010].ch8" => &[
+            (30, 2, true),
+            (36, 2, false),
+            (42, 2, true),
+            (48, 2, false),
+            (54, 8, true),
+            (60, 8, false),
+            (120, 5, true),
+            (126, 5, false),
+        ],
+        // A new random number on each key press
+        "programs/Random Number Test [Matthew Mikolay, 2010].ch8" => &[
+            (100, 1, true),
+            (106, 1, false),
+            (200, 7, true),
+            (206, 7, false),
+            (320, 0xA, true),
+            (326, 0xA, false),
+        ],
+        // Each key lights its position on the keypad
+        "programs/Keypad Test [Hap, 2006].ch8" => &[
+            (50, 0, true),
+            (250, 0, false),
+            (250, 0xF, true),
+            (450, 0xF, false),
+            (450, 9, true),
+        ],
+        _ => DEFAULT_SCRIPT,
+    }
+}
+
+fn pack_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/roms/revival-pack")
+}
+
+fn golden_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/revival-pack.txt")
+}
+
+/// ROMs which cannot run: the 'hires' directory holds programs for the
+/// 64x64 VIP mode, which is not emulated (nor indexed by make-romdb.py), and
+/// the clock program calls a routine in 1802 machine code (02D8)
+const SKIPPED_DIR: &str = "hires";
+const SKIPPED_ROMS: [&str; 1] = ["programs/Clock Program [Bill Fisher, 1981].ch8"];
+
+/// Paths of the ROMs relative to the pack, sorted
+fn list_roms() -> Vec<String> {
+    let mut roms = Vec::new();
+    for dir in fs::read_dir(pack_dir()).unwrap() {
+        let dir = dir.unwrap().path();
+        if !dir.is_dir() || dir.ends_with(SKIPPED_DIR) {
+            continue;
+        }
+        for file in fs::read_dir(&dir).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "ch8") {
+                let rel = file.strip_prefix(pack_dir()).unwrap();
+                roms.push(rel.to_str().unwrap().replace('\\', "/"));
+            }
+        }
+    }
+    roms.retain(|rom| !SKIPPED_ROMS.contains(&rom.as_str()));
+    roms.sort();
+    roms
+}
+
+fn screen_hash(emu: &Chip8Emulator) -> String {
+    let screen = &emu.peripherals().screen;
+    let mut data = Vec::new();
+    data.extend_from_slice(&screen.width().to_le_bytes());
+    data.extend_from_slice(&screen.height().to_le_bytes());
+    data.extend_from_slice(screen.planes());
+    format!("{:08x}", crc32(&data))
+}
+
+/// Run a ROM, returns the `frame:hash` of each checkpoint. Once the CPU
+/// fails, the error replaces the hash.
+fn run_rom(name: &str) -> String {
+    let rom = fs::read(pack_dir().join(name)).unwrap();
+    let info = romdb::lookup(&rom).unwrap_or_else(|| panic!("{}: not in the ROM database", name));
+    let mut emu = Chip8Emulator::new(info.cpu_hz);
+    emu.apply_rom_info(info);
+    emu.load_rom(&rom);
+    emu.set_cpu_rng_seed(SEED);
+    let cpu_hz = emu.cpu_hz() as u64;
+    let to_cycles = |frame: u64| frame * cpu_hz / CHIP8_PERIPH_HZ as u64;
+
+    let mut events = script(name).iter().peekable();
+    let mut error = None;
+    let mut out = String::new();
+    for frame in CHECKPOINTS.iter() {
+        let end = to_cycles(*frame);
+        while error.is_none() && emu.cycles() < end {
+            while let Some((_, key, pressed)) = events.next_if(|ev| to_cycles(ev.0) <= emu.cycles()) {
+                let keypad = &mut emu.peripherals_mut().keypad;
+                if *pressed {
+                    keypad.key_pressed(*key);
+                } else {
+                    keypad.key_released(*key);
+                }
+            }
+            let next = events.peek().map_or(end, |ev| to_cycles(ev.0).min(end));
+            let before = emu.cycles();
+            if let Err(e) = emu.tick((next - before).max(1) as usize) {
+                error = Some(format!("{:?}@{}", e.kind, emu.cycles()));
+            } else if emu.cycles() == before {
+                // Halted
+                break;
+            }
+        }
+        let state = error.clone().unwrap_or_else(|| screen_hash(&emu));
+        let _ = write!(out, " {}:{}", frame, state);
+    }
+    out
+}
+
+fn parse_golden(text: &str) -> BTreeMap<String, String> {
+    let mut golden = BTreeMap::new();
+    for line in text.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        // The ROM names contain spaces, the hashes start at the first
+        // checkpoint
+        let start = line.find(&format!(" {}:", CHECKPOINTS[0])).expect("invalid golden line");
+        golden.insert(line[..start].trim_end().to_string(), line[start..].to_string());
+    }
+    golden
+}
+
+#[test]
+fn revival_pack() {
+    let results: Vec<(String, String)> = list_roms()
+        .into_iter()
+        .map(|name| {
+            let hashes = run_rom(&name);
+            (name, hashes)
+        })
+        .collect();
+    assert!(results.len() >= 100, "ROMs not found in {}", pack_dir().display());
+
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        let mut text = String::new();
+        text += "# Screen hashes of the revival-pack ROMs at the given frames, see tests/golden.rs\n";
+        text += &format!("# Regenerate with {}=1 cargo test -p chip8 --features romdb --test golden\n", UPDATE_VAR);
+        for (name, hashes) in results.iter() {
+            text += &format!("{}{}\n", name, hashes);
+        }
+        fs::create_dir_all(golden_path().parent().unwrap()).unwrap();
+        fs::write(golden_path(), text).unwrap();
+        return;
+    }
+
+    let text = fs::read_to_string(golden_path()).unwrap_or_default();
+    let mut golden = parse_golden(&text);
+    let mut failures = Vec::new();
+    for (name, hashes) in results.iter() {
+        match golden.remove(name) {
+            Some(expected) if expected.trim() == hashes.trim() => {}
+            Some(expected) => failures.push(format!("{}\n    expected:{}\n    actual:  {}", name, expected, hashes)),
+            None => failures.push(format!("{}: no golden hashes", name)),
+        }
+    }
+    for name in golden.keys() {
+        failures.push(format!("{}: ROM not found", name));
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} ROMs differ from the golden hashes (set {}=1 to regenerate):\n{}",
+        failures.len(),
+        results.len(),
+        UPDATE_VAR,
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,151 @@
+//
+// Decoding and encoding of the instructions
+//
+// Besides the exhaustive check of the 16-bit words, random instructions and
+// programs are generated to check that encoding then decoding gives the
+// instruction back.
+mod common;
+
+use std::collections::HashSet;
+use std::mem::discriminant;
+
+use chip8::{Insn, Pcg32};
+use common::assemble;
+
+const SEED: u64 = 0xC8_1E57;
+const ITERATIONS: usize = 100_000;
+
+/// Number of variants of `Insn`
+const VARIANTS: u32 = 50;
+
+/// Random instruction of the given variant, with random operands
+fn insn_of(variant: u32, rng: &mut Pcg32) -> Insn {
+    let bits = rng.generate();
+    let addr = (bits & 0xFFF) as u16;
+    let x = (bits & 0xF) as u8;
+    let y = ((bits >> 4) & 0xF) as u8;
+    let n = ((bits >> 8) & 0xF) as u8;
+    let byte = (bits >> 16) as u8;
+    match variant {
+        0 => Insn::Cls,
+        1 => Insn::Ret,
+        2 => Insn::Jump(addr),
+        3 => Insn::JumpV0(addr),
+        4 => Insn::Call(addr),
+        5 => Insn::SkipEqI(x, byte),
+        6 => Insn::SkipNeqI(x, byte),
+        7 => Insn::SkipEq(x, y),
+        8 => Insn::SkipNeq(x, y),
+        9 => Insn::LoadI(x, byte),
+        10 => Insn::AddI(x, byte),
+        11 => Insn::Move(x, y),
+        12 => Insn::Or(x, y),
+        13 => Insn::And(x, y),
+        14 => Insn::Xor(x, y),
+        15 => Insn::Add(x, y),
+        16 => Insn::Sub(x, y),
+        17 => Insn::Shr(x, y),
+        18 => Insn::SubN(x, y),
+        19 => Insn::Shl(x, y),
+        20 => Insn::LoadA(addr),
+        21 => Insn::AddA(x),
+        22 => Insn::RndAnd(x, byte),
+        23 => Insn::DrawSprite(x, y, n),
+        24 => Insn::SkipKeyPressed(x),
+        25 => Insn::SkipKeyNPressed(x),
+        26 => Insn::LoadTimer(x),
+        27 => Insn::WaitForKey(x),
+        28 => Insn::SetDelayTimer(x),
+        29 => Insn::SetSoundTimer(x),
+        30 => Insn::SpriteLoc(x),
+        31 => Insn::StoreBCD(x),
+        32 => Insn::StoreRegs(x),
+        33 => Insn::LoadRegs(x),
+        34 => Insn::ScrollDown(n),
+        35 => Insn::ScrollRight,
+        36 => Insn::ScrollLeft,
+        37 => Insn::Exit,
+        38 => Insn::LoRes,
+        39 => Insn::HiRes,
+        40 => Insn::BigSpriteLoc(x),
+        41 => Insn::StoreFlags(x),
+        42 => Insn::LoadFlags(x),
+        43 => Insn::ScrollUp(n),
+        44 => Insn::SaveRange(x, y),
+        45 => Insn::LoadRange(x, y),
+        46 => Insn::LoadLongA(bits as u16),
+        47 => Insn::SelectPlanes(x),
+        48 => Insn::LoadAudio,
+        49 => Insn::SetPitch(x),
+        _ => unreachable!(),
+    }
+}
+
+fn random_insn(rng: &mut Pcg32) -> Insn {
+    let variant = rng.generate() % VARIANTS;
+    insn_of(variant, rng)
+}
+
+/// Decode a program assembled by `assemble`
+fn disassemble(rom: &[u8]) -> Vec<Insn> {
+    let word = |k: usize| u16::from_be_bytes([rom[k], rom[k + 1]]);
+    let mut insns = Vec::new();
+    let mut k = 0;
+    while k < rom.len() {
+        let next = if k + 2 < rom.len() { word(k + 2) } else { 0 };
+        let insn = Insn::decode_long(word(k), next).expect("invalid instruction");
+        k += insn.size() as usize;
+        insns.push(insn);
+    }
+    insns
+}
+
+#[test]
+fn decode_encode_all_words() {
+    let mut variants = HashSet::new();
+    for word in 0..=0xFFFFu16 {
+        if let Some(insn) = Insn::decode(word) {
+            assert_eq!(insn.encode(), word, "{:?}", insn);
+            variants.insert(discriminant(&insn));
+        }
+    }
+    // All but the long load, which needs the next word
+    assert_eq!(variants.len() as u32, VARIANTS - 1);
+    assert_eq!(Insn::decode(0xF000), None);
+}
+
+#[test]
+fn encode_decode_random() {
+    let mut rng = Pcg32::default();
+    rng.reset(SEED, 1);
+    let mut variants = HashSet::new();
+    for _ in 0..ITERATIONS {
+        let insn = random_insn(&mut rng);
+        variants.insert(discriminant(&insn));
+        let next = rng.generate() as u16;
+        let decoded = match insn {
+            Insn::LoadLongA(addr) => Insn::decode_long(insn.encode(), addr),
+            _ => Insn::decode_long(insn.encode(), next),
+        };
+        assert_eq!(decoded, Some(insn.clone()), "{:04x}", insn.encode());
+
+        let mut bytes = [0u8; 4];
+        assert_eq!(insn.write_bytes(&mut bytes), insn.size() as usize);
+        assert_eq!(bytes[..2], insn.encode().to_be_bytes());
+    }
+    assert_eq!(variants.len() as u32, VARIANTS);
+}
+
+#[test]
+fn assemble_random_programs() {
+    let mut rng = Pcg32::default();
+    rng.reset(SEED, 2);
+    for _ in 0..ITERATIONS / 100 {
+        let len = 1 + rng.generate() as usize % 64;
+        let program: Vec<Insn> = (0..len).map(|_| random_insn(&mut rng)).collect();
+        let rom = assemble(&program);
+        let size: u16 = program.iter().map(Insn::size).sum();
+        assert_eq!(rom.len(), size as usize);
+        assert_eq!(disassemble(&rom), program);
+    }
+}
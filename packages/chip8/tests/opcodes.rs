@@ -0,0 +1,641 @@
+//
+// Semantics of each instruction of `Chip8Cpu::exec_insn`
+//
+// The instructions run with the default quirks of their platform, the
+// quirk presets are covered by tests/quirks.rs.
+mod common;
+
+use chip8::cpu::{CpuErrorKind, CpuStatus};
+use chip8::{Insn, Platform, Quirks};
+use common::{Machine, ROM_ADDR};
+
+/// Run `insn` with VX = x and VY = y (X = 1, Y = 2), returns VX and VF
+fn alu(insn: fn(u8, u8) -> Insn, x: u8, y: u8) -> (u8, u8) {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, x);
+    m.set_v(2, y);
+    m.set_v(0xF, 0xAA);
+    m.exec(insn(1, 2)).unwrap();
+    (m.v(1), m.v(0xF))
+}
+
+/// Same as `alu`, VF being the destination
+fn alu_vf(insn: fn(u8, u8) -> Insn, x: u8, y: u8) -> u8 {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(0xF, x);
+    m.set_v(2, y);
+    m.exec(insn(0xF, 2)).unwrap();
+    m.v(0xF)
+}
+
+#[test]
+fn cls() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.run(&[Insn::LoadA(0), Insn::DrawSprite(0, 0, 5)]).unwrap();
+    assert!(!m.lit_pixels().is_empty());
+    m.exec(Insn::Cls).unwrap();
+    assert!(m.lit_pixels().is_empty());
+}
+
+#[test]
+fn jump() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.run(&[Insn::Jump(0x346)]).unwrap();
+    assert_eq!(m.cpu.pc(), 0x346);
+}
+
+#[test]
+fn jump_v0() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(0, 0x10);
+    m.set_v(3, 0x20);
+    m.run(&[Insn::JumpV0(0x300)]).unwrap();
+    assert_eq!(m.cpu.pc(), 0x310);
+}
+
+#[test]
+fn call_ret() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::Call(0x300)]);
+    m.periph.memory[0x300..0x302].copy_from_slice(&Insn::Ret.encode().to_be_bytes());
+    m.step(1).unwrap();
+    assert_eq!(m.cpu.pc(), 0x300);
+    assert_eq!(m.cpu.sp(), 1);
+    // The return address is the next instruction
+    assert_eq!(m.cpu.stack()[0], ROM_ADDR + 2);
+    m.step(1).unwrap();
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 2);
+    assert_eq!(m.cpu.sp(), 0);
+}
+
+#[test]
+fn call_stack_overflow() {
+    let mut m = Machine::new(Platform::Chip8);
+    // Recursive call
+    m.load(&[Insn::Call(ROM_ADDR)]);
+    m.step(16).unwrap();
+    assert_eq!(m.cpu.sp(), 16);
+    assert_eq!(m.cpu.stack(), &[ROM_ADDR + 2; 16]);
+    assert_eq!(m.step(1).unwrap_err().kind, CpuErrorKind::StackOverflow);
+    assert_eq!(m.cpu.sp(), 16);
+}
+
+#[test]
+fn ret_empty_stack() {
+    let mut m = Machine::new(Platform::Chip8);
+    assert_eq!(m.run(&[Insn::Ret]).unwrap_err().kind, CpuErrorKind::PopEmptyStack);
+    assert_eq!(m.cpu.sp(), 0);
+    assert_eq!(m.cpu.pc(), ROM_ADDR);
+}
+
+#[test]
+fn skips() {
+    let cases: &[(Insn, bool)] = &[
+        (Insn::SkipEqI(1, 5), true),
+        (Insn::SkipEqI(1, 6), false),
+        (Insn::SkipNeqI(1, 6), true),
+        (Insn::SkipNeqI(1, 5), false),
+        (Insn::SkipEq(1, 2), true),
+        (Insn::SkipEq(1, 3), false),
+        (Insn::SkipNeq(1, 3), true),
+        (Insn::SkipNeq(1, 2), false),
+    ];
+    for (insn, taken) in cases {
+        let mut m = Machine::new(Platform::Chip8);
+        m.set_v(1, 5);
+        m.set_v(2, 5);
+        m.set_v(3, 6);
+        m.run(std::slice::from_ref(insn)).unwrap();
+        let expected = if *taken { ROM_ADDR + 4 } else { ROM_ADDR + 2 };
+        assert_eq!(m.cpu.pc(), expected, "{:?}", insn);
+    }
+}
+
+#[test]
+fn skip_long_insn() {
+    // On XO-CHIP, skips step over the 4 bytes of F000 NNNN
+    let mut m = Machine::new(Platform::XoChip);
+    m.load(&[Insn::SkipEqI(0, 0), Insn::LoadLongA(0x1234)]);
+    m.step(1).unwrap();
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 6);
+    assert_eq!(m.cpu.reg_i(), 0);
+}
+
+#[test]
+fn load_add_immediate() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.run(&[Insn::LoadI(3, 0xFE), Insn::AddI(3, 3), Insn::LoadI(4, 1), Insn::AddI(4, 2)]).unwrap();
+    // 7XNN wraps around without a carry
+    assert_eq!(m.v(3), 1);
+    assert_eq!(m.v(4), 3);
+    assert_eq!(m.v(0xF), 0);
+}
+
+#[test]
+fn move_and_logic() {
+    assert_eq!(alu(Insn::Move, 0x12, 0x34), (0x34, 0xAA));
+    // Without the VF reset quirk, VF is left untouched
+    assert_eq!(alu(Insn::Or, 0b1100, 0b1010), (0b1110, 0xAA));
+    assert_eq!(alu(Insn::And, 0b1100, 0b1010), (0b1000, 0xAA));
+    assert_eq!(alu(Insn::Xor, 0b1100, 0b1010), (0b0110, 0xAA));
+}
+
+#[test]
+fn add_carry() {
+    assert_eq!(alu(Insn::Add, 1, 2), (3, 0));
+    assert_eq!(alu(Insn::Add, 0xFF, 1), (0, 1));
+    assert_eq!(alu(Insn::Add, 200, 100), (44, 1));
+    assert_eq!(alu(Insn::Add, 0xFF, 0), (0xFF, 0));
+    // The flag is written after the result
+    assert_eq!(alu_vf(Insn::Add, 0xFF, 1), 1);
+    assert_eq!(alu_vf(Insn::Add, 1, 1), 0);
+}
+
+#[test]
+fn sub_borrow() {
+    // VF = 1 when there is no borrow
+    assert_eq!(alu(Insn::Sub, 5, 3), (2, 1));
+    assert_eq!(alu(Insn::Sub, 3, 5), (0xFE, 0));
+    assert_eq!(alu(Insn::Sub, 4, 4), (0, 1));
+    assert_eq!(alu(Insn::Sub, 0, 0xFF), (1, 0));
+    assert_eq!(alu_vf(Insn::Sub, 5, 3), 1);
+    assert_eq!(alu_vf(Insn::Sub, 3, 5), 0);
+}
+
+#[test]
+fn subn_borrow() {
+    assert_eq!(alu(Insn::SubN, 3, 5), (2, 1));
+    assert_eq!(alu(Insn::SubN, 5, 3), (0xFE, 0));
+    assert_eq!(alu(Insn::SubN, 4, 4), (0, 1));
+    assert_eq!(alu_vf(Insn::SubN, 3, 5), 1);
+    assert_eq!(alu_vf(Insn::SubN, 5, 3), 0);
+}
+
+#[test]
+fn shifts() {
+    // Shifted in place by default, VY is ignored
+    assert_eq!(alu(Insn::Shr, 0b1000_0011, 0xFF), (0b0100_0001, 1));
+    assert_eq!(alu(Insn::Shr, 0b1000_0010, 0xFF), (0b0100_0001, 0));
+    assert_eq!(alu(Insn::Shl, 0b1000_0001, 0xFF), (0b0000_0010, 1));
+    assert_eq!(alu(Insn::Shl, 0b0100_0001, 0xFF), (0b1000_0010, 0));
+    // The shifted out bit wins over the result
+    assert_eq!(alu_vf(Insn::Shr, 0b10, 0), 0);
+    assert_eq!(alu_vf(Insn::Shr, 0b11, 0), 1);
+    assert_eq!(alu_vf(Insn::Shl, 0x80, 0), 1);
+    assert_eq!(alu_vf(Insn::Shl, 0x01, 0), 0);
+}
+
+#[test]
+fn index_register() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(2, 0x22);
+    m.set_v(0xF, 0xAA);
+    m.run(&[Insn::LoadA(0xFF0), Insn::AddA(2)]).unwrap();
+    // I is not limited to 12 bits and VF is not a carry
+    assert_eq!(m.cpu.reg_i(), 0x1012);
+    assert_eq!(m.v(0xF), 0xAA);
+}
+
+#[test]
+fn rnd_and() {
+    let mut a = Machine::new(Platform::Chip8);
+    let mut b = Machine::new(Platform::Chip8);
+    a.periph.set_rng_seed(7);
+    b.periph.set_rng_seed(7);
+    for _ in 0..64 {
+        a.exec(Insn::RndAnd(1, 0x0F)).unwrap();
+        b.exec(Insn::RndAnd(1, 0x0F)).unwrap();
+        assert_eq!(a.v(1) & !0x0F, 0);
+        assert_eq!(a.v(1), b.v(1));
+    }
+    a.exec(Insn::RndAnd(1, 0)).unwrap();
+    assert_eq!(a.v(1), 0);
+}
+
+/// Lit pixels of the font sprite of 0 drawn at (x, y)
+fn zero_glyph(x: u32, y: u32) -> Vec<(u32, u32)> {
+    let mut lit = Vec::new();
+    for (dy, row) in [0xF0u8, 0x90, 0x90, 0x90, 0xF0].iter().enumerate() {
+        for dx in 0..8 {
+            if row & (0x80 >> dx) != 0 {
+                lit.push((x + dx, y + dy as u32));
+            }
+        }
+    }
+    lit.sort_by_key(|&(x, y)| (y, x));
+    lit
+}
+
+#[test]
+fn draw_sprite_collision() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 10);
+    m.set_v(2, 4);
+    m.set_v(0xF, 0xAA);
+    m.run(&[Insn::LoadA(0), Insn::DrawSprite(1, 2, 5)]).unwrap();
+    assert_eq!(m.lit_pixels(), zero_glyph(10, 4));
+    assert_eq!(m.v(0xF), 0);
+
+    // Drawing it again erases it
+    m.exec(Insn::DrawSprite(1, 2, 5)).unwrap();
+    assert!(m.lit_pixels().is_empty());
+    assert_eq!(m.v(0xF), 1);
+
+    // A single pixel in common is a collision
+    m.exec(Insn::DrawSprite(1, 2, 5)).unwrap();
+    m.set_v(1, 13);
+    m.exec(Insn::DrawSprite(1, 2, 1)).unwrap();
+    assert_eq!(m.v(0xF), 1);
+    m.set_v(1, 30);
+    m.exec(Insn::DrawSprite(1, 2, 5)).unwrap();
+    assert_eq!(m.v(0xF), 0);
+}
+
+#[test]
+fn draw_sprite_wrap() {
+    let mut m = Machine::new(Platform::Chip8);
+    // The initial coordinates wrap around
+    m.set_v(1, 64 + 10);
+    m.set_v(2, 32 + 4);
+    m.run(&[Insn::LoadA(0), Insn::DrawSprite(1, 2, 5)]).unwrap();
+    assert_eq!(m.lit_pixels(), zero_glyph(10, 4));
+
+    // And the sprite wraps at the edges by default
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 62);
+    m.set_v(2, 30);
+    m.run(&[Insn::LoadA(0), Insn::DrawSprite(1, 2, 5)]).unwrap();
+    let mut expected: Vec<_> = zero_glyph(62, 30).into_iter().map(|(x, y)| (x % 64, y % 32)).collect();
+    expected.sort_by_key(|&(x, y)| (y, x));
+    assert_eq!(m.lit_pixels(), expected);
+}
+
+#[test]
+fn skip_key() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 0xA);
+    m.periph.keypad.key_pressed(0xA);
+    m.run(&[Insn::SkipKeyPressed(1)]).unwrap();
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 4);
+
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 0xA);
+    m.periph.keypad.key_pressed(0xB);
+    m.run(&[Insn::SkipKeyPressed(1), Insn::SkipKeyNPressed(1)]).unwrap();
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 6);
+}
+
+#[test]
+fn wait_for_key() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::WaitForKey(3)]);
+    // Blocked on the instruction until a key is pressed
+    for _ in 0..3 {
+        m.step(1).unwrap();
+        assert_eq!(*m.cpu.status(), CpuStatus::WaitEvent);
+        assert_eq!(m.cpu.pc(), ROM_ADDR);
+    }
+    m.periph.keypad.key_pressed(0xC);
+    m.periph.keypad.key_pressed(0x7);
+    m.step(1).unwrap();
+    assert_eq!(*m.cpu.status(), CpuStatus::Running);
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 2);
+    // The lowest key wins
+    assert_eq!(m.v(3), 0x7);
+}
+
+#[test]
+fn timers() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 42);
+    m.set_v(2, 7);
+    m.run(&[Insn::SetDelayTimer(1), Insn::SetSoundTimer(2)]).unwrap();
+    assert_eq!(m.periph.delay_timer, 42);
+    assert_eq!(m.periph.sound_timer, 7);
+    m.periph.tick();
+    m.exec(Insn::LoadTimer(3)).unwrap();
+    assert_eq!(m.v(3), 41);
+}
+
+#[test]
+fn sprite_loc() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(1, 0xA);
+    m.exec(Insn::SpriteLoc(1)).unwrap();
+    assert_eq!(m.cpu.reg_i(), 50);
+    assert_eq!(m.mem(50, 5), &[0xF0, 0x90, 0xF0, 0x90, 0x90]);
+    m.set_v(1, 16);
+    assert_eq!(m.exec(Insn::SpriteLoc(1)).unwrap_err().kind, CpuErrorKind::InvalidSprite);
+}
+
+#[test]
+fn store_bcd() {
+    for (value, digits) in [(0u8, [0u8, 0, 0]), (7, [0, 0, 7]), (42, [0, 4, 2]), (123, [1, 2, 3]), (255, [2, 5, 5])] {
+        let mut m = Machine::new(Platform::Chip8);
+        m.set_v(5, value);
+        m.run(&[Insn::LoadA(0x300), Insn::StoreBCD(5)]).unwrap();
+        assert_eq!(m.mem(0x300, 3), &digits, "{}", value);
+        assert_eq!(m.cpu.reg_i(), 0x300);
+    }
+}
+
+#[test]
+fn store_load_regs() {
+    let mut m = Machine::new(Platform::Chip8);
+    for r in 0..16 {
+        m.set_v(r, 0x10 + r);
+    }
+    m.run(&[Insn::LoadA(0x300), Insn::StoreRegs(3)]).unwrap();
+    assert_eq!(m.mem(0x300, 5), &[0x10, 0x11, 0x12, 0x13, 0]);
+
+    m.periph.memory[0x400..0x410].copy_from_slice(&[0xA0; 16]);
+    m.exec(Insn::LoadA(0x400)).unwrap();
+    m.exec(Insn::LoadRegs(0xF)).unwrap();
+    assert_eq!(m.regs(), [0xA0; 16]);
+}
+
+#[test]
+fn platform_checks() {
+    // Extended instructions are invalid on the lower platforms
+    let cases: &[(Insn, Platform)] = &[
+        (Insn::HiRes, Platform::SuperChip),
+        (Insn::Exit, Platform::SuperChip),
+        (Insn::StoreFlags(0), Platform::SuperChip),
+        (Insn::ScrollUp(1), Platform::XoChip),
+        (Insn::SaveRange(0, 1), Platform::XoChip),
+        (Insn::LoadLongA(0x1000), Platform::XoChip),
+        (Insn::SelectPlanes(3), Platform::XoChip),
+        (Insn::SetPitch(0), Platform::XoChip),
+    ];
+    for (insn, platform) in cases {
+        let mut m = Machine::new(Platform::Chip8);
+        assert_eq!(m.exec(insn.clone()).unwrap_err().kind, CpuErrorKind::InvalidInstruction, "{:?}", insn);
+        let mut m = Machine::new(*platform);
+        assert!(m.exec(insn.clone()).is_ok(), "{:?}", insn);
+    }
+    let mut m = Machine::new(Platform::SuperChip);
+    assert_eq!(m.exec(Insn::LoadAudio).unwrap_err().kind, CpuErrorKind::InvalidInstruction);
+}
+
+#[test]
+fn schip_exit() {
+    let mut m = Machine::new(Platform::SuperChip);
+    m.run(&[Insn::Exit]).unwrap();
+    assert_eq!(*m.cpu.status(), CpuStatus::Halted);
+    // Nothing runs anymore
+    m.step(10).unwrap();
+    assert_eq!(m.cpu.pc(), ROM_ADDR);
+    assert_eq!(m.cpu.cycles(), 1);
+}
+
+#[test]
+fn schip_resolution() {
+    let mut m = Machine::new(Platform::SuperChip);
+    m.exec(Insn::HiRes).unwrap();
+    assert_eq!(m.periph.screen.dims(), (128, 64));
+    m.exec(Insn::LoRes).unwrap();
+    assert_eq!(m.periph.screen.dims(), (64, 32));
+}
+
+#[test]
+fn schip_big_sprite() {
+    let mut m = Machine::new(Platform::SuperChip);
+    m.set_v(1, 3);
+    m.exec(Insn::BigSpriteLoc(1)).unwrap();
+    assert_eq!(m.cpu.reg_i(), 0x50 + 30);
+    m.set_v(1, 16);
+    assert_eq!(m.exec(Insn::BigSpriteLoc(1)).unwrap_err().kind, CpuErrorKind::InvalidSprite);
+
+    // DXY0 draws 16x16 sprites, two bytes per row
+    let mut m = Machine::new(Platform::SuperChip);
+    m.periph.memory[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+    m.run(&[Insn::HiRes, Insn::LoadA(0x300), Insn::DrawSprite(0, 0, 0)]).unwrap();
+    let lit = m.lit_pixels();
+    assert_eq!(lit.len(), 256);
+    assert!(lit.iter().all(|&(x, y)| x < 16 && y < 16));
+    assert_eq!(m.v(0xF), 0);
+    m.exec(Insn::DrawSprite(0, 0, 0)).unwrap();
+    assert_eq!(m.v(0xF), 1);
+}
+
+#[test]
+fn schip_flags() {
+    let mut m = Machine::new(Platform::SuperChip);
+    for r in 0..8 {
+        m.set_v(r, r + 1);
+    }
+    m.exec(Insn::StoreFlags(3)).unwrap();
+    assert_eq!(m.periph.rpl_flags[..5], [1, 2, 3, 4, 0]);
+    for r in 0..8 {
+        m.set_v(r, 0);
+    }
+    m.exec(Insn::LoadFlags(7)).unwrap();
+    assert_eq!(m.regs()[..8], [1, 2, 3, 4, 0, 0, 0, 0]);
+}
+
+#[test]
+fn scrolls() {
+    let single = |insn: Insn, platform: Platform| {
+        let mut m = Machine::new(platform);
+        m.periph.screen.set_pixel((8, 8), 1);
+        m.exec(insn).unwrap();
+        m.lit_pixels()
+    };
+    assert_eq!(single(Insn::ScrollDown(3), Platform::SuperChip), [(8, 11)]);
+    assert_eq!(single(Insn::ScrollRight, Platform::SuperChip), [(12, 8)]);
+    assert_eq!(single(Insn::ScrollLeft, Platform::SuperChip), [(4, 8)]);
+    assert_eq!(single(Insn::ScrollUp(3), Platform::XoChip), [(8, 5)]);
+}
+
+#[test]
+fn xochip_ranges() {
+    let mut m = Machine::new(Platform::XoChip);
+    for r in 0..16 {
+        m.set_v(r, 0x10 + r);
+    }
+    m.run(&[Insn::LoadA(0x300), Insn::SaveRange(2, 4), Insn::LoadA(0x310), Insn::SaveRange(4, 2)]).unwrap();
+    assert_eq!(m.mem(0x300, 4), &[0x12, 0x13, 0x14, 0]);
+    // Reversed ranges are stored in the given order
+    assert_eq!(m.mem(0x310, 4), &[0x14, 0x13, 0x12, 0]);
+    // I is never changed
+    assert_eq!(m.cpu.reg_i(), 0x310);
+
+    m.exec(Insn::LoadRange(7, 9)).unwrap();
+    assert_eq!(m.regs()[7..10], [0x14, 0x13, 0x12]);
+    m.exec(Insn::LoadRange(0xF, 0xD)).unwrap();
+    assert_eq!(m.regs()[0xD..], [0x12, 0x13, 0x14]);
+}
+
+#[test]
+fn xochip_long_load() {
+    let mut m = Machine::new(Platform::XoChip);
+    m.run(&[Insn::LoadLongA(0xBEEF), Insn::AddA(0)]).unwrap();
+    assert_eq!(m.cpu.reg_i(), 0xBEEF);
+    assert_eq!(m.cpu.pc(), ROM_ADDR + 6);
+}
+
+#[test]
+fn xochip_planes() {
+    let mut m = Machine::new(Platform::XoChip);
+    m.periph.memory[0x300..0x302].copy_from_slice(&[0x80, 0x40]);
+    // Both planes: one byte each
+    m.run(&[Insn::SelectPlanes(3), Insn::LoadA(0x300), Insn::DrawSprite(0, 0, 1)]).unwrap();
+    assert_eq!(m.periph.screen.pixel(0, 0), 1);
+    assert_eq!(m.periph.screen.pixel(1, 0), 2);
+
+    // Only the selected planes are cleared
+    m.exec(Insn::SelectPlanes(2)).unwrap();
+    m.exec(Insn::Cls).unwrap();
+    assert_eq!(m.lit_pixels(), [(0, 0)]);
+}
+
+#[test]
+fn xochip_audio() {
+    let mut m = Machine::new(Platform::XoChip);
+    let pattern: Vec<u8> = (0..16).collect();
+    m.periph.memory[0x300..0x310].copy_from_slice(&pattern);
+    m.set_v(4, 200);
+    m.run(&[Insn::LoadA(0x300), Insn::LoadAudio, Insn::SetPitch(4)]).unwrap();
+    assert_eq!(m.periph.audio_pattern[..], pattern[..]);
+    assert_eq!(m.periph.pitch, 200);
+}
+
+#[test]
+fn logic_resets_vf() {
+    let quirks = Quirks { logic_resets_vf: true, ..Quirks::default() };
+    for insn in [Insn::Or(1, 2), Insn::And(1, 2), Insn::Xor(1, 2)] {
+        let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+        m.set_v(0xF, 0xAA);
+        m.exec(insn.clone()).unwrap();
+        assert_eq!(m.v(0xF), 0, "{:?}", insn);
+    }
+}
+
+#[test]
+fn memory_bounds() {
+    // I-relative accesses crossing the 4 KiB limit fail as a whole
+    let cases: &[(Insn, u16, u32)] = &[
+        (Insn::StoreBCD(0), 0xFFE, 0x1000),
+        (Insn::StoreRegs(3), 0xFFD, 0x1000),
+        (Insn::LoadRegs(3), 0xFFD, 0x1000),
+        (Insn::DrawSprite(0, 0, 5), 0xFFC, 0x1000),
+        (Insn::StoreRegs(0), 0x1234, 0x1234),
+    ];
+    for (insn, i, addr) in cases {
+        let mut m = Machine::new(Platform::Chip8);
+        m.set_v(0, 0xAB);
+        m.cpu.set_reg_i(*i);
+        m.load(std::slice::from_ref(insn));
+        let before = m.periph.memory;
+        let e = m.step(1).unwrap_err();
+        assert_eq!((e.kind, e.pc), (CpuErrorKind::MemoryError { addr: *addr }, ROM_ADDR), "{:?}", insn);
+        assert!(m.periph.memory[..] == before[..], "{:?}", insn);
+        assert!(m.lit_pixels().is_empty(), "{:?}", insn);
+    }
+
+    // The last bytes are still accessible
+    let mut m = Machine::new(Platform::Chip8);
+    m.set_v(0, 123);
+    m.cpu.set_reg_i(0xFFD);
+    m.exec(Insn::StoreBCD(0)).unwrap();
+    assert_eq!(m.mem(0xFFD, 3), &[1, 2, 3]);
+}
+
+#[test]
+fn memory_bounds_fetch() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::Jump(0xFFF)]);
+    m.step(1).unwrap();
+    let e = m.step(1).unwrap_err();
+    assert_eq!((e.kind, e.pc, e.opcode), (CpuErrorKind::MemoryError { addr: 0x1000 }, 0xFFF, None));
+
+    // XO-CHIP has 64 KiB, the PC wraps around at its end
+    let mut m = Machine::new(Platform::XoChip);
+    m.periph.memory[0xFFFE..].copy_from_slice(&Insn::AddI(1, 1).encode().to_be_bytes());
+    m.cpu.set_pc(0xFFFE);
+    m.step(1).unwrap();
+    assert_eq!(m.cpu.pc(), 0);
+    assert_eq!(m.v(1), 1);
+}
+
+#[test]
+fn memory_bounds_platform() {
+    // 4 KiB on CHIP-8 and SUPER-CHIP, 64 KiB on XO-CHIP
+    for (platform, size) in [(Platform::Chip8, 0x1000), (Platform::SuperChip, 0x1000), (Platform::XoChip, 0x10000)] {
+        assert_eq!(platform.memory_size(), size);
+        let mut m = Machine::new(platform);
+        m.set_v(0, 123);
+        m.cpu.set_reg_i(0x1000);
+        m.load(&[Insn::StoreBCD(0)]);
+        let result = m.step(1);
+        if size > 0x1000 {
+            assert!(result.is_ok(), "{:?}", platform);
+            assert_eq!(m.mem(0x1000, 3), &[1, 2, 3]);
+        } else {
+            assert_eq!(result.unwrap_err().kind, CpuErrorKind::MemoryError { addr: 0x1000 }, "{:?}", platform);
+            assert_eq!(m.mem(0x1000, 3), &[0, 0, 0], "{:?}", platform);
+        }
+    }
+
+    // Wrapping happens at the platform limit
+    let quirks = Quirks { wrap_memory: true, ..Quirks::default() };
+    let mut m = Machine::with_quirks(Platform::SuperChip, quirks);
+    m.set_v(0, 123);
+    m.run(&[Insn::LoadA(0xFFF), Insn::StoreBCD(0)]).unwrap();
+    assert_eq!((m.mem(0xFFF, 1), m.mem(0, 2), m.mem(0x1000, 2)), (&[1][..], &[2, 3][..], &[0, 0][..]));
+}
+
+#[test]
+fn memory_wrap() {
+    let quirks = Quirks { wrap_memory: true, ..Quirks::default() };
+    let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+    m.set_v(0, 123);
+    m.run(&[Insn::LoadA(0xFFE), Insn::StoreBCD(0)]).unwrap();
+    assert_eq!(m.mem(0xFFE, 2), &[1, 2]);
+    assert_eq!(m.mem(0, 1), &[3]);
+
+    // Sprites too, here two rows followed by the top of the 0 glyph
+    let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+    m.periph.memory[0xFFE..0x1000].copy_from_slice(&[0x80, 0x80]);
+    m.run(&[Insn::LoadA(0xFFE), Insn::DrawSprite(0, 0, 3)]).unwrap();
+    assert_eq!(m.lit_pixels(), [(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (3, 2)]);
+
+    // And the PC
+    let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+    m.periph.memory[0xFFF] = 0x60;
+    m.periph.memory[0] = 0x42;
+    m.cpu.set_pc(0xFFF);
+    m.step(1).unwrap();
+    assert_eq!(m.v(0), 0x42);
+}
+
+#[test]
+fn error_context() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::Call(0x300)]);
+    m.periph.memory[0x300..0x302].copy_from_slice(&Insn::Call(0x400).encode().to_be_bytes());
+    // XO-CHIP instruction
+    m.periph.memory[0x400..0x402].copy_from_slice(&[0x5A, 0xB3]);
+    m.step(2).unwrap();
+    let e = m.step(1).unwrap_err();
+    assert_eq!(e.kind, CpuErrorKind::InvalidInstruction);
+    assert_eq!((e.pc, e.opcode, e.cycle), (0x400, Some(0x5AB3), 3));
+    assert_eq!(e.backtrace(), &[0x302, 0x202]);
+    assert_eq!(e.to_string(), "invalid opcode 0x5AB3 at 0x400 (cycle 3), called from 0x300 < 0x200");
+
+    // Undecodable opcode
+    let mut m = Machine::new(Platform::Chip8);
+    m.periph.memory[0x200..0x202].copy_from_slice(&[0x01, 0x23]);
+    let e = m.step(1).unwrap_err();
+    assert_eq!(e.to_string(), "invalid opcode 0x0123 at 0x200 (cycle 1)");
+
+    // Only the innermost calls are kept
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::Call(ROM_ADDR)]);
+    let e = m.step(17).unwrap_err();
+    assert_eq!(e.backtrace(), &[ROM_ADDR + 2; 4]);
+    assert_eq!(
+        e.to_string(),
+        "stack overflow by opcode 0x2200 at 0x200 (cycle 17), called from 0x200 < 0x200 < 0x200 < 0x200"
+    );
+}
@@ -0,0 +1,140 @@
+//
+// Quirk presets, checked against the register and memory states left by a
+// program going through every quirk
+mod common;
+
+use chip8::{Insn, Platform, Quirks};
+use common::{Machine, ROM_ADDR};
+
+const STORE_ADDR: u16 = 0x300;
+
+/// Address of the n-th instruction of `program`
+const fn at(n: u16) -> u16 {
+    ROM_ADDR + 2 * n
+}
+
+fn program() -> Vec<Insn> {
+    vec![
+        Insn::LoadI(0, 2),
+        Insn::LoadI(1, 0x81),
+        Insn::LoadI(2, 0x03),
+        // Shift: V2 = V1 << 1 (VF = 1) or V2 << 1 (VF = 0)
+        Insn::Shl(2, 1),
+        Insn::Move(3, 0xF),
+        // Logic: VF reset or left to 0x55
+        Insn::LoadI(0xF, 0x55),
+        Insn::Or(4, 1),
+        Insn::Move(5, 0xF),
+        // Clipping: the 0 glyph drawn across the right edge
+        Insn::LoadI(7, 62),
+        Insn::LoadA(0),
+        Insn::DrawSprite(7, 8, 5),
+        // Index: I after storing V0 to V5
+        Insn::LoadA(STORE_ADDR),
+        Insn::StoreRegs(5),
+        // Jump: to at(16) + V0 (V6 = 3) or at(16) + V2 (V6 = 2)
+        Insn::LoadI(2, 4),
+        Insn::JumpV0(at(16)),
+        Insn::LoadI(6, 0xEE),
+        Insn::LoadI(6, 0xEE),
+        Insn::AddI(6, 1),
+        Insn::AddI(6, 2),
+    ]
+}
+
+/// Expected state after running `program`
+struct Expected {
+    regs: [u8; 16],
+    reg_i: u16,
+    stored: [u8; 6],
+    /// Lit pixels, 14 if the sprite wraps and 7 if it is clipped
+    lit: usize,
+}
+
+fn expected(shift_flag: u8, shifted: u8, logic_vf: u8, index_inc: u16, jump_marker: u8, lit: usize) -> Expected {
+    Expected {
+        regs: [2, 0x81, 4, shift_flag, 0x81, logic_vf, jump_marker, 62, 0, 0, 0, 0, 0, 0, 0, 0],
+        reg_i: STORE_ADDR + index_inc,
+        stored: [2, 0x81, shifted, shift_flag, 0x81, logic_vf],
+        lit,
+    }
+}
+
+fn check(name: &str, quirks: Quirks, expected: Expected) {
+    let program = program();
+    let end = at(program.len() as u16);
+    let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+    m.load(&program);
+    while m.cpu.pc() != end {
+        assert!(m.cpu.cycles() < 100, "{}: runaway program", name);
+        m.step(1).unwrap();
+    }
+    assert_eq!(m.regs(), expected.regs, "{}: registers", name);
+    assert_eq!(m.cpu.reg_i(), expected.reg_i, "{}: I", name);
+    assert_eq!(m.mem(STORE_ADDR, 6), &expected.stored, "{}: stored registers", name);
+    assert_eq!(m.lit_pixels().len(), expected.lit, "{}: lit pixels", name);
+    // The left columns of the glyph are visible in all cases
+    assert_ne!(m.periph.screen.pixel(62, 0), 0, "{}", name);
+    assert_eq!(m.periph.screen.pixel(0, 0) != 0, expected.lit == 14, "{}: wrapped pixel", name);
+}
+
+#[test]
+fn legacy() {
+    check("legacy", Quirks::legacy(), expected(0, 0x06, 0x55, 0, 3, 14));
+}
+
+#[test]
+fn cosmac_vip() {
+    check("cosmac_vip", Quirks::cosmac_vip(), expected(1, 0x02, 0, 6, 3, 7));
+}
+
+#[test]
+fn chip48() {
+    check("chip48", Quirks::chip48(), expected(0, 0x06, 0x55, 5, 2, 7));
+}
+
+#[test]
+fn super_chip() {
+    check("super_chip", Quirks::super_chip(), expected(0, 0x06, 0x55, 0, 2, 7));
+}
+
+#[test]
+fn xo_chip() {
+    check("xo_chip", Quirks::xo_chip(), expected(1, 0x02, 0x55, 6, 3, 14));
+}
+
+#[test]
+fn wrap_memory() {
+    for (name, quirks, wraps) in [
+        ("legacy", Quirks::legacy(), false),
+        ("cosmac_vip", Quirks::cosmac_vip(), true),
+        ("chip48", Quirks::chip48(), false),
+        ("super_chip", Quirks::super_chip(), false),
+        ("xo_chip", Quirks::xo_chip(), true),
+    ] {
+        let mut m = Machine::with_quirks(Platform::Chip8, quirks);
+        m.set_v(0, 255);
+        m.load(&[Insn::LoadA(0xFFF), Insn::StoreBCD(0)]);
+        let result = m.step(2);
+        assert_eq!(result.is_ok(), wraps, "{}: {:?}", name, result);
+        let expected: &[u8] = if wraps { &[5, 5, 2] } else { &[0xF0, 0x90, 0] };
+        assert_eq!(m.mem(0, 2), &expected[..2], "{}: wrapped bytes", name);
+        assert_eq!(m.mem(0xFFF, 1), &expected[2..], "{}: last byte", name);
+    }
+}
+
+#[test]
+fn platform_defaults() {
+    assert_eq!(Platform::Chip8.default_quirks(), Quirks::legacy());
+    assert_eq!(Platform::SuperChip.default_quirks(), Quirks::super_chip());
+    assert_eq!(Platform::XoChip.default_quirks(), Quirks::xo_chip());
+    for (name, quirks) in [
+        ("default", Quirks::legacy()),
+        ("vip", Quirks::cosmac_vip()),
+        ("chip48", Quirks::chip48()),
+        ("schip", Quirks::super_chip()),
+        ("xochip", Quirks::xo_chip()),
+    ] {
+        assert_eq!(name.parse::<Quirks>(), Ok(quirks));
+    }
+}
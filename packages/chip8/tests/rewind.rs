@@ -0,0 +1,84 @@
+//
+// Rewind: going back restores the exact earlier states
+#![cfg(feature = "std")]
+mod common;
+
+use chip8::rewind::RewindConfig;
+use chip8::{Chip8Emulator, Insn, STATE_SIZE};
+use common::{assemble, save_state_vec, ROM_ADDR};
+
+/// Scribble random sprites, one per frame
+fn emulator(config: RewindConfig) -> Chip8Emulator {
+    let mut emu = Chip8Emulator::new(600);
+    emu.set_cpu_rng_seed(7);
+    emu.load_rom(&assemble(&[
+        Insn::RndAnd(0, 0x3F),
+        Insn::RndAnd(1, 0x1F),
+        Insn::RndAnd(2, 0x0F),
+        Insn::SpriteLoc(2),
+        Insn::DrawSprite(0, 1, 5),
+        Insn::SetDelayTimer(0),
+        Insn::Jump(ROM_ADDR),
+    ]));
+    emu.enable_rewind(config);
+    emu
+}
+
+/// Run `n` frames, returns the state after each of them, that of frame 0
+/// first
+fn run(emu: &mut Chip8Emulator, n: usize) -> Vec<Vec<u8>> {
+    let mut states = vec![save_state_vec(emu)];
+    for _ in 0..n {
+        emu.run_frame().unwrap();
+        states.push(save_state_vec(emu));
+    }
+    states
+}
+
+#[test]
+fn rewind_frames() {
+    let config = RewindConfig { interval: 1, capacity: 100, keyframe_interval: 8 };
+    let mut emu = emulator(config);
+    let states = run(&mut emu, 60);
+    let mut frame = 60;
+    for n in [1, 5, 8, 13, 30] {
+        assert_eq!(emu.rewind(n), n);
+        frame -= n as usize;
+        assert!(save_state_vec(&emu) == states[frame], "frame {}", frame);
+    }
+    // Back to the start, no further
+    assert_eq!(emu.rewind(100), 3);
+    assert!(save_state_vec(&emu) == states[0]);
+    assert_eq!(emu.rewind(1), 0);
+
+    // The run goes on as the first time
+    let replay = run(&mut emu, 10);
+    assert!(replay == states[..11]);
+}
+
+#[test]
+fn rewind_interval() {
+    let config = RewindConfig { interval: 4, capacity: 100, keyframe_interval: 3 };
+    let mut emu = emulator(config);
+    let states = run(&mut emu, 30);
+    // Snapshots every 4 frames: back to 24, at least 5 frames ago
+    assert_eq!(emu.rewind(5), 6);
+    assert!(save_state_vec(&emu) == states[24]);
+    assert_eq!(emu.rewind(4), 4);
+    assert!(save_state_vec(&emu) == states[20]);
+}
+
+#[test]
+fn rewind_capacity() {
+    let config = RewindConfig { interval: 1, capacity: 20, keyframe_interval: 5 };
+    let mut emu = emulator(config);
+    let states = run(&mut emu, 100);
+    let history = emu.rewind_history().unwrap();
+    assert!(history.len() <= 20 && history.len() > 15, "{} snapshots", history.len());
+    assert!(history.memory_usage() < 5 * STATE_SIZE, "{} bytes", history.memory_usage());
+
+    // The oldest snapshot left
+    let oldest = 100 - (history.len() - 1);
+    assert_eq!(emu.rewind(1000) as usize, 100 - oldest);
+    assert!(save_state_vec(&emu) == states[oldest]);
+}
@@ -0,0 +1,49 @@
+//
+// Built-in ROM database: lookups and the settings applied to the emulator
+#![cfg(feature = "romdb")]
+
+use std::fs;
+use std::path::Path;
+
+use chip8::romdb::{self, entries};
+use chip8::{crc32, Chip8Emulator, Platform, Quirks};
+
+fn read_rom(name: &str) -> Vec<u8> {
+    let pack = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/roms/revival-pack");
+    fs::read(pack.join(name)).unwrap()
+}
+
+#[test]
+fn table_sorted() {
+    // `lookup_crc32` binary-searches the table
+    assert!(entries().windows(2).all(|w| w[0].crc32 < w[1].crc32));
+    for info in entries() {
+        assert_eq!(romdb::lookup_crc32(info.crc32), Some(info), "{}", info.title);
+    }
+}
+
+#[test]
+fn crc32_check_value() {
+    // CRC-32/IEEE, the checksum of the ROM files
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn load_rom_auto() {
+    let mut emu = Chip8Emulator::new(1000);
+    let info = emu.load_rom_auto(&read_rom("games/Blitz [David Winter].ch8")).unwrap();
+    assert_eq!(info.title, "Blitz [David Winter]");
+    assert_eq!((emu.cpu().platform(), emu.cpu_hz()), (Platform::Chip8, 700));
+    assert_eq!(*emu.cpu().quirks(), Quirks { clip_sprites: true, ..Quirks::legacy() });
+
+    let info = emu.load_rom_auto(&read_rom("games/Space Invaders [David Winter].ch8")).unwrap();
+    assert_eq!(info.title, "Space Invaders [David Winter]");
+    assert_eq!(emu.cpu_hz(), 700);
+    assert!(!emu.cpu().quirks().clip_sprites);
+
+    // Unknown ROMs keep the settings
+    emu.set_cpu_hz(600);
+    assert_eq!(emu.load_rom_auto(&[0x12, 0x00]), None);
+    assert_eq!((emu.cpu_hz(), *emu.cpu().quirks()), (600, Quirks::legacy()));
+}
@@ -0,0 +1,6 @@
+; Draw the number of the failed check (in VE), then stop
+fail:   LD V0, X
+        LD V1, Y
+        LD F, VE
+        DRW V0, V1, 5
+halt:   JP halt
@@ -0,0 +1,97 @@
+; Self-checking program for the emulator tests (tests/selftest.rs). The
+; assembler tests check that it still assembles to selftest.ch8:
+;
+;     chip8-asm selftest.asm
+;
+; Each check sets VE to its number, and jumps to `fail` when it does not
+; pass. Once all the checks pass, VE is PASSED and "OK" is drawn, otherwise
+; the number of the failed check is drawn.
+
+PASSED  equ 0xFF
+SCRATCH equ 0x380
+X       equ 28
+Y       equ 13
+
+start:  LD VE, 1                ; 8XY4 sets the carry
+        LD V0, 200
+        LD V1, 100
+        ADD V0, V1
+        SE V0, 44
+        JP fail
+        SE VF, 1
+        JP fail
+
+        LD VE, 2                ; 8XY5 sets VF without borrow, also if equal
+        LD V0, 7
+        LD V1, 7
+        SUB V0, V1
+        SE V0, 0
+        JP fail
+        SE VF, 1
+        JP fail
+
+        LD VE, 3                ; 8XY7 clears VF on borrow
+        LD V0, 9
+        LD V1, 4
+        SUBN V0, V1
+        SE V0, -5
+        JP fail
+        SE VF, 0
+        JP fail
+
+        LD VE, 4                ; The flag wins when VF is the destination
+        LD VF, 0xFF
+        LD V1, 1
+        ADD VF, V1
+        SE VF, 1
+        JP fail
+
+        LD VE, 5                ; BCD, compared with a table
+        LD V0, 137
+        LD I, SCRATCH
+        LD B, V0
+        LD V2, [I]
+        LD V3, V0
+        LD V4, V1
+        LD V5, V2
+        LD I, digits
+        LD V2, [I]
+        CALL compare
+
+        LD VE, 6                ; Big-endian words
+        LD I, words + 2
+        LD V1, [I]
+        SE V0, 0x03
+        JP fail
+        SNE V1, 0x80
+        JP pass
+        JP fail
+
+pass:   LD VE, PASSED
+        LD V0, X
+        LD V1, Y
+        LD V2, 0                ; The 0 glyph is an O
+        LD F, V2
+        DRW V0, V1, 5
+        ADD V0, 5
+        LD I, letter_k
+        DRW V0, V1, 5
+done:   JP done
+
+; V0-V2 must equal V3-V5
+compare:
+        SE V0, V3
+        JP fail
+        SE V1, V4
+        JP fail
+        SE V2, V5
+        JP fail
+        RET
+
+        include "selftest-fail.asm"
+
+        org 0x300
+digits: db 1, 3, 7
+words:  dw 0x1234, SCRATCH
+letter_k:
+        sprite "#..#....", "#.#.....", "##......", "#.#.....", "#..#...."
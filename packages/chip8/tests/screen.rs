@@ -0,0 +1,115 @@
+//
+// Damage tracking of the screen, for displays updating only what changed
+mod common;
+
+use chip8::{Damage, Insn, Platform, Screen, CHIP8_FB_H, CHIP8_FB_W, SCHIP_FB_H, SCHIP_FB_W};
+use common::Machine;
+
+const fn rect(x: u32, y: u32, width: u32, height: u32) -> Damage {
+    Damage { x, y, width, height }
+}
+
+/// Screen with its Y axis stored top-down, the initial damage taken
+fn screen() -> Screen {
+    let mut screen = Screen::new();
+    screen.set_inverted_y(false);
+    screen.take_damage();
+    screen
+}
+
+#[test]
+fn initial_damage() {
+    let mut screen = Screen::new();
+    assert!(screen.is_dirty());
+    assert_eq!(screen.take_damage(), Some(rect(0, 0, CHIP8_FB_W as u32, CHIP8_FB_H as u32)));
+    assert!(!screen.is_dirty());
+    assert_eq!(screen.take_damage(), None);
+}
+
+#[test]
+fn pixels() {
+    let mut screen = screen();
+    let changes = screen.change_count();
+    screen.xor_pixel((3, 4), 1);
+    screen.xor_pixel((10, 6), 1);
+    assert_ne!(screen.change_count(), changes);
+    let damage = screen.take_damage().unwrap();
+    assert_eq!(damage, rect(3, 4, 8, 3));
+    assert_eq!(damage.rows(), 4..7);
+    assert_eq!(damage.columns(), 3..11);
+
+    // Writing the same value is not a change
+    let changes = screen.change_count();
+    screen.set_pixel((10, 6), 1);
+    assert_eq!(screen.change_count(), changes);
+    assert_eq!(screen.take_damage(), None);
+}
+
+#[test]
+fn inverted_y() {
+    let mut screen = Screen::new();
+    screen.take_damage();
+    screen.xor_pixel((3, 4), 1);
+    // In the layout of `data`, bottom-up
+    assert_eq!(screen.take_damage(), Some(rect(3, CHIP8_FB_H as u32 - 5, 1, 1)));
+}
+
+#[test]
+fn clear() {
+    let mut screen = screen();
+    screen.clear();
+    assert_eq!(screen.take_damage(), None);
+    screen.xor_pixel((5, 1), 1);
+    screen.xor_pixel((2, 9), 1);
+    screen.take_damage();
+    screen.clear();
+    assert_eq!(screen.take_damage(), Some(rect(2, 1, 4, 9)));
+}
+
+#[test]
+fn scroll() {
+    let mut screen = screen();
+    screen.set_hires(true);
+    assert_eq!(screen.take_damage(), Some(rect(0, 0, SCHIP_FB_W as u32, SCHIP_FB_H as u32)));
+    screen.xor_pixel((20, 30), 1);
+    screen.take_damage();
+    screen.scroll_down(4);
+    assert_eq!(screen.take_damage(), Some(rect(20, 30, 1, 5)));
+    screen.scroll_left(4);
+    assert_eq!(screen.take_damage(), Some(rect(16, 34, 5, 1)));
+}
+
+#[test]
+fn palette() {
+    let mut screen = screen();
+    let mut palette = *screen.palette();
+    palette[1] = 0xFF00_FF00;
+    screen.set_palette(palette);
+    assert_eq!(screen.take_damage(), Some(rect(0, 0, CHIP8_FB_W as u32, CHIP8_FB_H as u32)));
+}
+
+#[test]
+fn sprite() {
+    let mut m = Machine::new(Platform::Chip8);
+    m.periph.screen.set_inverted_y(false);
+    m.periph.screen.take_damage();
+    m.set_v(0, 10);
+    m.set_v(1, 3);
+    // The 1 glyph: 0x20, 0x60, 0x20, 0x20, 0x70
+    m.exec(Insn::LoadA(5)).unwrap();
+    m.exec(Insn::DrawSprite(0, 1, 5)).unwrap();
+    assert_eq!(m.periph.screen.take_damage(), Some(rect(11, 3, 3, 5)));
+    m.exec(Insn::DrawSprite(0, 1, 5)).unwrap();
+    assert_eq!(m.periph.screen.take_damage(), Some(rect(11, 3, 3, 5)));
+    assert!(m.lit_pixels().is_empty());
+}
+
+#[test]
+fn load_state() {
+    let mut emu = chip8::Chip8Emulator::new(500);
+    let mut state = vec![0u8; chip8::STATE_SIZE];
+    emu.save_state(&mut state).unwrap();
+    emu.peripherals_mut().screen.take_damage();
+    emu.load_state(&state).unwrap();
+    assert_eq!(emu.peripherals_mut().screen.take_damage(), Some(rect(0, 0, CHIP8_FB_W as u32, CHIP8_FB_H as u32)));
+}
@@ -0,0 +1,62 @@
+//
+// Self-checking program assembled from tests/roms/selftest.asm
+mod common;
+
+use chip8::{Platform, Quirks};
+use common::{Machine, ROM_ADDR};
+
+const SELFTEST: &[u8] = include_bytes!("roms/selftest.ch8");
+
+/// VE once all the checks passed
+const PASSED: u8 = 0xFF;
+
+fn run(platform: Platform, quirks: Quirks, rom: &[u8]) -> Machine {
+    let mut m = Machine::with_quirks(platform, quirks);
+    let start = ROM_ADDR as usize;
+    m.periph.memory[start..start + rom.len()].copy_from_slice(rom);
+    m.step(200).unwrap();
+    m
+}
+
+/// Pixels of the glyphs drawn at (28, 13)
+fn glyph_pixels(rows: &[u8]) -> Vec<(u32, u32)> {
+    let mut lit = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..8 {
+            if row & (0x80 >> x) != 0 {
+                lit.push((28 + x, 13 + y as u32));
+            }
+        }
+    }
+    lit
+}
+
+#[test]
+fn selftest_passes() {
+    for (name, platform, quirks) in [
+        ("legacy", Platform::Chip8, Quirks::legacy()),
+        ("cosmac_vip", Platform::Chip8, Quirks::cosmac_vip()),
+        ("chip48", Platform::Chip8, Quirks::chip48()),
+        ("super_chip", Platform::SuperChip, Quirks::super_chip()),
+        ("xo_chip", Platform::XoChip, Quirks::xo_chip()),
+    ] {
+        let m = run(platform, quirks, SELFTEST);
+        assert_eq!(m.v(0xE), PASSED, "{}: failed check", name);
+        // "O" then "K", 5 pixels apart
+        let mut expected = glyph_pixels(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        expected.extend(glyph_pixels(&[0x90, 0xA0, 0xC0, 0xA0, 0x90]).iter().map(|(x, y)| (x + 5, *y)));
+        expected.sort_by_key(|(x, y)| (*y, *x));
+        assert_eq!(m.lit_pixels(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn selftest_reports_failures() {
+    // Expect 1 instead of 0 in the `SE V0, 0` of check 2
+    let mut rom = SELFTEST.to_vec();
+    assert_eq!(rom[0x18..0x1A], [0x30, 0x00]);
+    rom[0x19] = 1;
+    let m = run(Platform::Chip8, Quirks::legacy(), &rom);
+    assert_eq!(m.v(0xE), 2);
+    assert_eq!(m.lit_pixels(), glyph_pixels(&[0xF0, 0x10, 0xF0, 0x80, 0xF0]));
+}
@@ -0,0 +1,120 @@
+//
+// Save states: round trip and validation of the snapshots
+mod common;
+
+use chip8::state::STATE_VERSION;
+use chip8::{crc32, Chip8Emulator, Insn, Platform, StateError, TimingModel, STATE_SIZE};
+use common::{assemble, save_state_vec, ROM_ADDR};
+
+/// Offset of the platform, after the header and the emulator fields
+const PLATFORM_OFFSET: usize = 25;
+
+/// Draw random sprites while the timers run
+fn emulator() -> Chip8Emulator {
+    let mut emu = Chip8Emulator::new(700);
+    emu.set_platform(Platform::XoChip);
+    emu.set_timing(TimingModel::CosmacVip);
+    emu.set_cpu_rng_seed(42);
+    emu.load_rom(&assemble(&[
+        Insn::LoadI(0, 30),
+        Insn::SetDelayTimer(0),
+        Insn::SetSoundTimer(0),
+        Insn::RndAnd(1, 0x3F),
+        Insn::RndAnd(2, 0x1F),
+        Insn::RndAnd(3, 0x0F),
+        Insn::SpriteLoc(3),
+        Insn::DrawSprite(1, 2, 5),
+        Insn::Call(ROM_ADDR + 20),
+        Insn::Jump(ROM_ADDR + 6),
+        Insn::StoreRegs(3),
+        Insn::Ret,
+    ]));
+    emu.peripherals_mut().keypad.key_pressed(7);
+    emu
+}
+
+/// Replace a byte and fix the checksum
+fn patch(state: &mut [u8], offset: usize, value: u8) {
+    state[offset] = value;
+    let crc = crc32(&state[..STATE_SIZE - 4]);
+    state[STATE_SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[test]
+fn round_trip() {
+    let mut emu = emulator();
+    emu.advance_ms(250).unwrap();
+    let state = save_state_vec(&emu);
+    assert_eq!(&state[..4], b"C8ST");
+    assert_eq!(u16::from_le_bytes([state[4], state[5]]), STATE_VERSION);
+
+    let mut restored = Chip8Emulator::new(60);
+    restored.load_state(&state).unwrap();
+    assert_eq!(save_state_vec(&restored), state);
+    assert_eq!(restored.cpu().platform(), Platform::XoChip);
+    assert_eq!(restored.timing(), TimingModel::CosmacVip);
+    assert_eq!(restored.framebuffer(), emu.framebuffer());
+
+    // Both go on identically, the RNG included
+    emu.advance_ms(500).unwrap();
+    restored.advance_ms(500).unwrap();
+    assert_eq!(save_state_vec(&restored), save_state_vec(&emu));
+}
+
+#[test]
+fn orientation() {
+    // The rows are saved top-down, whatever the storage order
+    let mut emu = emulator();
+    emu.advance_ms(250).unwrap();
+    let state = save_state_vec(&emu);
+    let mut upright = Chip8Emulator::new(60);
+    upright.peripherals_mut().screen.set_inverted_y(false);
+    upright.load_state(&state).unwrap();
+    let (screen, restored) = (&emu.peripherals().screen, &upright.peripherals().screen);
+    assert!(screen.is_inverted_y());
+    assert_ne!(restored.planes(), screen.planes());
+    for y in 0..32 {
+        for x in 0..64 {
+            assert_eq!(restored.pixel(x, y), screen.pixel(x, y), "({}, {})", x, y);
+        }
+    }
+    assert_eq!(save_state_vec(&upright), state);
+}
+
+#[test]
+fn buffer_too_small() {
+    let mut state = vec![0u8; STATE_SIZE - 1];
+    assert_eq!(emulator().save_state(&mut state), Err(StateError::BufferTooSmall));
+}
+
+#[test]
+fn rejected() {
+    let good = save_state_vec(&emulator());
+    let mut bad_magic = good.clone();
+    bad_magic[0] = b'X';
+    let mut version = good.clone();
+    version[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    let mut bad_crc = good.clone();
+    bad_crc[STATE_SIZE / 2] ^= 1;
+    let mut platform = good.clone();
+    patch(&mut platform, PLATFORM_OFFSET, 3);
+    let mut longer = good.clone();
+    longer.push(0);
+
+    let cases: &[(&str, &[u8], StateError)] = &[
+        ("empty", &[], StateError::BadMagic),
+        ("magic", &bad_magic, StateError::BadMagic),
+        ("version", &version, StateError::UnsupportedVersion(STATE_VERSION + 1)),
+        ("truncated", &good[..STATE_SIZE - 1], StateError::InvalidLength),
+        ("longer", &longer, StateError::InvalidLength),
+        ("crc", &bad_crc, StateError::ChecksumMismatch),
+        ("platform", &platform, StateError::InvalidValue("platform")),
+    ];
+    for (name, state, error) in cases {
+        // The emulator is left unchanged
+        let mut emu = Chip8Emulator::new(500);
+        let before = save_state_vec(&emu);
+        assert_eq!(emu.load_state(state), Err(*error), "{}", name);
+        assert_eq!(save_state_vec(&emu), before, "{}", name);
+    }
+}
@@ -0,0 +1,302 @@
+//
+// Timing models: instructions scheduled against the 60Hz frames
+mod common;
+
+use chip8::{Chip8Emulator, FrameResult, Insn, Quirks, StopReason, TimingModel};
+use common::{assemble, save_state_vec, ROM_ADDR};
+
+const CPU_HZ: u32 = 500;
+
+fn emulator(timing: TimingModel, program: &[Insn]) -> Chip8Emulator {
+    let mut emu = Chip8Emulator::new(CPU_HZ);
+    emu.set_quirks(Quirks::cosmac_vip());
+    emu.set_timing(timing);
+    emu.load_rom(&assemble(program));
+    emu
+}
+
+/// Draw the 0 glyph forever
+fn draw_loop() -> Vec<Insn> {
+    vec![Insn::LoadA(0), Insn::DrawSprite(0, 0, 5), Insn::Jump(ROM_ADDR + 2)]
+}
+
+#[test]
+fn flat() {
+    let mut emu = emulator(TimingModel::Flat, &draw_loop());
+    emu.advance_ms(1000).unwrap();
+    assert_eq!(emu.cycles(), CPU_HZ as u64);
+}
+
+#[test]
+fn vip_display_wait() {
+    let mut emu = emulator(TimingModel::CosmacVip, &draw_loop());
+    emu.advance_ms(1000).unwrap();
+    // One sprite per frame: the first load, then a sprite and a jump in
+    // each of the 59 other frames
+    assert_eq!(emu.cycles(), 1 + 2 * 59);
+    // cpu_hz is ignored
+    let mut fast = emulator(TimingModel::CosmacVip, &draw_loop());
+    fast.set_cpu_hz(100_000);
+    fast.advance_ms(1000).unwrap();
+    assert_eq!(fast.cycles(), emu.cycles());
+}
+
+#[test]
+fn vip_timers() {
+    let program = [
+        Insn::LoadI(0, 60),
+        Insn::SetDelayTimer(0),
+        Insn::AddI(1, 1),
+        Insn::Jump(ROM_ADDR + 4),
+    ];
+    let mut emu = emulator(TimingModel::CosmacVip, &program);
+    emu.advance_ms(500).unwrap();
+    assert_eq!(emu.peripherals().delay_timer, 30);
+    // About 1836 / 52 + 1836 / 92 instructions per frame
+    let per_frame = emu.cycles() / 30;
+    assert!((25..=40).contains(&per_frame), "{} instructions per frame", per_frame);
+}
+
+#[test]
+fn vip_long_instructions() {
+    let program = [
+        Insn::LoadI(0, 255),
+        Insn::SetDelayTimer(0),
+        Insn::Cls,
+        Insn::Jump(ROM_ADDR + 4),
+    ];
+    let mut emu = emulator(TimingModel::CosmacVip, &program);
+    emu.tick(2 + 2 * 10).unwrap();
+    // A clear takes more than a frame
+    let frames = 255 - emu.peripherals().delay_timer as u64;
+    assert!(frames > 10 && frames < 20, "{} frames", frames);
+}
+
+#[test]
+fn vip_halted() {
+    let mut emu = emulator(TimingModel::CosmacVip, &[Insn::LoadI(0, 60), Insn::SetDelayTimer(0), Insn::Exit]);
+    emu.set_platform(chip8::Platform::SuperChip);
+    emu.set_timing(TimingModel::CosmacVip);
+    emu.advance_ms(500).unwrap();
+    // The frames go on once halted
+    assert_eq!(emu.cycles(), 3);
+    assert_eq!(emu.peripherals().delay_timer, 30);
+}
+
+#[test]
+fn state_keeps_timing() {
+    let mut emu = emulator(TimingModel::CosmacVip, &draw_loop());
+    emu.advance_ms(100).unwrap();
+    let state = save_state_vec(&emu);
+
+    let mut restored = Chip8Emulator::new(CPU_HZ);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.timing(), TimingModel::CosmacVip);
+    emu.advance_ms(100).unwrap();
+    restored.advance_ms(100).unwrap();
+    assert_eq!(restored.cycles(), emu.cycles());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn movie_replay() {
+    use chip8::movie::{Movie, MoviePlayer, MovieRecorder};
+
+    // Draw the glyph of each key pressed
+    let program = [
+        Insn::WaitForKey(0),
+        Insn::SpriteLoc(0),
+        Insn::DrawSprite(1, 2, 5),
+        Insn::AddI(1, 5),
+        Insn::Jump(ROM_ADDR),
+    ];
+    let rom = assemble(&program);
+    // Milliseconds to run, then key to toggle, with fractions of a step
+    // at 700Hz
+    let script = [(100, 3), (16, 3), (117, 7), (50, 7), (203, 0)];
+
+    for timing in [TimingModel::Flat, TimingModel::CosmacVip] {
+        let mut emu = emulator(timing, &program);
+        emu.set_cpu_hz(700);
+        let mut recorder = MovieRecorder::new(&mut emu, &rom, 1);
+        let mut pressed = [false; 16];
+        for (ms, key) in script.iter() {
+            emu.advance_ms(*ms).unwrap();
+            let k = *key as usize;
+            pressed[k] = !pressed[k];
+            if pressed[k] {
+                recorder.key_pressed(&mut emu, *key);
+            } else {
+                recorder.key_released(&mut emu, *key);
+            }
+        }
+        let movie = Movie::from_bytes(&recorder.finish(&emu).to_bytes()).unwrap();
+        assert_eq!(movie.timing, timing);
+
+        let mut replay = movie.make_emulator(&rom).unwrap();
+        assert_eq!(replay.timing(), timing);
+        let mut player = MoviePlayer::new(movie);
+        for (ms, _) in script.iter() {
+            player.advance_ms(&mut replay, *ms).unwrap();
+        }
+        assert_eq!(replay.cycles(), emu.cycles(), "{:?}", timing);
+        assert_eq!(replay.peripherals().screen.planes(), emu.peripherals().screen.planes(), "{:?}", timing);
+        assert_eq!(replay.cpu().reg_i(), emu.cpu().reg_i(), "{:?}", timing);
+    }
+}
+
+#[test]
+fn run_frame_flat() {
+    let mut emu = emulator(TimingModel::Flat, &draw_loop());
+    let mut steps = Vec::new();
+    for _ in 0..60 {
+        let before = emu.cycles();
+        assert_eq!(emu.run_frame().unwrap().stop, StopReason::Completed);
+        steps.push(emu.cycles() - before);
+    }
+    // 500 / 60 instructions per frame, without drift
+    assert_eq!(emu.cycles(), CPU_HZ as u64);
+    assert!(steps.iter().all(|n| *n == 8 || *n == 9), "{:?}", steps);
+}
+
+#[test]
+fn advance_ms_slices() {
+    // Timers running while counting, with a clear longer than a frame
+    // for the VIP timing
+    let program = [
+        Insn::LoadI(0, 120),
+        Insn::SetDelayTimer(0),
+        Insn::Cls,
+        Insn::AddI(1, 1),
+        Insn::Jump(ROM_ADDR + 4),
+    ];
+    for timing in [TimingModel::Flat, TimingModel::CosmacVip] {
+        // Up to the end of the 60th frame, or the end of the instruction
+        // running over it
+        let mut frames = emulator(timing, &program);
+        frames.set_cpu_hz(700);
+        frames.run_frame().unwrap();
+        while frames.peripherals().delay_timer > 60 {
+            frames.run_frame().unwrap();
+        }
+        if timing == TimingModel::Flat {
+            assert_eq!(frames.cycles(), 700);
+        }
+
+        // The same instructions and frames, whatever the slices
+        let mut whole = None;
+        for slices in [&[1000][..], &[1; 1000], &[16, 17, 17], &[3, 7, 990]] {
+            let mut emu = emulator(timing, &program);
+            emu.set_cpu_hz(700);
+            let mut ms = 0;
+            while ms < 1000 {
+                for slice in slices.iter() {
+                    emu.advance_ms(*slice).unwrap();
+                    ms += slice;
+                }
+            }
+            assert_eq!(emu.cycles(), frames.cycles(), "{:?}, slices of {:?} ms", timing, slices);
+            assert_eq!(emu.peripherals().delay_timer, frames.peripherals().delay_timer);
+            // The carried remainders included
+            let state = save_state_vec(&emu);
+            assert!(*whole.get_or_insert_with(|| state.clone()) == state, "{:?}, slices of {:?} ms", timing, slices);
+        }
+    }
+}
+
+#[test]
+fn advance_ms_fast() {
+    // More than 2^32 / 1000 instructions per second
+    let mut emu = emulator(TimingModel::Flat, &[Insn::Jump(ROM_ADDR)]);
+    emu.set_cpu_hz(5_000_000);
+    emu.advance_ms(1000).unwrap();
+    assert_eq!(emu.cycles(), 5_000_000);
+}
+
+#[test]
+fn run_frame_vip() {
+    let mut emu = emulator(TimingModel::CosmacVip, &draw_loop());
+    emu.run_frame().unwrap();
+    assert_eq!(emu.cycles(), 1);
+    for frame in 1..10 {
+        let result = emu.run_frame().unwrap();
+        // A sprite and a jump, the next sprite waiting for the next frame
+        assert_eq!(emu.cycles(), 1 + 2 * frame);
+        assert!(result.screen_changed);
+    }
+}
+
+#[test]
+fn run_frame_result() {
+    let program = [
+        Insn::LoadI(0, 30),
+        Insn::SetSoundTimer(0),
+        Insn::LoadA(0),
+        Insn::DrawSprite(0, 0, 5),
+        Insn::WaitForKey(1),
+        Insn::Cls,
+        Insn::Jump(ROM_ADDR + 8),
+    ];
+    let mut emu = emulator(TimingModel::Flat, &program);
+    let frame = emu.run_frame().unwrap();
+    assert_eq!(
+        frame,
+        FrameResult { stop: StopReason::Completed, screen_changed: true, sound_active: true, waiting_for_key: true }
+    );
+    let frame = emu.run_frame().unwrap();
+    assert!(!frame.screen_changed && frame.waiting_for_key);
+
+    emu.peripherals_mut().keypad.key_pressed(4);
+    for _ in 0..29 {
+        emu.run_frame().unwrap();
+    }
+    let frame = emu.run_frame().unwrap();
+    assert_eq!(
+        frame,
+        FrameResult { stop: StopReason::Completed, screen_changed: false, sound_active: false, waiting_for_key: false }
+    );
+    assert_eq!(emu.cpu().gpr(1), Some(4));
+}
+
+#[test]
+fn run_frame_breakpoint() {
+    let mut emu = emulator(TimingModel::Flat, &draw_loop());
+    emu.debugger_mut().add_breakpoint(ROM_ADDR + 4);
+    let frame = emu.run_frame().unwrap();
+    assert_eq!(frame.stop, StopReason::Breakpoint(ROM_ADDR + 4));
+    assert_eq!(emu.cycles(), 2);
+    // The rest of the frame
+    emu.resume();
+    assert_eq!(emu.run_frame().unwrap().stop, StopReason::Breakpoint(ROM_ADDR + 4));
+    assert_eq!(emu.cycles(), 4);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn movie_replay_frames() {
+    use chip8::movie::{MoviePlayer, MovieRecorder};
+
+    let program = [Insn::WaitForKey(0), Insn::SpriteLoc(0), Insn::DrawSprite(1, 2, 5), Insn::Jump(ROM_ADDR)];
+    let rom = assemble(&program);
+    for timing in [TimingModel::Flat, TimingModel::CosmacVip] {
+        let mut emu = emulator(timing, &program);
+        let mut recorder = MovieRecorder::new(&mut emu, &rom, 1);
+        let mut results = Vec::new();
+        for frame in 0..40 {
+            match frame {
+                5 => recorder.key_pressed(&mut emu, 3),
+                20 => recorder.key_released(&mut emu, 3),
+                _ => {}
+            }
+            results.push(emu.run_frame().unwrap());
+        }
+        let movie = recorder.finish(&emu);
+
+        let mut replay = movie.make_emulator(&rom).unwrap();
+        let mut player = MoviePlayer::new(movie);
+        for expected in results.iter() {
+            assert_eq!(player.run_frame(&mut replay).unwrap(), *expected, "{:?}", timing);
+        }
+        assert_eq!(replay.cycles(), emu.cycles(), "{:?}", timing);
+    }
+}
@@ -0,0 +1,122 @@
+//
+// Trace sinks: the text, compat and binary formats
+//
+// The text and compat outputs are compared against tests/golden/trace.txt
+// and tests/golden/trace-compat.txt.
+#![cfg(feature = "std")]
+mod common;
+
+use std::io::ErrorKind;
+
+use chip8::trace::{TraceFormat, TraceReader, TraceRecord, TraceWriter, TRACE_MAGIC, TRACE_RECORD_SIZE, TRACE_VERSION};
+use chip8::{Insn, Platform};
+use common::Machine;
+
+/// Trace of a short XO-CHIP program, with a long load and a call
+fn trace(format: TraceFormat) -> Vec<u8> {
+    let mut m = Machine::new(Platform::XoChip);
+    m.load(&[
+        Insn::LoadI(1, 0x05),
+        Insn::LoadLongA(0x1234),
+        Insn::Call(0x20A),
+        Insn::Jump(0x208),
+        Insn::Add(1, 1),
+        Insn::Ret,
+    ]);
+    let mut writer = TraceWriter::new(Vec::new(), format);
+    for _ in 0..6 {
+        m.cpu.tick_traced(&mut m.periph, &mut writer).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn record(cycle: u64, pc: u16, opcode: u32, v1: u8, i: u16, sp: u8) -> TraceRecord {
+    let mut v = [0; 16];
+    v[1] = v1;
+    TraceRecord { cycle, pc, opcode, v, i, sp }
+}
+
+fn read(trace: &[u8]) -> Vec<Result<TraceRecord, ErrorKind>> {
+    TraceReader::new(trace).unwrap().map(|r| r.map_err(|e| e.kind())).collect()
+}
+
+#[test]
+fn text() {
+    let text = String::from_utf8(trace(TraceFormat::Text)).unwrap();
+    assert_eq!(text, include_str!("golden/trace.txt"));
+}
+
+#[test]
+fn compat() {
+    // The state before each instruction
+    let text = String::from_utf8(trace(TraceFormat::Compat)).unwrap();
+    assert_eq!(text, include_str!("golden/trace-compat.txt"));
+}
+
+#[test]
+fn binary_round_trip() {
+    let trace = trace(TraceFormat::Binary);
+    assert_eq!(trace[..4], TRACE_MAGIC);
+    assert_eq!(trace[4..6], TRACE_VERSION.to_le_bytes());
+    assert_eq!(trace.len(), 6 + 6 * TRACE_RECORD_SIZE);
+    // The state after each instruction
+    let expected = [
+        record(1, 0x200, 0x6105, 0x05, 0x0000, 0),
+        record(2, 0x202, 0xF000_1234, 0x05, 0x1234, 0),
+        record(3, 0x206, 0x220A, 0x05, 0x1234, 1),
+        record(4, 0x20A, 0x8114, 0x0A, 0x1234, 1),
+        record(5, 0x20C, 0x00EE, 0x0A, 0x1234, 0),
+        record(6, 0x208, 0x1208, 0x0A, 0x1234, 0),
+    ];
+    assert_eq!(read(&trace), expected.iter().map(|r| Ok(*r)).collect::<Vec<_>>());
+
+    // Only the header
+    assert_eq!(read(&trace[..6]), []);
+}
+
+#[test]
+fn truncated_record() {
+    let trace = trace(TraceFormat::Binary);
+    let records = read(&trace[..trace.len() - 1]);
+    assert_eq!(records.len(), 6);
+    assert!(records[..5].iter().all(Result::is_ok));
+    assert_eq!(records[5], Err(ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn bad_header() {
+    let trace = trace(TraceFormat::Binary);
+    let error = |header: &[u8]| TraceReader::new(header).map(|_| ()).unwrap_err();
+
+    let mut magic = trace.clone();
+    magic[0] = b'X';
+    let e = error(&magic);
+    assert_eq!((e.kind(), e.to_string()), (ErrorKind::InvalidData, "not a binary trace".to_string()));
+    let mut version = trace.clone();
+    version[4..6].copy_from_slice(&(TRACE_VERSION + 1).to_le_bytes());
+    let e = error(&version);
+    assert_eq!((e.kind(), e.to_string()), (ErrorKind::InvalidData, "unsupported trace version".to_string()));
+    assert_eq!(error(&trace[..5]).kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(error(&[]).kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn write_error() {
+    // The first error is kept and reported at the end
+    let mut buf = [0u8; 40];
+    let mut m = Machine::new(Platform::Chip8);
+    m.load(&[Insn::Jump(0x200)]);
+    let mut writer = TraceWriter::new(&mut buf[..], TraceFormat::Binary);
+    for _ in 0..3 {
+        m.cpu.tick_traced(&mut m.periph, &mut writer).unwrap();
+    }
+    assert_eq!(writer.finish().map(|_| ()).unwrap_err().kind(), ErrorKind::WriteZero);
+}
+
+#[test]
+fn format_names() {
+    assert_eq!("text".parse(), Ok(TraceFormat::Text));
+    assert_eq!("compat".parse(), Ok(TraceFormat::Compat));
+    assert_eq!("binary".parse(), Ok(TraceFormat::Binary));
+    assert!("json".parse::<TraceFormat>().is_err());
+}